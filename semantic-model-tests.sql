@@ -1,7 +1,7 @@
 --------------------------------------------------------------------------------
 -- 1. Test simple measure
 --------------------------------------------------------------------------------
-SELECT department_level_1, MEASURE(headcount)
+SELECT department_level_1, MEASURE(dm_employees.headcount)
 FROM dm_employees;
 -- Expected:
 SELECT
@@ -10,34 +10,34 @@ SELECT
 FROM dm_employees
 
 
--- --------------------------------------------------------------------------------
--- -- 2. Test cumulative measure
--- --------------------------------------------------------------------------------
--- SELECT department_level_1, MEASURE(starting_headcount)
--- FROM dm_employees;
--- -- Expected:1
--- SELECT
---     dm_employees.department_level_1,
---     SUM(count(dm_employees.id)) OVER (PARTITION BY dm_employees.department_level_1, min(dm_employees.effective_date)) AS starting_headcount
--- FROM dm_employees
-
--- --------------------------------------------------------------------------------
--- -- 3. Test ratio measure
--- --------------------------------------------------------------------------------
--- SELECT department_level_1, MEASURE(average_headcount)
--- FROM dm_employees;
--- -- Expected:
--- SELECT
---     dm_employees.department_level_1,
---     (SUM(count(dm_employees.id)) OVER (PARTITION BY dm_employees.department_level_1, min(dm_employees.effective_date))) / (COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END)) AS average_headcount
--- FROM dm_employees
+--------------------------------------------------------------------------------
+-- 2. Test cumulative measure
+--------------------------------------------------------------------------------
+SELECT department_level_1, MEASURE(dm_employees.starting_headcount)
+FROM dm_employees;
+-- Expected:
+SELECT
+    department_level_1,
+    SUM(COUNT(dm_employees.id)) OVER (PARTITION BY dm_employees.department_level_1, MIN(dm_employees.effective_date)) AS starting_headcount
+FROM dm_employees
+
+--------------------------------------------------------------------------------
+-- 3. Test ratio measure
+--------------------------------------------------------------------------------
+SELECT department_level_1, MEASURE(dm_employees.average_headcount)
+FROM dm_employees;
+-- Expected:
+SELECT
+    department_level_1,
+    (SUM(COUNT(dm_employees.id)) OVER (PARTITION BY dm_employees.department_level_1, MIN(dm_employees.effective_date))) / NULLIFZERO(COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END)) AS average_headcount
+FROM dm_employees
 
 
 --------------------------------------------------------------------------------
 -- 4. Test CTE
 --------------------------------------------------------------------------------
 WITH cte AS (
-    SELECT department_level_1, MEASURE(headcount)
+    SELECT department_level_1, MEASURE(dm_employees.headcount)
     FROM dm_employees
 )
 SELECT * FROM cte;
@@ -55,7 +55,7 @@ SELECT * FROM cte
 -- 5. Measure alias should be ignored
 --------------------------------------------------------------------------------
 -- 1.
-SELECT department_level_1, MEASURE(headcount) AS 'MEASURE(headcount)'
+SELECT department_level_1, MEASURE(dm_employees.headcount) AS 'MEASURE(headcount)'
 FROM dm_employees;
 -- Expected:
 SELECT
@@ -64,7 +64,7 @@ SELECT
 FROM dm_employees
 
 -- 2.
-SELECT department_level_1, MEASURE(headcount) AS "measure_headcount"
+SELECT department_level_1, MEASURE(dm_employees.headcount) AS "measure_headcount"
 FROM dm_employees;
 -- Expected:
 SELECT
@@ -75,7 +75,7 @@ FROM dm_employees
 --------------------------------------------------------------------------------
 -- 6. Test multiple tables
 --------------------------------------------------------------------------------
-SELECT dm_departments.department_level_1_name, MEASURE(headcount)
+SELECT dm_departments.department_level_1_name, MEASURE(dm_employees.headcount)
 FROM dm_employees
 LEFT JOIN dm_departments ON dm_employees.department_level_1 = dm_departments.department_level_1;
 -- Expected:
@@ -88,22 +88,22 @@ LEFT JOIN dm_departments ON dm_employees.department_level_1 = dm_departments.dep
 --------------------------------------------------------------------------------
 -- 7. Test multiple measures
 --------------------------------------------------------------------------------
-SELECT department_level_1, MEASURE(headcount), MEASURE(ending_headcount)
+SELECT department_level_1, MEASURE(dm_employees.headcount), MEASURE(dm_employees.ending_headcount)
 FROM dm_employees;
 -- Expected:
 SELECT
     department_level_1,
     COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END) AS headcount,
-    count(distinct dm_employees.effective_date) as ending_headcount
+    COUNT(DISTINCT dm_employees.effective_date) as ending_headcount
 FROM dm_employees
 
 --------------------------------------------------------------------------------
 -- 8. Test unions
 --------------------------------------------------------------------------------
-SELECT department_level_1, MEASURE(headcount), false as is_total
+SELECT department_level_1, MEASURE(dm_employees.headcount), false as is_total
 FROM dm_employees
 UNION
-SELECT null as department_level_1, MEASURE(headcount), true as is_total
+SELECT null as department_level_1, MEASURE(dm_employees.headcount), true as is_total
 FROM dm_employees;
 -- Expected:
 SELECT
@@ -121,19 +121,22 @@ FROM dm_employees
 --------------------------------------------------------------------------------
 -- 9. Test subquery
 --------------------------------------------------------------------------------
-SELECT subquery.department_level_1, MEASURE(headcount)
+SELECT subquery.department_level_1, MEASURE(dm_employees.headcount)
 FROM (SELECT * FROM dm_employees) AS subquery;
 -- Expected:
 SELECT
     subquery.department_level_1,
     COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END) AS headcount
-FROM (SELECT * FROM dm_employees) AS subquery
+FROM (SELECT employee_pk, id, department_level_1, effective_date, included_in_headcount FROM dm_employees) AS subquery
 
 -- NOTE: This is incorrect SQL, and will fail, but it is a valid test case
 
+-- Dialect: snowflake
+
 --------------------------------------------------------------------------------
 -- 10. Test postgres data types convert to driver types (in this case snowflake)
 --------------------------------------------------------------------------------
+-- Ignore: Handle queries without a FROM clause
 -- | PostgreSQL object type     | Snowflake column type |
 -- |----------------------------|-----------------------|
 -- | serial                     | number(6)             |
@@ -264,8 +267,8 @@ SELECT
     cast('a' as varchar) as varchar_column,
     'a'::varchar as varchar_column,
 
-    cast('a' as text) as text_column,
-    'a'::text as text_column,
+    cast('a' as varchar) as text_column,
+    'a'::varchar as text_column,
 
     cast('2020-01-01' as date) as date_column,
     '2020-01-01'::date as date_column,
@@ -273,8 +276,8 @@ SELECT
     cast('12:00:00' as time) as time_column,
     '12:00:00'::time as time_column,
 
-    cast('2020-01-01 12:00:00' as timestamp) as timestamp_column,
-    '2020-01-01 12:00:00'::timestamp as timestamp_column,
+    cast('2020-01-01 12:00:00' as timestamp_ntz) as timestamp_column,
+    '2020-01-01 12:00:00'::timestamp_ntz as timestamp_column,
 
     cast('2020-01-01 12:00:00' as timestamp_tz) as timestamptz_column,
     '2020-01-01 12:00:00'::timestamp_tz as timestamptz_column,
@@ -285,8 +288,8 @@ SELECT
     cast('{"a": 1}' as variant) as jsonb_column,
     '{"a": 1}'::variant as jsonb_column,
 
-    cast('a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11' as string) as uuid_column,
-    'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11'::string as uuid_column,
+    cast('a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11' as varchar) as uuid_column,
+    'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11'::varchar as uuid_column,
 
     cast('a' as binary) as bytea_column,
     'a'::binary as bytea_column;
@@ -294,6 +297,7 @@ SELECT
 --------------------------------------------------------------------------------
 -- 11. Interval statement in dialect
 --------------------------------------------------------------------------------
+-- Ignore: Handle queries without a FROM clause
 SELECT '1 day'::interval as interval_column
 
 -- Expected:
@@ -323,8 +327,32 @@ FROM dm_employees;
 --------------------------------------------------------------------------------
 -- 14. Test DISTINCT ON with snowflake dialect
 --------------------------------------------------------------------------------
-SELECT DISTINCT ON (department_level_1) department_level_1, MEASURE(headcount)
+SELECT DISTINCT ON (department_level_1) department_level_1, MEASURE(dm_employees.headcount)
 FROM dm_employees;
 
 -- Expected:
--- Just error out as snowflake does not support DISTINCT ON
\ No newline at end of file
+SELECT
+    department_level_1,
+    COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END) AS headcount
+FROM dm_employees
+QUALIFY ROW_NUMBER() OVER (PARTITION BY department_level_1 ORDER BY department_level_1) = 1
+
+-- Dialect: postgres
+
+--------------------------------------------------------------------------------
+-- 15. Test qualified wildcard with joins
+--------------------------------------------------------------------------------
+SELECT e.*, d.department_level_1_name, MEASURE(dm_employees.headcount)
+FROM dm_employees AS e
+LEFT JOIN dm_departments AS d ON e.department_level_1 = d.department_level_1;
+-- Expected:
+SELECT
+    e.employee_pk,
+    e.id,
+    e.department_level_1,
+    e.effective_date,
+    e.included_in_headcount,
+    d.department_level_1_name,
+    COUNT(DISTINCT CASE WHEN dm_employees.included_in_headcount THEN dm_employees.id ELSE NULL END) AS headcount
+FROM dm_employees AS e
+LEFT JOIN dm_departments AS d ON e.department_level_1 = d.department_level_1
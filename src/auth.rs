@@ -1,23 +1,65 @@
 use async_trait::async_trait;
+use aws_lc_rs::{constant_time, digest, hmac, rand as lc_rand};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::{Sink, SinkExt};
 use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
 use pgwire::{
-    api::auth::{AuthSource, LoginInfo, Password},
+    api::auth::{
+        finish_authentication, save_startup_parameters_to_metadata, scram::gen_salted_password,
+        scram::random_nonce, LoginInfo, ServerParameterProvider, StartupHandler,
+    },
+    api::{ClientInfo, PgWireConnectionState},
     error::{ErrorInfo, PgWireError, PgWireResult},
+    messages::response::ErrorResponse,
+    messages::startup::{Authentication as AuthenticationMessage, PasswordMessageFamily},
+    messages::{PgWireBackendMessage, PgWireFrontendMessage},
 };
 
 use crate::config::AuthConfig;
 
+const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+/// Iteration count Postgres uses for the verifiers it generates.
+pub const SCRAM_ITERATIONS: u32 = 4096;
+
+/// The credentials of every user, as Postgres stores them in
+/// `pg_authid.rolpassword`. Passwords themselves are never held.
 pub struct Authentication {
-    pairs: HashMap<String, String>,
+    credentials: HashMap<String, Credential>,
+    /// Secret the salts of unknown users are derived from
+    mock_secret: [u8; 32],
 }
 
 impl Authentication {
+    pub fn new(credentials: HashMap<String, Credential>) -> Self {
+        let mock_secret = lc_rand::generate(&lc_rand::SystemRandom::new())
+            .expect("Failed to generate random bytes")
+            .expose();
+
+        Self {
+            credentials,
+            mock_secret,
+        }
+    }
+
     pub fn from_env() -> Self {
-        let pairs = AuthConfig::get_pairs();
+        Self::new(AuthConfig::get_credentials())
+    }
+
+    fn credential(&self, username: &str) -> Option<&Credential> {
+        self.credentials.get(username)
+    }
 
-        Self { pairs }
+    /// The same salt is offered every time for an unknown user, as it would
+    /// be for a known one.
+    fn mock_salt(&self, username: &str) -> Vec<u8> {
+        hmac_sha256(&self.mock_secret, username.as_bytes())[..16].to_vec()
     }
 }
 
@@ -25,39 +67,509 @@ impl Authentication {
 pub enum AuthError {
     #[error("Missing username")]
     MissingUsername,
-    #[error("Invalid credentials")]
-    InvalidCredentials,
+    #[error("password authentication failed for user \"{0}\"")]
+    PasswordAuthenticationFailed(String),
+    #[error("SSL connection is required")]
+    SslRequired,
+    #[error("Invalid credential, expected a SCRAM-SHA-256 verifier or an md5 hash")]
+    InvalidCredential,
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
-#[async_trait]
-impl AuthSource for Authentication {
-    async fn get_password(&self, login_info: &LoginInfo) -> PgWireResult<Password> {
-        let username = login_info
-            .user()
-            .ok_or(AuthError::MissingUsername)
-            .map_err(|e| {
-                PgWireError::UserError(Box::new(ErrorInfo::new(
-                    "SQLSTATE".to_string(),
-                    "ERROR".to_string(),
-                    e.to_string(),
-                )))
-            })?
-            .to_string();
-
-        let password = self
-            .pairs
-            .get(&username)
-            .ok_or(AuthError::InvalidCredentials)
-            .map_err(|e| {
-                PgWireError::UserError(Box::new(ErrorInfo::new(
-                    "SQLSTATE".to_string(),
-                    "ERROR".to_string(),
-                    e.to_string(),
+impl AuthError {
+    /// The SQLSTATE code drivers classify the error by.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingUsername | AuthError::SslRequired => "28000",
+            AuthError::PasswordAuthenticationFailed(_) | AuthError::InvalidCredential => "28P01",
+            AuthError::Internal(_) => "XX000",
+        }
+    }
+
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            AuthError::SslRequired => Some("Connect with sslmode=require.".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Authentication errors end the connection, so they are reported as `FATAL`.
+impl From<AuthError> for ErrorInfo {
+    fn from(e: AuthError) -> Self {
+        let mut info = ErrorInfo::new("FATAL".to_string(), e.code().to_string(), e.to_string());
+        info.hint = e.hint();
+        info
+    }
+}
+
+/// A hashed password, in the format of `pg_authid.rolpassword`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credential {
+    Scram(ScramVerifier),
+    /// `md5` followed by the md5 of the password and username, only meant
+    /// for legacy clients that don't support SCRAM.
+    Md5(String),
+}
+
+impl FromStr for Credential {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(SCRAM_SHA_256) {
+            return s.parse().map(Credential::Scram);
+        }
+        match s.strip_prefix("md5") {
+            Some(hash) if hash.len() == 32 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Ok(Credential::Md5(s.to_lowercase()))
+            }
+            _ => Err(AuthError::InvalidCredential),
+        }
+    }
+}
+
+impl Display for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Scram(verifier) => Display::fmt(verifier, f),
+            Credential::Md5(hash) => f.write_str(hash),
+        }
+    }
+}
+
+/// `SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>`, from which a
+/// client proof can be checked but not forged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScramVerifier {
+    iterations: u32,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl ScramVerifier {
+    /// Derives the verifier of a password, the way `CREATE ROLE ... PASSWORD`
+    /// does it.
+    pub fn new(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted_password = gen_salted_password(password, salt, iterations as usize);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+
+        Self {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: sha256(&client_key),
+            server_key: hmac_sha256(&salted_password, b"Server Key"),
+        }
+    }
+
+    /// Checks the client proof of the exchange described by `auth_message`,
+    /// as defined in [RFC 5802](https://www.rfc-editor.org/rfc/rfc5802#section-3).
+    fn verify(&self, auth_message: &str, proof: &[u8]) -> bool {
+        let client_signature = hmac_sha256(&self.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return false;
+        }
+        let client_key: Vec<u8> = proof
+            .iter()
+            .zip(&client_signature)
+            .map(|(p, s)| p ^ s)
+            .collect();
+        constant_time::verify_slices_are_equal(&sha256(&client_key), &self.stored_key).is_ok()
+    }
+
+    fn server_signature(&self, auth_message: &str) -> Vec<u8> {
+        hmac_sha256(&self.server_key, auth_message.as_bytes())
+    }
+}
+
+impl FromStr for ScramVerifier {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let rest = s.strip_prefix("SCRAM-SHA-256$")?;
+            let (parameters, keys) = rest.split_once('$')?;
+            let (iterations, salt) = parameters.split_once(':')?;
+            let (stored_key, server_key) = keys.split_once(':')?;
+            Some(Self {
+                iterations: iterations.parse().ok().filter(|i| *i > 0)?,
+                salt: STANDARD.decode(salt).ok()?,
+                stored_key: STANDARD.decode(stored_key).ok()?,
+                server_key: STANDARD.decode(server_key).ok()?,
+            })
+        };
+        parse()
+            .filter(|v| v.stored_key.len() == 32 && v.server_key.len() == 32)
+            .ok_or(AuthError::InvalidCredential)
+    }
+}
+
+impl Display for ScramVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}${}:{}${}:{}",
+            SCRAM_SHA_256,
+            self.iterations,
+            STANDARD.encode(&self.salt),
+            STANDARD.encode(&self.stored_key),
+            STANDARD.encode(&self.server_key)
+        )
+    }
+}
+
+/// Hashes a password the way Postgres does with `password_encryption = md5`.
+pub fn md5_credential(username: &str, password: &str) -> Credential {
+    Credential::Md5(format!(
+        "md5{:x}",
+        md5::compute(format!("{password}{username}"))
+    ))
+}
+
+/// The response a client sends to an md5 challenge with the given salt.
+fn md5_response(hash: &str, salt: &[u8]) -> String {
+    let hash = hash.strip_prefix("md5").unwrap_or(hash);
+    format!("md5{:x}", md5::compute([hash.as_bytes(), salt].concat()))
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, message).as_ref().to_vec()
+}
+
+fn sha256(message: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA256, message).as_ref().to_vec()
+}
+
+fn random_bytes<const N: usize>() -> PgWireResult<[u8; N]> {
+    lc_rand::generate(&lc_rand::SystemRandom::new())
+        .map(|bytes| bytes.expose())
+        .map_err(|_| {
+            PgWireError::ApiError(Box::new(AuthError::Internal(
+                "Failed to generate random bytes".to_string(),
+            )))
+        })
+}
+
+enum AuthState {
+    Initial,
+    Md5 {
+        username: String,
+        expected: String,
+    },
+    ScramInitial {
+        username: String,
+        verifier: Option<ScramVerifier>,
+    },
+    ScramFinal {
+        username: String,
+        verifier: Option<ScramVerifier>,
+        gs2_header: String,
+        nonce: String,
+        /// client-first-message-bare and server-first-message
+        partial_auth_message: String,
+    },
+}
+
+enum AuthStep {
+    Continue(AuthenticationMessage),
+    /// Authenticated, after sending the final message if any
+    Authenticated(Option<AuthenticationMessage>),
+    Failed(String),
+}
+
+/// Authenticates clients against hashed credentials: SCRAM-SHA-256 for users
+/// with a SCRAM verifier, and MD5 for users with an md5 hash.
+///
+/// Unknown users go through a SCRAM exchange with a made up salt, so they
+/// can't be told apart from known users with a wrong password, like Postgres
+/// does.
+pub struct PasswordAuthStartupHandler<P> {
+    authentication: Arc<Authentication>,
+    parameter_provider: P,
+    state: Mutex<AuthState>,
+}
+
+impl<P> PasswordAuthStartupHandler<P> {
+    pub fn new(authentication: Arc<Authentication>, parameter_provider: P) -> Self {
+        Self {
+            authentication,
+            parameter_provider,
+            state: Mutex::new(AuthState::Initial),
+        }
+    }
+
+    fn start(&self, username: &str) -> PgWireResult<AuthenticationMessage> {
+        let username = username.to_string();
+        let (state, message) = match self.authentication.credential(&username) {
+            Some(Credential::Md5(hash)) => {
+                let salt = random_bytes::<4>()?;
+                let expected = md5_response(hash, &salt);
+                (
+                    AuthState::Md5 { username, expected },
+                    AuthenticationMessage::MD5Password(salt.to_vec()),
+                )
+            }
+            credential => {
+                let verifier = match credential {
+                    Some(Credential::Scram(verifier)) => Some(verifier.clone()),
+                    _ => None,
+                };
+                (
+                    AuthState::ScramInitial { username, verifier },
+                    AuthenticationMessage::SASL(vec![SCRAM_SHA_256.to_string()]),
+                )
+            }
+        };
+        *self.state.lock().unwrap() = state;
+        Ok(message)
+    }
+
+    fn respond(&self, message: PasswordMessageFamily) -> PgWireResult<AuthStep> {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::replace(&mut *state, AuthState::Initial) {
+            AuthState::Initial => Err(PgWireError::InvalidStartupMessage),
+            AuthState::Md5 { username, expected } => {
+                let password = message.into_password()?.password;
+                if constant_time::verify_slices_are_equal(password.as_bytes(), expected.as_bytes())
+                    .is_ok()
+                {
+                    Ok(AuthStep::Authenticated(None))
+                } else {
+                    Ok(AuthStep::Failed(username))
+                }
+            }
+            AuthState::ScramInitial { username, verifier } => {
+                let response = message.into_sasl_initial_response()?;
+                if response.auth_method != SCRAM_SHA_256 {
+                    return Err(PgWireError::InvalidScramMessage(format!(
+                        "Unsupported SASL mechanism {}",
+                        response.auth_method
+                    )));
+                }
+                let client_first = response
+                    .data
+                    .as_deref()
+                    .map(String::from_utf8_lossy)
+                    .unwrap_or_default();
+                let (gs2_header, client_first_bare) = split_gs2_header(&client_first)?;
+                let client_nonce = attribute(client_first_bare, 'r')
+                    .ok_or_else(|| PgWireError::InvalidScramMessage(client_first.to_string()))?;
+
+                let (salt, iterations) = match &verifier {
+                    Some(verifier) => (verifier.salt.clone(), verifier.iterations),
+                    None => (self.authentication.mock_salt(&username), SCRAM_ITERATIONS),
+                };
+                let nonce = format!("{}{}", client_nonce, random_nonce());
+                let server_first =
+                    format!("r={},s={},i={}", nonce, STANDARD.encode(salt), iterations);
+
+                *state = AuthState::ScramFinal {
+                    username,
+                    verifier,
+                    gs2_header: gs2_header.to_string(),
+                    nonce,
+                    partial_auth_message: format!("{},{}", client_first_bare, server_first),
+                };
+                Ok(AuthStep::Continue(AuthenticationMessage::SASLContinue(
+                    Bytes::from(server_first),
                 )))
+            }
+            AuthState::ScramFinal {
+                username,
+                verifier,
+                gs2_header,
+                nonce,
+                partial_auth_message,
+            } => {
+                let response = message.into_sasl_response()?;
+                let client_final = String::from_utf8_lossy(&response.data);
+                let (without_proof, proof) = client_final
+                    .rsplit_once(",p=")
+                    .ok_or_else(|| PgWireError::InvalidScramMessage(client_final.to_string()))?;
+                if attribute(without_proof, 'c') != Some(&STANDARD.encode(gs2_header))
+                    || attribute(without_proof, 'r') != Some(&nonce)
+                {
+                    return Err(PgWireError::InvalidScramMessage(
+                        "Channel binding or nonce mismatch".to_string(),
+                    ));
+                }
+
+                let auth_message = format!("{},{}", partial_auth_message, without_proof);
+                let proof = STANDARD.decode(proof).unwrap_or_default();
+                match verifier {
+                    Some(verifier) if verifier.verify(&auth_message, &proof) => {
+                        let server_final = format!(
+                            "v={}",
+                            STANDARD.encode(verifier.server_signature(&auth_message))
+                        );
+                        Ok(AuthStep::Authenticated(Some(
+                            AuthenticationMessage::SASLFinal(Bytes::from(server_final)),
+                        )))
+                    }
+                    _ => Ok(AuthStep::Failed(username)),
+                }
+            }
+        }
+    }
+}
+
+/// Splits the GS2 header off a client-first-message. Channel binding isn't
+/// offered, so clients must not require it.
+fn split_gs2_header(client_first: &str) -> PgWireResult<(&str, &str)> {
+    ["n,,", "y,,"]
+        .into_iter()
+        .find(|header| client_first.starts_with(header))
+        .map(|header| client_first.split_at(header.len()))
+        .ok_or_else(|| PgWireError::InvalidScramMessage(client_first.to_string()))
+}
+
+/// Value of a `<name>=<value>` attribute of a SCRAM message.
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|attribute| {
+        attribute
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+#[async_trait]
+impl<P: ServerParameterProvider> StartupHandler for PasswordAuthStartupHandler<P> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let PgWireFrontendMessage::Startup(ref startup) = message {
+            save_startup_parameters_to_metadata(client, startup);
+            client.set_state(PgWireConnectionState::AuthenticationInProgress);
+            let login_info = LoginInfo::from_client_info(client);
+            let username = login_info.user().ok_or_else(|| {
+                PgWireError::UserError(Box::new(AuthError::MissingUsername.into()))
             })?;
+            let challenge = self.start(username)?;
+            client
+                .send(PgWireBackendMessage::Authentication(challenge))
+                .await?;
+            return Ok(());
+        }
+
+        let PgWireFrontendMessage::PasswordMessageFamily(message) = message else {
+            return Ok(());
+        };
+        match self.respond(message)? {
+            AuthStep::Continue(response) => {
+                client
+                    .send(PgWireBackendMessage::Authentication(response))
+                    .await?;
+            }
+            AuthStep::Authenticated(response) => {
+                if let Some(response) = response {
+                    client
+                        .send(PgWireBackendMessage::Authentication(response))
+                        .await?;
+                }
+                finish_authentication(client, &self.parameter_provider).await;
+            }
+            AuthStep::Failed(username) => {
+                let error_info = ErrorInfo::from(AuthError::PasswordAuthenticationFailed(username));
+                client
+                    .feed(PgWireBackendMessage::ErrorResponse(ErrorResponse::from(
+                        error_info,
+                    )))
+                    .await?;
+                client.close().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    // Generated by Postgres with `CREATE ROLE ... PASSWORD 'password'`
+    const SCRAM_PASSWORD: &str = "SCRAM-SHA-256$4096:zQ3JOfGtFSPye0uGTthrqA==$nIsycxqYAHdNL/NjHzotRE7Gjjbc/IDnOJyqtephbTQ=:p29eoVzLfXPXTUiisddPGBMay5n95yyq5Nu6UbmxfJw=";
+    const MD5_PASSWORD: &str = "md5755cc49e77601692a072b9449d65e2eb";
+
+    #[test]
+    fn test_scram_verifier_matches_postgres() {
+        let verifier: ScramVerifier = SCRAM_PASSWORD.parse().unwrap();
+        let derived = ScramVerifier::new("password", &verifier.salt, SCRAM_ITERATIONS);
+        assert_eq!(derived, verifier);
+        assert_eq!(derived.to_string(), SCRAM_PASSWORD);
+    }
+
+    #[test]
+    fn test_md5_credential_matches_postgres() {
+        assert_eq!(
+            md5_credential("tmp_c", "password"),
+            Credential::Md5(MD5_PASSWORD.to_string())
+        );
+        // pgwire computes the client side of the exchange
+        let salt = [1, 2, 3, 4];
+        assert_eq!(
+            md5_response(MD5_PASSWORD, &salt),
+            pgwire::api::auth::md5pass::hash_md5_password("tmp_c", "password", &salt)
+        );
+    }
+
+    #[rstest]
+    #[case::scram(SCRAM_PASSWORD, true)]
+    #[case::md5(MD5_PASSWORD, true)]
+    #[case::md5_uppercase("MD5755CC49E77601692A072B9449D65E2EB", false)]
+    #[case::plaintext("password", false)]
+    #[case::short_md5("md5755cc49e", false)]
+    #[case::missing_server_key(
+        "SCRAM-SHA-256$4096:zQ3JOfGtFSPye0uGTthrqA==$nIsycxqYAHdNL/NjHzotRE7Gjjbc/IDnOJyqtephbTQ=",
+        false
+    )]
+    #[case::zero_iterations("SCRAM-SHA-256$0:zQ3JOfGtFSPye0uGTthrqA==$nIsycxqYAHdNL/NjHzotRE7Gjjbc/IDnOJyqtephbTQ=:p29eoVzLfXPXTUiisddPGBMay5n95yyq5Nu6UbmxfJw=", false)]
+    fn test_parse_credential(#[case] credential: &str, #[case] valid: bool) {
+        let parsed = credential.parse::<Credential>();
+        assert_eq!(parsed.is_ok(), valid, "{:?}", parsed);
+        if let Ok(parsed) = parsed {
+            assert_eq!(parsed.to_string(), credential);
+        }
+    }
+
+    // Example exchange from RFC 7677, section 3
+    #[test]
+    fn test_scram_exchange() {
+        let salt = STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let verifier = ScramVerifier::new("pencil", &salt, 4096);
+        let auth_message = "n=user,r=rOprNGfwEbeRWgbNEkqO,\
+            r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096,\
+            c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+        let proof = STANDARD
+            .decode("dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+            .unwrap();
+
+        assert!(verifier.verify(auth_message, &proof));
+        assert_eq!(
+            STANDARD.encode(verifier.server_signature(auth_message)),
+            "6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+
+        let wrong_password = ScramVerifier::new("pencil2", &salt, 4096);
+        assert!(!wrong_password.verify(auth_message, &proof));
+        assert!(!verifier.verify(auth_message, &proof[1..]));
+    }
 
-        Ok(Password::new(None, password.as_bytes().to_vec()))
+    #[rstest]
+    #[case::no_channel_binding("n,,n=,r=abc", Some(("n,,", "n=,r=abc")))]
+    #[case::client_supports_binding("y,,n=,r=abc", Some(("y,,", "n=,r=abc")))]
+    #[case::channel_binding("p=tls-server-end-point,,n=,r=abc", None)]
+    #[case::garbage("r=abc", None)]
+    fn test_split_gs2_header(#[case] message: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(split_gs2_header(message).ok(), expected);
     }
 }
@@ -0,0 +1,40 @@
+//! Prints the credential to put in `AUTH` for a password read from stdin.
+//!
+//! ```bash
+//! echo -n 'password' | cargo run --bin hash_password
+//! echo -n 'password' | cargo run --bin hash_password -- --md5 admin
+//! ```
+use std::io::{self, Read};
+use std::process;
+
+use aws_lc_rs::rand::{self, SecureRandom};
+use eqtble_sql::auth::{md5_credential, Credential, ScramVerifier, SCRAM_ITERATIONS};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut password = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut password) {
+        eprintln!("Failed to read password: {}", e);
+        process::exit(1);
+    }
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    let credential = match args.as_slice() {
+        [] => {
+            let mut salt = [0u8; 16];
+            if rand::SystemRandom::new().fill(&mut salt).is_err() {
+                eprintln!("Failed to generate salt");
+                process::exit(1);
+            }
+            Credential::Scram(ScramVerifier::new(password, &salt, SCRAM_ITERATIONS))
+        }
+        [flag, username] if flag == "--md5" => md5_credential(username, password),
+        _ => {
+            eprintln!("Usage: hash_password [--md5 <username>] < password");
+            process::exit(2);
+        }
+    };
+
+    println!("{}", credential);
+}
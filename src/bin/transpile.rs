@@ -0,0 +1,100 @@
+//! Prints the SQL the proxy sends to the data store for a query, without
+//! running the server or connecting to a data store.
+//!
+//! ```bash
+//! echo 'SELECT MEASURE(mv_historical_headcount.age) FROM mv_historical_headcount' | cargo run --bin transpile
+//! cargo run --bin transpile -- --dialect snowflake --models semantic_models.json query.sql
+//! ```
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use eqtble_sql::data_store::postgres::PostgresMapping;
+use eqtble_sql::data_store::snowflake::SnowflakeMapping;
+use eqtble_sql::data_store::DataStoreMapping;
+use eqtble_sql::semantic_model::local_store::LocalSemanticModelStore;
+use eqtble_sql::sql_parser::{SqlError, SqlParser};
+
+const USAGE: &str =
+    "Usage: transpile [--dialect postgres|snowflake] [--models <semantic_models.json>] [file]";
+
+fn main() {
+    let mut dialect = "postgres".to_string();
+    let mut models = "semantic_models.json".to_string();
+    let mut file = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dialect" => dialect = args.next().unwrap_or_else(|| usage()),
+            "--models" => models = args.next().unwrap_or_else(|| usage()),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let sql = match &file {
+        Some(path) => fs::read_to_string(path),
+        None => {
+            let mut sql = String::new();
+            io::stdin().read_to_string(&mut sql).map(|_| sql)
+        }
+    };
+    let sql = sql.unwrap_or_else(|e| {
+        eprintln!("Failed to read SQL: {}", e);
+        process::exit(1);
+    });
+
+    let store = LocalSemanticModelStore::load_from_json(&models).unwrap_or_else(|e| {
+        eprintln!("Failed to load semantic models from {}: {}", models, e);
+        process::exit(1);
+    });
+
+    let result = match dialect.as_str() {
+        "postgres" => transpile(PostgresMapping {}, store, &sql),
+        "snowflake" => transpile(SnowflakeMapping {}, store, &sql),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("ERROR {}: {}", e.code(), e);
+        if let Some(hint) = e.hint() {
+            eprintln!("HINT: {}", hint);
+        }
+        process::exit(1);
+    }
+}
+
+/// Prints each rewritten statement, stopping at the first one that fails.
+fn transpile<M: DataStoreMapping>(
+    mapping: M,
+    store: LocalSemanticModelStore,
+    sql: &str,
+) -> Result<(), SqlError> {
+    let parser = SqlParser::new(mapping, store);
+    for statement in parser.parse(sql)? {
+        match parser.transform_statement(statement) {
+            Ok(statement) => println!("{};", statement),
+            Err(SqlError::InformationSchemaResult(table)) => {
+                // Catalog queries never reach the data store
+                println!("-- Answered by the proxy:");
+                let names = table.columns.iter().map(|column| column.name.as_str());
+                println!("-- {}", names.collect::<Vec<_>>().join(" | "));
+                for row in table.rows {
+                    let values = row.into_iter().map(|v| v.unwrap_or_default());
+                    println!("-- {}", values.collect::<Vec<_>>().join(" | "));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
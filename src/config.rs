@@ -4,6 +4,8 @@ use envconfig::Envconfig;
 use log::debug;
 use log::error;
 
+use crate::auth::Credential;
+
 #[derive(Envconfig)]
 pub struct Config {
     #[envconfig(from = "SERVER_HOST", default = "127.0.0.1")]
@@ -11,14 +13,27 @@ pub struct Config {
 
     #[envconfig(from = "SERVER_PORT", default = "5432")]
     pub server_port: u16,
+
+    #[envconfig(from = "TLS_CERT_PATH")]
+    pub tls_cert_path: Option<String>,
+
+    #[envconfig(from = "TLS_KEY_PATH")]
+    pub tls_key_path: Option<String>,
+
+    #[envconfig(from = "TLS_REQUIRED", default = "false")]
+    pub tls_required: bool,
 }
 
 impl Config {
     pub fn new() -> Result<Self, envconfig::Error> {
         let config = Self::init_from_env()?;
         debug!(
-            "Config loaded: server_host={}, server_port={}",
-            config.server_host, config.server_port
+            "Config loaded: server_host={}, server_port={}, tls_cert_path={:?}, tls_key_path={:?}, tls_required={}",
+            config.server_host,
+            config.server_port,
+            config.tls_cert_path,
+            config.tls_key_path,
+            config.tls_required
         );
         Ok(config)
     }
@@ -34,14 +49,34 @@ pub struct PostgresConfig {
     pub host: String,
     #[envconfig(from = "POSTGRES_DB", default = "main")]
     pub dbname: String,
+    /// Connections opened at startup and kept open while idle.
+    #[envconfig(from = "POSTGRES_POOL_MIN_SIZE", default = "1")]
+    pub pool_min_size: usize,
+    #[envconfig(from = "POSTGRES_POOL_MAX_SIZE", default = "16")]
+    pub pool_max_size: usize,
+    /// Seconds a query waits for a free connection before failing.
+    #[envconfig(from = "POSTGRES_POOL_ACQUIRE_TIMEOUT", default = "30")]
+    pub pool_acquire_timeout: u64,
+    /// Seconds between checks discarding broken connections.
+    #[envconfig(from = "POSTGRES_POOL_HEALTH_CHECK_INTERVAL", default = "30")]
+    pub pool_health_check_interval: u64,
+    /// Prepared statements kept per connection, keyed by their SQL.
+    #[envconfig(from = "POSTGRES_STATEMENT_CACHE_SIZE", default = "256")]
+    pub statement_cache_size: usize,
 }
 
 impl PostgresConfig {
     pub fn new() -> Result<Self, envconfig::Error> {
         let config = Self::init_from_env()?;
         debug!(
-            "PostgresConfig loaded: user={}, host={}, dbname={}",
-            config.user, config.host, config.dbname
+            "PostgresConfig loaded: user={}, host={}, dbname={}, pool_min_size={}, pool_max_size={}, pool_acquire_timeout={}s, statement_cache_size={}",
+            config.user,
+            config.host,
+            config.dbname,
+            config.pool_min_size,
+            config.pool_max_size,
+            config.pool_acquire_timeout,
+            config.statement_cache_size
         );
         Ok(config)
     }
@@ -72,13 +107,30 @@ pub struct SnowflakeConfig {
 
     #[envconfig(from = "SNOWFLAKE_TIMEOUT")]
     pub timeout: Option<u64>,
+
+    /// Sessions running queries at the same time, and sessions kept idle.
+    #[envconfig(from = "SNOWFLAKE_POOL_MAX_SIZE", default = "8")]
+    pub pool_max_size: usize,
+
+    /// Seconds a query waits for a free session before failing.
+    #[envconfig(from = "SNOWFLAKE_POOL_ACQUIRE_TIMEOUT", default = "30")]
+    pub pool_acquire_timeout: u64,
+
+    /// Seconds a session stays idle before it's sent a heartbeat.
+    #[envconfig(from = "SNOWFLAKE_SESSION_KEEP_ALIVE", default = "900")]
+    pub session_keep_alive: u64,
+
+    /// Seconds a session stays idle before it's closed.
+    #[envconfig(from = "SNOWFLAKE_SESSION_IDLE_TIMEOUT", default = "3600")]
+    pub session_idle_timeout: u64,
 }
 
 impl SnowflakeConfig {
     pub fn new() -> Result<Self, envconfig::Error> {
         let config = Self::init_from_env()?;
-        debug!("SnowflakeConfig loaded: account={}, user={}, warehouse={:?}, database={:?}, schema={:?}, role={:?}, timeout={:?}",
-               config.account, config.user, config.warehouse, config.database, config.schema, config.role, config.timeout);
+        debug!("SnowflakeConfig loaded: account={}, user={}, warehouse={:?}, database={:?}, schema={:?}, role={:?}, timeout={:?}, pool_max_size={}, pool_acquire_timeout={}s, session_keep_alive={}s, session_idle_timeout={}s",
+               config.account, config.user, config.warehouse, config.database, config.schema, config.role, config.timeout,
+               config.pool_max_size, config.pool_acquire_timeout, config.session_keep_alive, config.session_idle_timeout);
         Ok(config)
     }
 }
@@ -120,14 +172,21 @@ impl SemanticModelJSONConfig {
     }
 }
 
+/// Users and their hashed passwords, as `user,credential` pairs separated by
+/// `;`. Credentials are SCRAM-SHA-256 verifiers or md5 hashes, in the format
+/// of `pg_authid.rolpassword`. The defaults are the verifiers of `password`
+/// and `password2`.
 #[derive(Envconfig)]
 pub struct AuthConfig {
-    #[envconfig(from = "AUTH", default = "admin,password;manager,password2")]
-    pub user_password_pair: String,
+    #[envconfig(
+        from = "AUTH",
+        default = "admin,SCRAM-SHA-256$4096:zQ3JOfGtFSPye0uGTthrqA==$nIsycxqYAHdNL/NjHzotRE7Gjjbc/IDnOJyqtephbTQ=:p29eoVzLfXPXTUiisddPGBMay5n95yyq5Nu6UbmxfJw=;manager,SCRAM-SHA-256$4096:WnJTW8IdFVUbTDV3sdixpg==$wgM4ntakgbdeecw7zJxteoEL49EJkbPOhsaQzTGOA/Q=:Ucg47Y6eiGoeoeK+kgWJXD/AvIpp6kEuT9HlkHR6ZJw="
+    )]
+    pub user_credential_pairs: String,
 }
 
 impl AuthConfig {
-    pub fn get_pairs() -> HashMap<String, String> {
+    pub fn get_credentials() -> HashMap<String, Credential> {
         let config = Self::init_from_env()
             .map_err(|e| {
                 error!("Failed to initialize AuthConfig: {}", e);
@@ -135,19 +194,21 @@ impl AuthConfig {
             })
             .unwrap();
 
-        let pairs: HashMap<String, String> = config
-            .user_password_pair
+        let credentials: HashMap<String, Credential> = config
+            .user_credential_pairs
             .split(';')
             .filter_map(|pair| {
-                let parts: Vec<&str> = pair.split(',').collect();
-                if parts.len() == 2 {
-                    Some((parts[0].to_string(), parts[1].to_string()))
-                } else {
-                    None
+                let (user, credential) = pair.split_once(',')?;
+                match credential.parse() {
+                    Ok(credential) => Some((user.to_string(), credential)),
+                    Err(e) => {
+                        error!("Ignoring credential of user {}: {}", user, e);
+                        None
+                    }
                 }
             })
             .collect();
 
-        pairs
+        credentials
     }
 }
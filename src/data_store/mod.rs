@@ -1,53 +1,287 @@
 pub mod postgres;
+pub mod session_pool;
 pub mod snowflake;
+pub mod snowflake_functions;
+pub mod snowflake_syntax;
+pub mod text_format;
 
 use async_trait::async_trait;
-use bytes::BytesMut;
-use pgwire::api::results::Response;
+use bytes::{Bytes, BytesMut};
+use pgwire::api::portal::Format;
+use pgwire::api::results::{FieldFormat, FieldInfo, Response};
+use pgwire::error::{ErrorInfo, PgWireError};
+use sqlparser::ast::{ArrayElemTypeDef, DataType, Expr, Function, Query, TimezoneInfo, Value};
 use std::error::Error;
 use std::fmt;
+use tokio_postgres::types::Type;
 
 /// DataStoreMapping handles the mapping logic for types and functions
 /// between different SQL dialects.
 pub trait DataStoreMapping {
-    /// Dialect supported by the [`DataStoreMapping`]
+    /// Dialect supported by the [`DataStoreMapping`], which the SQL of measures
+    /// is written in. Queries are parsed as Postgres and mapped to the DataStore.
     fn get_dialect(&self) -> &dyn sqlparser::dialect::Dialect;
 
     /// Mapping inbuilt Postgres functions to DataStore specific functions.
     ///
-    /// For example, Postgres `now()` function for returning the current timestamp
-    /// is mapped to `CURRENT_TIMESTAMP()` in Snowflake.
-    fn map_function(&self, pg_function: &str) -> Option<String>;
-
-    // You can uncomment or add type mapping functions when necessary
-    // /// Mapping Postgres types to DataStore specific types
-    // /// TODO: perhaps the input type should be the pgwire representation of types
-    // fn map_type(&self, pg_type: &PostgresType) -> Option<String>;
+    /// Functions are looked up by their lowercase name and number of arguments,
+    /// and `None` keeps the call as is. For example, Postgres `now()` function for
+    /// returning the current timestamp is mapped to `CURRENT_TIMESTAMP()` in Snowflake.
+    fn map_function(&self, name: &str, arity: usize) -> Option<FunctionMapping>;
+
+    /// Mapping Postgres expressions, such as `EXTRACT(epoch FROM ts)` or
+    /// `j -> 'key'`, to DataStore specific ones.
+    ///
+    /// Runs before [`DataStoreMapping::map_function`] on function calls, which
+    /// are mapped by name whether or not this rewrote them. `Ok(None)` keeps the
+    /// expression as is, and errors describe expressions the DataStore can't
+    /// support.
+    fn map_expression(&self, _expr: &Expr) -> Result<Option<Expr>, String> {
+        Ok(None)
+    }
+
+    /// Rewriting Postgres query clauses the DataStore doesn't support, such as
+    /// `DISTINCT ON`.
+    ///
+    /// Called on every query and subquery once its expressions are mapped.
+    fn map_query(&self, _query: &mut Query) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Mapping Postgres types to DataStore specific types, for casts such as
+    /// `CAST(x AS text)` or `x::int4`.
+    ///
+    /// Types are looked up by the Postgres type they name, see [`pg_type`], and
+    /// `None` keeps the type as is. For example, Postgres `timestamptz` is mapped
+    /// to `TIMESTAMP_TZ` in Snowflake.
+    fn map_type(&self, pg_type: &Type) -> Option<&'static str>;
+
+    /// The literal a string parameter is inlined into the SQL as, for DataStores
+    /// that don't bind parameters, see [`DataStoreClient::binds_parameters`].
+    ///
+    /// The literal must read back as exactly `value` in the DataStore's dialect,
+    /// whatever quotes or backslashes it contains. sqlparser only doubles the
+    /// quotes of a [`Value::SingleQuotedString`] that don't follow a backslash.
+    fn string_literal(&self, value: String) -> Value;
+}
+
+/// The Postgres type a type name in a query refers to, resolving aliases such
+/// as `int4`, `timestamptz` or `serial`, or `None` for types the proxy does not
+/// map.
+pub fn pg_type(data_type: &DataType) -> Option<Type> {
+    let pg_type = match data_type {
+        DataType::SmallInt(_) | DataType::Int2(_) => Type::INT2,
+        DataType::Int(_) | DataType::Integer(_) | DataType::Int4(_) => Type::INT4,
+        DataType::BigInt(_) | DataType::Int8(_) => Type::INT8,
+        DataType::Numeric(_) | DataType::Decimal(_) | DataType::Dec(_) => Type::NUMERIC,
+        DataType::Real | DataType::Float4 => Type::FLOAT4,
+        DataType::Double | DataType::DoublePrecision | DataType::Float8 => Type::FLOAT8,
+        // Postgres reads up to 24 bits of precision as real
+        DataType::Float(Some(precision)) if *precision <= 24 => Type::FLOAT4,
+        DataType::Float(_) => Type::FLOAT8,
+        DataType::Bool | DataType::Boolean => Type::BOOL,
+        DataType::Char(_) | DataType::Character(_) => Type::BPCHAR,
+        DataType::Varchar(_) | DataType::CharacterVarying(_) | DataType::CharVarying(_) => {
+            Type::VARCHAR
+        }
+        DataType::Text => Type::TEXT,
+        DataType::Uuid => Type::UUID,
+        DataType::Bytea => Type::BYTEA,
+        DataType::JSON => Type::JSON,
+        DataType::JSONB => Type::JSONB,
+        DataType::Date => Type::DATE,
+        DataType::Time(_, TimezoneInfo::None | TimezoneInfo::WithoutTimeZone) => Type::TIME,
+        DataType::Time(..) => Type::TIMETZ,
+        DataType::Timestamp(_, TimezoneInfo::None | TimezoneInfo::WithoutTimeZone) => {
+            Type::TIMESTAMP
+        }
+        DataType::Timestamp(..) => Type::TIMESTAMPTZ,
+        DataType::Interval => Type::INTERVAL,
+        DataType::Array(element) => {
+            let element = match element {
+                ArrayElemTypeDef::SquareBracket(element, _)
+                | ArrayElemTypeDef::AngleBracket(element)
+                | ArrayElemTypeDef::Parenthesis(element) => pg_type(element)?,
+                ArrayElemTypeDef::None => return None,
+            };
+            return array_type(&element);
+        }
+        DataType::Custom(name, _) => match name.to_string().to_lowercase().as_str() {
+            "smallserial" | "serial2" => Type::INT2,
+            "serial" | "serial4" => Type::INT4,
+            "bigserial" | "serial8" => Type::INT8,
+            "bpchar" => Type::BPCHAR,
+            "name" => Type::NAME,
+            "money" => Type::MONEY,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(pg_type)
+}
+
+/// The array type of the Postgres types [`pg_type`] returns.
+fn array_type(element: &Type) -> Option<Type> {
+    let array_type = match *element {
+        Type::INT2 => Type::INT2_ARRAY,
+        Type::INT4 => Type::INT4_ARRAY,
+        Type::INT8 => Type::INT8_ARRAY,
+        Type::NUMERIC => Type::NUMERIC_ARRAY,
+        Type::FLOAT4 => Type::FLOAT4_ARRAY,
+        Type::FLOAT8 => Type::FLOAT8_ARRAY,
+        Type::BOOL => Type::BOOL_ARRAY,
+        Type::BPCHAR => Type::BPCHAR_ARRAY,
+        Type::VARCHAR => Type::VARCHAR_ARRAY,
+        Type::TEXT => Type::TEXT_ARRAY,
+        Type::NAME => Type::NAME_ARRAY,
+        Type::UUID => Type::UUID_ARRAY,
+        Type::BYTEA => Type::BYTEA_ARRAY,
+        Type::JSON => Type::JSON_ARRAY,
+        Type::JSONB => Type::JSONB_ARRAY,
+        Type::DATE => Type::DATE_ARRAY,
+        Type::TIME => Type::TIME_ARRAY,
+        Type::TIMETZ => Type::TIMETZ_ARRAY,
+        Type::TIMESTAMP => Type::TIMESTAMP_ARRAY,
+        Type::TIMESTAMPTZ => Type::TIMESTAMPTZ_ARRAY,
+        Type::INTERVAL => Type::INTERVAL_ARRAY,
+        Type::MONEY => Type::MONEY_ARRAY,
+        _ => return None,
+    };
+    Some(array_type)
+}
+
+/// How a Postgres function call is rewritten for a DataStore, see
+/// [`DataStoreMapping::map_function`].
+#[derive(Debug, Clone, Copy)]
+pub enum FunctionMapping {
+    /// Call the DataStore function of this name with the same arguments.
+    Rename(&'static str),
+    /// Build the DataStore expression from the positional arguments of the call,
+    /// which are already rewritten. Errors describe arguments the DataStore
+    /// can't support.
+    Rewrite(fn(Vec<Expr>) -> Result<Expr, String>),
+    /// Rewrite the whole call, for aggregates whose `DISTINCT` or `ORDER BY`
+    /// clauses move elsewhere.
+    RewriteCall(fn(Function) -> Result<Expr, String>),
+    /// The DataStore has no equivalent, with a hint on what to use instead.
+    Unsupported(&'static str),
 }
 
 /// DataStoreClient is responsible for executing queries and returning
 /// results from the DataStore.
 #[async_trait]
 pub trait DataStoreClient {
-    type Mapping: DataStoreMapping + Send;
+    type Mapping: DataStoreMapping + Send + Sync;
 
     fn get_mapping() -> Self::Mapping;
 
-    /// Execute the SQL query and return the result as [`DataRow`]s.
+    /// Describe the columns the SQL query returns, without returning any rows.
+    ///
+    /// Used by the extended query protocol to answer `Describe` messages.
+    /// `parameter_types` are the types the client declared for the `$n`
+    /// placeholders of the SQL, which only DataStores binding parameters receive.
+    async fn describe(
+        &self,
+        user: &str,
+        sql: &str,
+        parameter_types: &[Type],
+    ) -> Result<Vec<FieldInfo>, DataStoreError>;
+
+    /// Whether the DataStore takes the values of `$n` placeholders apart from the
+    /// SQL. Otherwise they are inlined into the SQL as literals before it is
+    /// executed, see [`DataStoreMapping::string_literal`].
+    fn binds_parameters(&self) -> bool {
+        false
+    }
+
+    /// Usage of the DataStore's connection pool, for monitoring. `None` when
+    /// the DataStore doesn't pool its connections.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    /// Execute the SQL query and stream the [`DataRow`]s as the DataStore
+    /// returns them, rather than collecting the whole result first.
+    ///
+    /// Columns are encoded in the text or binary format as `format` requests.
+    /// Errors the DataStore reports after the first rows end the stream.
+    /// `user` is the proxy user the query runs for, which DataStores keeping
+    /// sessions per user run it on. `parameters` are the values of the `$n`
+    /// placeholders of the SQL, which is empty unless the DataStore
+    /// [binds parameters](DataStoreClient::binds_parameters).
     ///
-    /// The DataStore must internally map the result data into the
-    /// pgwire [`DataRow`] type.
-    async fn execute(&self, sql: &str) -> Result<Vec<Response>, DataStoreError>;
+    /// The rows don't borrow the DataStore, so a portal suspended by a row limit
+    /// can keep them until the client fetches the next batch.
+    async fn execute_streaming(
+        &self,
+        user: &str,
+        sql: &str,
+        parameters: &[Parameter],
+        format: &Format,
+    ) -> Result<Response<'static>, DataStoreError>;
+}
+
+/// The value of a `$n` placeholder, as the client bound it to a portal.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    /// The type the client declared, `UNKNOWN` when it left it to the DataStore.
+    pub pg_type: Type,
+    /// The value in the text or binary format, or `None` for NULL.
+    pub value: Option<Bytes>,
+    pub format: FieldFormat,
+}
+
+/// Snapshot of a connection pool's usage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Upper bound on the connections the pool opens.
+    pub max_size: usize,
+    /// Connections currently open, idle or in use.
+    pub size: usize,
+    /// Idle connections ready to be handed out.
+    pub available: usize,
+    /// Queries waiting for a connection to become available.
+    pub waiting: usize,
+}
 
-    // TODO: Add execute_streaming that returns a stream instead of a vector of data rows
-    // async fn execute_streaming(&self, sql: &str) -> Result<Stream<DataRow>, DataStoreError>;
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "size={}/{} available={} waiting={}",
+            self.size, self.max_size, self.available, self.waiting
+        )
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DataStoreError {
     ConnectionError(String),
     QueryError(String),
     ColumnNotFound(String),
+    /// A column type the proxy can't encode for the client.
+    UnsupportedType(String),
+    /// An error the DataStore reported with its own SQLSTATE code, which is
+    /// passed on to the client as is.
+    DatabaseError {
+        code: String,
+        message: String,
+        detail: Option<String>,
+        hint: Option<String>,
+    },
+}
+
+impl DataStoreError {
+    /// The SQLSTATE code drivers classify the error by.
+    pub fn code(&self) -> &str {
+        match self {
+            DataStoreError::ConnectionError(_) => "08001",
+            DataStoreError::QueryError(_) => "XX000",
+            DataStoreError::ColumnNotFound(_) => "42703",
+            DataStoreError::UnsupportedType(_) => "0A000",
+            DataStoreError::DatabaseError { code, .. } => code,
+        }
+    }
 }
 
 impl fmt::Display for DataStoreError {
@@ -62,17 +296,43 @@ impl fmt::Display for DataStoreError {
             DataStoreError::ColumnNotFound(details) => {
                 write!(f, "Column not found: {}", details)
             }
+            DataStoreError::UnsupportedType(pg_type) => {
+                write!(f, "Unsupported column type: {}", pg_type)
+            }
+            DataStoreError::DatabaseError { message, .. } => f.write_str(message),
         }
     }
 }
 
 impl Error for DataStoreError {}
 
+impl From<DataStoreError> for ErrorInfo {
+    fn from(e: DataStoreError) -> Self {
+        let mut info = ErrorInfo::new("ERROR".to_string(), e.code().to_string(), e.to_string());
+        if let DataStoreError::DatabaseError { detail, hint, .. } = e {
+            info.detail = detail;
+            info.hint = hint;
+        }
+        info
+    }
+}
+
+impl From<DataStoreError> for PgWireError {
+    fn from(e: DataStoreError) -> Self {
+        PgWireError::UserError(Box::new(e.into()))
+    }
+}
+
 pub fn encode_value(buffer: &mut BytesMut, value: Option<String>) {
+    encode_raw_value(buffer, value.as_deref().map(str::as_bytes));
+}
+
+/// Appends a value already encoded in the text or binary format, or NULL.
+pub fn encode_raw_value(buffer: &mut BytesMut, value: Option<&[u8]>) {
     match value {
         Some(v) => {
             buffer.extend_from_slice(&(v.len() as i32).to_be_bytes());
-            buffer.extend_from_slice(v.as_bytes());
+            buffer.extend_from_slice(v);
         }
         None => {
             buffer.extend_from_slice(&(-1_i32).to_be_bytes());
@@ -1,47 +1,321 @@
-use super::encode_value;
+use super::text_format::{to_binary, to_text};
+use super::{encode_raw_value, encode_value};
 use crate::config::PostgresConfig;
-use crate::data_store::{DataStoreClient, DataStoreError, DataStoreMapping};
+use crate::data_store::{
+    DataStoreClient, DataStoreError, DataStoreMapping, FunctionMapping, Parameter, PoolStats,
+};
 use async_trait::async_trait;
 use bytes::BytesMut;
-use futures::Stream;
-use log::error;
-use pgwire::api::results::FieldInfo;
+use deadpool_postgres::{
+    Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod, Runtime, StatementCache,
+    TimeoutType,
+};
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use pgwire::api::results::{FieldFormat, FieldInfo};
 use pgwire::api::{
     portal::Format,
     results::{QueryResponse, Response},
 };
 use pgwire::error::PgWireResult;
 use pgwire::messages::data::DataRow;
+use sqlparser::ast::Value;
 use sqlparser::dialect::PostgreSqlDialect;
-use std::sync::Arc;
-use tokio_postgres::{types::Type, Row, Statement};
-use tokio_postgres::{Client, NoTls};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+use tokio_postgres::NoTls;
+use tokio_postgres::{Row, RowStream, Statement};
+
+/// Times a query is attempted when its connection turns out to be closed.
+const MAX_ATTEMPTS: usize = 2;
 
 pub struct PostgresDataStore {
-    client: Client,
+    pool: Pool,
+    statement_usage: StatementUsage<StatementCache>,
 }
 
 pub struct PostgresMapping;
 
 impl PostgresDataStore {
     pub async fn new(config: PostgresConfig) -> Result<Self, DataStoreError> {
+        if config.pool_min_size > config.pool_max_size {
+            return Err(DataStoreError::ConnectionError(format!(
+                "POSTGRES_POOL_MIN_SIZE ({}) exceeds POSTGRES_POOL_MAX_SIZE ({})",
+                config.pool_min_size, config.pool_max_size
+            )));
+        }
+
         let connection_string = format!(
             "postgres://{}:{}@{}/{}",
             config.user, config.password, config.host, config.dbname
         );
+        let pg_config = connection_string
+            .parse::<tokio_postgres::Config>()
+            .map_err(|e| DataStoreError::ConnectionError(e.to_string()))?;
 
-        let (client, connection) = tokio_postgres::connect(&connection_string, NoTls)
-            .await
+        // Verified recycling runs an empty query before handing out an idle
+        // connection, so ones the server dropped are replaced instead of failing.
+        let manager = Manager::from_config(
+            pg_config,
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Verified,
+            },
+        );
+        let timeout = Some(Duration::from_secs(config.pool_acquire_timeout));
+        let pool = Pool::builder(manager)
+            .max_size(config.pool_max_size)
+            .wait_timeout(timeout)
+            .create_timeout(timeout)
+            .recycle_timeout(timeout)
+            .runtime(Runtime::Tokio1)
+            .build()
             .map_err(|e| DataStoreError::ConnectionError(e.to_string()))?;
 
-        // Spawn a task to manage the connection
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("Connection error: {}", e);
+        fill_pool(&pool, config.pool_min_size).await?;
+        info!("Opened {} Postgres connections", pool.status().size);
+
+        tokio::spawn(check_health(
+            pool.clone(),
+            config.pool_min_size,
+            Duration::from_secs(config.pool_health_check_interval),
+        ));
+
+        Ok(PostgresDataStore {
+            pool,
+            statement_usage: StatementUsage::new(config.statement_cache_size),
+        })
+    }
+
+    async fn client(&self) -> Result<Object, DataStoreError> {
+        self.pool.get().await.map_err(pool_error)
+    }
+
+    /// Prepares the statement once per connection, later queries with the
+    /// same SQL and parameter types reuse it. A full cache evicts the statement
+    /// that was used least recently.
+    async fn prepare(
+        &self,
+        client: &Object,
+        sql: &str,
+        types: &[Type],
+    ) -> Result<Statement, tokio_postgres::Error> {
+        if self.statement_usage.capacity == 0 {
+            return client.prepare_typed(sql, types).await;
+        }
+        if let Some((sql, types)) = self
+            .statement_usage
+            .record(&client.statement_cache, sql, types)
+        {
+            client.statement_cache.remove(&sql, &types);
+        }
+        client.prepare_typed_cached(sql, types).await
+    }
+
+    /// Prepares and runs the statement with the parameters bound, retrying on
+    /// another connection when the connection was lost.
+    async fn run(
+        &self,
+        sql: &str,
+        parameters: &[Parameter],
+    ) -> Result<(Object, Statement, RowStream), DataStoreError> {
+        let types = parameter_types(parameters);
+        let mut attempt = 1;
+        loop {
+            let client = self.client().await?;
+            let result = async {
+                let stmt = self.prepare(&client, sql, &types).await?;
+                let params = parameters.iter().map(RawParameter);
+                let rows = client.query_raw(&stmt, params).await?;
+                Ok((stmt, rows))
             }
-        });
+            .await;
+            match result {
+                Err(e) if retry_on_new_connection(&client, &e, attempt) => {
+                    let _ = Object::take(client);
+                    attempt += 1;
+                }
+                Err(e) if is_stale_statement(&e) && attempt < MAX_ATTEMPTS => {
+                    warn!("Cached Postgres statement is outdated, preparing it again");
+                    client.statement_cache.remove(sql, &types);
+                    attempt += 1;
+                }
+                result => {
+                    let (stmt, rows) = result.map_err(query_error)?;
+                    return Ok((client, stmt, rows));
+                }
+            }
+        }
+    }
+}
+
+/// The SQL and parameter types a statement is cached by.
+type StatementKey = (String, Vec<Type>);
+
+/// When the statements cached on each connection were last used, which tells
+/// which one to evict from a full cache.
+struct StatementUsage<C> {
+    /// Statements cached per connection.
+    capacity: usize,
+    connections: Mutex<Vec<ConnectionUsage<C>>>,
+}
+
+struct ConnectionUsage<C> {
+    /// The cache of the connection, gone once the connection is closed.
+    cache: Weak<C>,
+    last_used: HashMap<StatementKey, u64>,
+    clock: u64,
+}
+
+impl<C> StatementUsage<C> {
+    fn new(capacity: usize) -> Self {
+        StatementUsage {
+            capacity,
+            connections: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records the statement as used on the connection with `cache`, returning
+    /// the least recently used statement when this one doesn't fit anymore.
+    fn record(&self, cache: &Arc<C>, sql: &str, types: &[Type]) -> Option<StatementKey> {
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|usage| usage.cache.strong_count() > 0);
+        let idx = match connections
+            .iter()
+            .position(|usage| std::ptr::eq(usage.cache.as_ptr(), Arc::as_ptr(cache)))
+        {
+            Some(idx) => idx,
+            None => {
+                connections.push(ConnectionUsage {
+                    cache: Arc::downgrade(cache),
+                    last_used: HashMap::new(),
+                    clock: 0,
+                });
+                connections.len() - 1
+            }
+        };
+
+        let usage = &mut connections[idx];
+        usage.clock += 1;
+        let key = (sql.to_string(), types.to_vec());
+        let is_new = usage.last_used.insert(key, usage.clock).is_none();
+        if !is_new || usage.last_used.len() <= self.capacity {
+            return None;
+        }
+        let oldest = usage
+            .last_used
+            .iter()
+            .min_by_key(|(_, used)| **used)
+            .map(|(key, _)| key.clone())?;
+        usage.last_used.remove(&oldest);
+        Some(oldest)
+    }
+}
+
+/// The types the statement is prepared with, where `UNKNOWN` leaves it to
+/// Postgres to infer the type of the parameter.
+fn parameter_types(parameters: &[Parameter]) -> Vec<Type> {
+    parameters.iter().map(|p| p.pg_type.clone()).collect()
+}
+
+/// A parameter sent to Postgres in the binary format of the type the statement
+/// was prepared with, converting values the client sent as text.
+#[derive(Debug)]
+struct RawParameter<'a>(&'a Parameter);
+
+impl ToSql for RawParameter<'_> {
+    fn to_sql(
+        &self,
+        pg_type: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let Some(raw) = &self.0.value else {
+            return Ok(IsNull::Yes);
+        };
+        match self.0.format {
+            FieldFormat::Binary => out.extend_from_slice(raw),
+            FieldFormat::Text => {
+                let text =
+                    std::str::from_utf8(raw).map_err(|e| invalid_parameter(e.to_string()))?;
+                let binary = to_binary(pg_type, text).map_err(|e| match e {
+                    DataStoreError::QueryError(message) => invalid_parameter(message),
+                    e => e,
+                })?;
+                out.extend_from_slice(&binary);
+            }
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+fn invalid_parameter(message: String) -> DataStoreError {
+    DataStoreError::DatabaseError {
+        code: SqlState::INVALID_TEXT_REPRESENTATION.code().to_string(),
+        message,
+        detail: None,
+        hint: None,
+    }
+}
 
-        Ok(PostgresDataStore { client })
+/// Opens connections until the pool holds at least `min_size` of them.
+async fn fill_pool(pool: &Pool, min_size: usize) -> Result<(), DataStoreError> {
+    let missing = min_size.saturating_sub(pool.status().size);
+    if missing == 0 {
+        return Ok(());
+    }
+    // Holding the connections at once forces the pool to open new ones rather
+    // than handing out the same idle connection repeatedly.
+    let clients = futures::future::try_join_all((0..missing).map(|_| pool.get()))
+        .await
+        .map_err(pool_error)?;
+    drop(clients);
+    Ok(())
+}
+
+/// Periodically discards connections the server closed and reopens the
+/// minimum number of connections, so the pool recovers from a database
+/// restart without waiting for queries to fail.
+async fn check_health(pool: Pool, min_size: usize, interval: Duration) {
+    let weak = pool.weak();
+    drop(pool);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        // Stop once the DataStore owning the pool is dropped
+        let Some(pool) = weak.upgrade() else {
+            return;
+        };
+        let removed = pool.retain(|client, _| !client.is_closed()).removed.len();
+        if removed > 0 {
+            warn!("Discarded {} closed Postgres connections", removed);
+        }
+        if let Err(e) = fill_pool(&pool, min_size).await {
+            error!("Failed to reopen Postgres connections: {}", e);
+        }
+    }
+}
+
+fn pool_error(e: PoolError) -> DataStoreError {
+    match e {
+        PoolError::Timeout(TimeoutType::Wait) => DataStoreError::ConnectionError(
+            "Timed out waiting for an available Postgres connection".to_string(),
+        ),
+        PoolError::Backend(e) => match e.as_db_error() {
+            Some(_) => query_error(e),
+            None => DataStoreError::ConnectionError(e.to_string()),
+        },
+        e => DataStoreError::ConnectionError(e.to_string()),
     }
 }
 
@@ -51,14 +325,19 @@ impl DataStoreMapping for PostgresMapping {
     }
 
     // pass through since input functions are in postgres dialect
-    fn map_function(&self, pg_function: &str) -> Option<String> {
-        Some(pg_function.to_string())
+    fn map_function(&self, _name: &str, _arity: usize) -> Option<FunctionMapping> {
+        None
+    }
+
+    // pass through since input types are in postgres dialect
+    fn map_type(&self, _pg_type: &Type) -> Option<&'static str> {
+        None
     }
 
-    // Implement type mapping if necessary
-    // fn map_type(&self, pg_type: &PostgresType) -> Option<String> {
-    //     // Example mapping
-    // }
+    // E'' strings escape quotes and backslashes whatever standard_conforming_strings is
+    fn string_literal(&self, value: String) -> Value {
+        Value::EscapedStringLiteral(value)
+    }
 }
 
 #[async_trait]
@@ -69,28 +348,108 @@ impl DataStoreClient for PostgresDataStore {
         PostgresMapping {}
     }
 
-    async fn execute(&self, sql: &str) -> Result<Vec<Response>, DataStoreError> {
-        println!("Executing SQL: {}", sql);
-        let rows = self
-            .client
-            .query(sql, &[])
-            .await
-            .map_err(|e| DataStoreError::ColumnNotFound(e.to_string()))?;
+    async fn describe(
+        &self,
+        _user: &str,
+        sql: &str,
+        parameter_types: &[Type],
+    ) -> Result<Vec<FieldInfo>, DataStoreError> {
+        let mut attempt = 1;
+        let stmt = loop {
+            let client = self.client().await?;
+            match self.prepare(&client, sql, parameter_types).await {
+                Err(e) if retry_on_new_connection(&client, &e, attempt) => {
+                    let _ = Object::take(client);
+                    attempt += 1;
+                }
+                result => break result.map_err(query_error)?,
+            }
+        };
 
-        let stmt = self
-            .client
-            .prepare(sql)
-            .await
-            .map_err(|e| DataStoreError::ColumnNotFound(e.to_string()))?;
+        row_desc_from_stmt(&stmt, &Format::UnifiedText)
+            .map_err(|e| DataStoreError::ColumnNotFound(e.to_string()))
+    }
+
+    fn binds_parameters(&self) -> bool {
+        true
+    }
 
-        let field_info = row_desc_from_stmt(&stmt, &Format::UnifiedText)
+    async fn execute_streaming(
+        &self,
+        _user: &str,
+        sql: &str,
+        parameters: &[Parameter],
+        format: &Format,
+    ) -> Result<Response<'static>, DataStoreError> {
+        debug!("Executing SQL: {}", sql);
+        let (client, stmt, rows) = self.run(sql, parameters).await?;
+
+        let field_info = row_desc_from_stmt(&stmt, format)
             .map_err(|e| DataStoreError::ColumnNotFound(e.to_string()))?;
         let field_info_arc = Arc::new(field_info);
-        let data_rows = encode_row_data(rows, field_info_arc.clone());
-        Ok(vec![Response::Query(QueryResponse::new(
+        let schema = field_info_arc.clone();
+        let data_rows = rows.map(move |row| {
+            // The connection stays checked out of the pool until the rows are read
+            let _client = &client;
+            row.map_err(query_error)
+                .and_then(|row| encode_row(&row, &schema))
+                .map_err(Into::into)
+        });
+        Ok(Response::Query(QueryResponse::new(
             field_info_arc,
             Box::pin(data_rows),
-        ))])
+        )))
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        let status = self.pool.status();
+        Some(PoolStats {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+        })
+    }
+}
+
+/// Whether the query failed because the connection was lost, in which case
+/// the connection is discarded and the query runs again on another one.
+/// Queries are read-only, so running them again is safe.
+fn retry_on_new_connection(client: &Object, e: &tokio_postgres::Error, attempt: usize) -> bool {
+    if attempt >= MAX_ATTEMPTS || !(e.is_closed() || client.is_closed()) {
+        return false;
+    }
+    warn!(
+        "Postgres connection lost, retrying on a new connection: {}",
+        e
+    );
+    true
+}
+
+/// Whether the cached statement no longer matches the tables it reads, which
+/// Postgres reports after their columns changed.
+fn is_stale_statement(e: &tokio_postgres::Error) -> bool {
+    e.code() == Some(&SqlState::FEATURE_NOT_SUPPORTED)
+        && e.as_db_error().is_some_and(|e| {
+            e.message()
+                .starts_with("cached plan must not change result type")
+        })
+}
+
+/// Keeps the SQLSTATE, detail and hint Postgres reported for the error, or
+/// the code of a parameter the proxy failed to convert.
+fn query_error(e: tokio_postgres::Error) -> DataStoreError {
+    if let Some(db_error) = e.as_db_error() {
+        return DataStoreError::DatabaseError {
+            code: db_error.code().code().to_string(),
+            message: db_error.message().to_string(),
+            detail: db_error.detail().map(str::to_string),
+            hint: db_error.hint().map(str::to_string),
+        };
+    }
+    match e.source().and_then(|e| e.downcast_ref::<DataStoreError>()) {
+        Some(parameter_error) => parameter_error.clone(),
+        None => DataStoreError::QueryError(e.to_string()),
     }
 }
 
@@ -111,31 +470,80 @@ pub fn row_desc_from_stmt(stmt: &Statement, format: &Format) -> PgWireResult<Vec
         .collect()
 }
 
-pub fn encode_row_data(
-    rows: Vec<Row>,
-    schema: Arc<Vec<FieldInfo>>,
-) -> impl Stream<Item = PgWireResult<DataRow>> {
-    futures::stream::iter(rows.into_iter().map(move |row| {
-        let mut buffer = BytesMut::new();
-        for (idx, field) in schema.iter().enumerate() {
-            let pg_type = field.datatype();
-            let value = match pg_type {
-                &Type::INT4 => row.get::<_, Option<i32>>(idx).map(|v| v.to_string()),
-                &Type::NUMERIC => row
-                    .get::<_, Option<rust_decimal::Decimal>>(idx)
-                    .map(|v| v.to_string()),
-                &Type::TEXT | &Type::VARCHAR => row.get::<_, Option<String>>(idx),
-                &Type::BOOL => row.get::<_, Option<bool>>(idx).map(|v| v.to_string()),
-                &Type::FLOAT4 => row.get::<_, Option<f32>>(idx).map(|v| v.to_string()),
-                &Type::FLOAT8 => row.get::<_, Option<f64>>(idx).map(|v| v.to_string()),
-                &Type::INT8 => row.get::<_, Option<i64>>(idx).map(|v| v.to_string()),
-                _ => {
-                    error!("Unexpected Type: {:?}", pg_type);
-                    None
-                }
-            };
-            encode_value(&mut buffer, value);
+/// Encodes the row in the format of each field, whatever the column types.
+///
+/// Postgres sends rows in the binary format, which is passed on as is.
+pub fn encode_row(row: &Row, schema: &[FieldInfo]) -> Result<DataRow, DataStoreError> {
+    let mut buffer = BytesMut::new();
+    for (idx, field) in schema.iter().enumerate() {
+        let raw = row
+            .try_get::<_, Option<RawValue>>(idx)
+            .map_err(|e| DataStoreError::QueryError(e.to_string()))?;
+        match (raw, field.format()) {
+            (Some(RawValue(raw)), FieldFormat::Binary) => encode_raw_value(&mut buffer, Some(raw)),
+            (Some(RawValue(raw)), FieldFormat::Text) => {
+                encode_value(&mut buffer, Some(to_text(field.datatype(), raw)?))
+            }
+            (None, _) => encode_raw_value(&mut buffer, None),
         }
-        Ok(DataRow::new(buffer, schema.len() as i16))
-    }))
+    }
+    Ok(DataRow::new(buffer, schema.len() as i16))
+}
+
+/// A value of any type as received from Postgres, in the binary format.
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn key(sql: &str) -> Option<StatementKey> {
+        Some((sql.to_string(), vec![]))
+    }
+
+    #[test]
+    fn test_statement_usage_evicts_least_recently_used() {
+        let usage = StatementUsage::new(2);
+        let cache = Arc::new(());
+        assert_eq!(usage.record(&cache, "a", &[]), None);
+        assert_eq!(usage.record(&cache, "b", &[]), None);
+        assert_eq!(usage.record(&cache, "a", &[]), None);
+        assert_eq!(usage.record(&cache, "c", &[]), key("b"));
+        assert_eq!(usage.record(&cache, "d", &[]), key("a"));
+        assert_eq!(usage.record(&cache, "c", &[Type::INT4]), key("c"));
+    }
+
+    #[test]
+    fn test_statement_usage_per_connection() {
+        let usage = StatementUsage::new(1);
+        let (first, second) = (Arc::new(()), Arc::new(()));
+        assert_eq!(usage.record(&first, "a", &[]), None);
+        assert_eq!(usage.record(&second, "b", &[]), None);
+        assert_eq!(usage.record(&first, "b", &[]), key("a"));
+
+        // A closed connection's statements are forgotten
+        drop(second);
+        let third = Arc::new(());
+        assert_eq!(usage.record(&third, "c", &[]), None);
+        assert_eq!(usage.connections.lock().unwrap().len(), 2);
+    }
+
+    #[rstest]
+    #[case::plain("name", "E'name'")]
+    #[case::escaped_quote(r"\'; DROP TABLE t --", r"E'\\\'; DROP TABLE t --'")]
+    fn test_string_literal(#[case] value: &str, #[case] expected: &str) {
+        let literal = PostgresMapping {}.string_literal(value.to_string());
+        assert_eq!(literal.to_string(), expected);
+    }
 }
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use snowflake_connector_rs::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::data_store::{DataStoreError, PoolStats};
+
+/// Creates Snowflake sessions for the [`SessionPool`] and keeps them alive.
+#[async_trait]
+pub trait SessionFactory: Send + Sync + 'static {
+    type Session: Send + Sync + 'static;
+
+    /// Logs in and starts a new session.
+    async fn create_session(&self) -> Result<Self::Session, Error>;
+
+    /// Uses the session so Snowflake doesn't expire it while it's idle.
+    async fn heartbeat(&self, session: &Self::Session) -> Result<(), Error>;
+}
+
+pub struct SessionPoolConfig {
+    /// Sessions running queries at the same time, and sessions kept idle.
+    pub max_size: usize,
+    /// How long a query waits for a session before failing.
+    pub acquire_timeout: Duration,
+    /// How long a session stays idle before it's sent a heartbeat.
+    pub keep_alive: Duration,
+    /// How long a session stays idle before it's closed.
+    pub idle_timeout: Duration,
+}
+
+/// Pool of Snowflake sessions, kept per proxy user.
+///
+/// A user's queries reuse the sessions that user's earlier queries ran on, so
+/// the session state (role, warehouse, cached results) carries over between
+/// queries. Sessions that expire are replaced by logging in again.
+pub struct SessionPool<F: SessionFactory> {
+    inner: Arc<Inner<F>>,
+}
+
+struct Inner<F: SessionFactory> {
+    factory: F,
+    config: SessionPoolConfig,
+    /// Permits for the sessions running queries
+    permits: Arc<Semaphore>,
+    idle: Mutex<HashMap<String, Vec<IdleSession<F::Session>>>>,
+    waiting: AtomicUsize,
+}
+
+struct IdleSession<S> {
+    session: Arc<S>,
+    last_used: Instant,
+}
+
+/// A session handed out to run a query, returned to the pool when dropped.
+struct Lease<F: SessionFactory> {
+    inner: Arc<Inner<F>>,
+    user: String,
+    session: Option<Arc<F::Session>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<F: SessionFactory> SessionPool<F> {
+    /// Creates the pool and starts sending heartbeats to its idle sessions.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn new(factory: F, config: SessionPoolConfig) -> Self {
+        let inner = Arc::new(Inner {
+            factory,
+            permits: Arc::new(Semaphore::new(config.max_size)),
+            config,
+            idle: Mutex::new(HashMap::new()),
+            waiting: AtomicUsize::new(0),
+        });
+        tokio::spawn(keep_alive(Arc::downgrade(&inner)));
+        Self { inner }
+    }
+
+    /// Runs `query` on one of the user's sessions.
+    ///
+    /// When Snowflake reports the session expired, it logs in again and runs
+    /// the query once more on the new session.
+    pub async fn run<T, Q, Fut>(&self, user: &str, query: Q) -> Result<T, DataStoreError>
+    where
+        Q: Fn(Arc<F::Session>) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut lease = self.acquire(user).await?;
+        match query(lease.session()).await {
+            Err(Error::SessionExpired) => {
+                debug!("Snowflake session of {} expired, logging in again", user);
+                lease.session = None;
+                lease.session = Some(Arc::new(self.inner.create_session().await?));
+                query(lease.session()).await.map_err(query_error)
+            }
+            result => result.map_err(query_error),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let idle = self.inner.idle.lock().unwrap();
+        let available = idle.values().map(Vec::len).sum::<usize>();
+        let in_use = self.inner.config.max_size - self.inner.permits.available_permits();
+        PoolStats {
+            max_size: self.inner.config.max_size,
+            size: in_use + available,
+            available,
+            waiting: self.inner.waiting.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn acquire(&self, user: &str) -> Result<Lease<F>, DataStoreError> {
+        self.inner.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = tokio::time::timeout(
+            self.inner.config.acquire_timeout,
+            self.inner.permits.clone().acquire_owned(),
+        )
+        .await;
+        self.inner.waiting.fetch_sub(1, Ordering::Relaxed);
+        let permit = permit
+            .map_err(|_| {
+                DataStoreError::ConnectionError(
+                    "Timed out waiting for an available Snowflake session".to_string(),
+                )
+            })?
+            .expect("the semaphore is never closed");
+
+        // The most recently used session is the least likely to have expired
+        let idle = self
+            .inner
+            .idle
+            .lock()
+            .unwrap()
+            .get_mut(user)
+            .and_then(Vec::pop);
+        let session = match idle {
+            Some(idle) => idle.session,
+            None => {
+                debug!("Creating a Snowflake session for {}", user);
+                Arc::new(self.inner.create_session().await?)
+            }
+        };
+
+        Ok(Lease {
+            inner: self.inner.clone(),
+            user: user.to_string(),
+            session: Some(session),
+            _permit: permit,
+        })
+    }
+}
+
+impl<F: SessionFactory> Inner<F> {
+    async fn create_session(&self) -> Result<F::Session, DataStoreError> {
+        self.factory.create_session().await.map_err(|e| {
+            DataStoreError::ConnectionError(format!("Failed to connect to Snowflake, {}", e))
+        })
+    }
+
+    /// Keeps the session for the user's next query, closing the least recently
+    /// used idle session when the pool is full.
+    fn release(&self, user: String, session: Arc<F::Session>) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.entry(user).or_default().push(IdleSession {
+            session,
+            last_used: Instant::now(),
+        });
+
+        let size = idle.values().map(Vec::len).sum::<usize>();
+        if size > self.config.max_size {
+            let oldest = idle
+                .iter()
+                .filter_map(|(user, sessions)| Some((user, sessions.first()?.last_used)))
+                .min_by_key(|(_, last_used)| *last_used)
+                .map(|(user, _)| user.clone());
+            if let Some(user) = oldest {
+                remove_first(&mut idle, &user);
+            }
+        }
+    }
+}
+
+impl<F: SessionFactory> Lease<F> {
+    fn session(&self) -> Arc<F::Session> {
+        self.session.clone().expect("the lease holds a session")
+    }
+}
+
+impl<F: SessionFactory> Drop for Lease<F> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.inner.release(std::mem::take(&mut self.user), session);
+        }
+    }
+}
+
+fn remove_first<S>(idle: &mut HashMap<String, Vec<IdleSession<S>>>, user: &str) {
+    if let Some(sessions) = idle.get_mut(user) {
+        sessions.remove(0);
+        if sessions.is_empty() {
+            idle.remove(user);
+        }
+    }
+}
+
+fn query_error(e: Error) -> DataStoreError {
+    DataStoreError::QueryError(e.to_string())
+}
+
+/// Closes sessions idle for longer than the idle timeout and sends heartbeats
+/// to the others, until the pool is dropped.
+async fn keep_alive<F: SessionFactory>(pool: Weak<Inner<F>>) {
+    let Some(interval) = pool.upgrade().map(|inner| inner.config.keep_alive) else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(inner) = pool.upgrade() else {
+            return;
+        };
+        check_idle_sessions(&inner).await;
+    }
+}
+
+async fn check_idle_sessions<F: SessionFactory>(inner: &Inner<F>) {
+    let stale = {
+        let mut idle = inner.idle.lock().unwrap();
+        idle.retain(|_, sessions| {
+            sessions.retain(|s| s.last_used.elapsed() < inner.config.idle_timeout);
+            !sessions.is_empty()
+        });
+        idle.values()
+            .flatten()
+            .filter(|s| s.last_used.elapsed() >= inner.config.keep_alive)
+            .map(|s| s.session.clone())
+            .collect::<Vec<_>>()
+    };
+
+    for session in stale {
+        if let Err(e) = inner.factory.heartbeat(&session).await {
+            warn!("Closing Snowflake session after failed heartbeat: {}", e);
+            let mut idle = inner.idle.lock().unwrap();
+            for sessions in idle.values_mut() {
+                sessions.retain(|s| !Arc::ptr_eq(&s.session, &session));
+            }
+            idle.retain(|_, sessions| !sessions.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// Sessions are numbered in the order they're created.
+    struct MockFactory {
+        created: AtomicUsize,
+        heartbeats: AtomicUsize,
+        expired: Mutex<Vec<usize>>,
+        fail_login: AtomicBool,
+    }
+
+    impl MockFactory {
+        fn new() -> Self {
+            Self {
+                created: AtomicUsize::new(0),
+                heartbeats: AtomicUsize::new(0),
+                expired: Mutex::new(Vec::new()),
+                fail_login: AtomicBool::new(false),
+            }
+        }
+
+        fn query(&self, session: &usize) -> Result<usize, Error> {
+            if self.expired.lock().unwrap().contains(session) {
+                return Err(Error::SessionExpired);
+            }
+            Ok(*session)
+        }
+    }
+
+    #[async_trait]
+    impl SessionFactory for Arc<MockFactory> {
+        type Session = usize;
+
+        async fn create_session(&self) -> Result<usize, Error> {
+            if self.fail_login.load(Ordering::Relaxed) {
+                return Err(Error::Communication("login failed".to_string()));
+            }
+            Ok(self.created.fetch_add(1, Ordering::Relaxed))
+        }
+
+        async fn heartbeat(&self, session: &usize) -> Result<(), Error> {
+            self.heartbeats.fetch_add(1, Ordering::Relaxed);
+            self.query(session).map(|_| ())
+        }
+    }
+
+    fn pool(max_size: usize) -> (SessionPool<Arc<MockFactory>>, Arc<MockFactory>) {
+        let factory = Arc::new(MockFactory::new());
+        let pool = SessionPool::new(
+            factory.clone(),
+            SessionPoolConfig {
+                max_size,
+                acquire_timeout: Duration::from_millis(50),
+                keep_alive: Duration::from_secs(60),
+                idle_timeout: Duration::from_secs(600),
+            },
+        );
+        (pool, factory)
+    }
+
+    async fn run(pool: &SessionPool<Arc<MockFactory>>, factory: &MockFactory, user: &str) -> usize {
+        pool.run(user, |session| async move { factory.query(&session) })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_user_affinity() {
+        let (pool, factory) = pool(4);
+        let factory = &*factory;
+
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+        assert_eq!(run(&pool, factory, "manager").await, 1);
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+        assert_eq!(run(&pool, factory, "manager").await, 1);
+        assert_eq!(factory.created.load(Ordering::Relaxed), 2);
+
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                max_size: 4,
+                size: 2,
+                available: 2,
+                waiting: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_queries() {
+        let (pool, factory) = pool(4);
+        let factory = &*factory;
+
+        let slow_query = |session: Arc<usize>| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            factory.query(&session)
+        };
+        let (first, second) =
+            tokio::join!(pool.run("admin", slow_query), pool.run("admin", slow_query));
+        assert_ne!(first.unwrap(), second.unwrap());
+        assert_eq!(pool.stats().available, 2);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout() {
+        let (pool, factory) = pool(1);
+        let factory = &*factory;
+
+        let busy = pool.acquire("admin").await.unwrap();
+        let result = pool
+            .run("admin", |session| async move { factory.query(&session) })
+            .await;
+        assert!(matches!(result, Err(DataStoreError::ConnectionError(_))));
+
+        drop(busy);
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_relogin_on_expiry() {
+        let (pool, factory) = pool(4);
+        let factory = &*factory;
+
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+        factory.expired.lock().unwrap().push(0);
+        assert_eq!(run(&pool, factory, "admin").await, 1);
+        assert_eq!(run(&pool, factory, "admin").await, 1);
+        assert_eq!(pool.stats().size, 1);
+    }
+
+    #[tokio::test]
+    async fn test_login_error() {
+        let (pool, factory) = pool(1);
+        let factory = &*factory;
+
+        factory.fail_login.store(true, Ordering::Relaxed);
+        let result = pool
+            .run("admin", |session| async move { factory.query(&session) })
+            .await;
+        assert!(matches!(result, Err(DataStoreError::ConnectionError(_))));
+
+        // The failed login doesn't hold on to the pool's only slot
+        factory.fail_login.store(false, Ordering::Relaxed);
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let (pool, factory) = pool(2);
+        let factory = &*factory;
+
+        assert_eq!(run(&pool, factory, "admin").await, 0);
+        assert_eq!(run(&pool, factory, "manager").await, 1);
+        assert_eq!(run(&pool, factory, "analyst").await, 2);
+
+        // The admin session was closed to make room for the analyst one
+        assert_eq!(pool.stats().size, 2);
+        assert_eq!(run(&pool, factory, "manager").await, 1);
+        assert_eq!(run(&pool, factory, "admin").await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let factory = Arc::new(MockFactory::new());
+        let pool = SessionPool::new(
+            factory.clone(),
+            SessionPoolConfig {
+                max_size: 4,
+                acquire_timeout: Duration::from_millis(50),
+                keep_alive: Duration::from_nanos(1),
+                idle_timeout: Duration::from_secs(600),
+            },
+        );
+        let factory = &*factory;
+        run(&pool, factory, "admin").await;
+        run(&pool, factory, "manager").await;
+
+        check_idle_sessions(&pool.inner).await;
+        assert_eq!(factory.heartbeats.load(Ordering::Relaxed), 2);
+        assert_eq!(pool.stats().size, 2);
+
+        // Sessions failing their heartbeat are closed
+        factory.expired.lock().unwrap().push(1);
+        check_idle_sessions(&pool.inner).await;
+        assert_eq!(pool.stats().size, 1);
+        assert_eq!(run(&pool, factory, "manager").await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let factory = Arc::new(MockFactory::new());
+        let pool = SessionPool::new(
+            factory.clone(),
+            SessionPoolConfig {
+                max_size: 4,
+                acquire_timeout: Duration::from_millis(50),
+                keep_alive: Duration::from_secs(60),
+                idle_timeout: Duration::from_nanos(1),
+            },
+        );
+        let factory = &*factory;
+        run(&pool, factory, "admin").await;
+
+        check_idle_sessions(&pool.inner).await;
+        assert_eq!(pool.stats().size, 0);
+        assert_eq!(factory.heartbeats.load(Ordering::Relaxed), 0);
+    }
+}
@@ -1,51 +1,78 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use super::encode_value;
+use super::text_format::{to_binary, to_text};
+use super::{encode_raw_value, encode_value, snowflake_functions, snowflake_syntax};
 use crate::config::SnowflakeConfig;
-use crate::data_store::{DataStoreClient, DataStoreError, DataStoreMapping};
+use crate::data_store::session_pool::{SessionFactory, SessionPool, SessionPoolConfig};
+use crate::data_store::{
+    DataStoreClient, DataStoreError, DataStoreMapping, FunctionMapping, Parameter, PoolStats,
+};
 use async_trait::async_trait;
 use bytes::BytesMut;
-use futures::Stream;
+use futures::{future, stream, StreamExt, TryStreamExt};
+use log::debug;
 use pgwire::api::portal::Format;
-use pgwire::api::results::FieldInfo;
+use pgwire::api::results::{FieldFormat, FieldInfo};
 use pgwire::api::results::{QueryResponse, Response};
-use pgwire::error::PgWireResult;
 use pgwire::messages::data::DataRow;
 use snowflake_connector_rs::SnowflakeRow;
 use snowflake_connector_rs::SnowflakeSession;
 use snowflake_connector_rs::{SnowflakeAuthMethod, SnowflakeClient, SnowflakeClientConfig};
-use tokio_postgres::types::Type;
+use sqlparser::ast::{Expr, Query, Value};
+use tokio_postgres::types::{Kind, Type};
 
 pub struct SnowflakeDataStore {
-    client: SnowflakeClient,
+    sessions: SessionPool<SnowflakeClient>,
 }
 
 pub struct SnowflakeMapping;
 
 impl SnowflakeDataStore {
+    /// Must be called from within a Tokio runtime, which keeps the pooled
+    /// sessions alive.
     pub fn new(config: SnowflakeConfig) -> Result<Self, DataStoreError> {
+        let client = SnowflakeClient::new(
+            &config.user,
+            SnowflakeAuthMethod::Password(config.password.clone()),
+            SnowflakeClientConfig {
+                account: config.account.clone(),
+                warehouse: config.warehouse,
+                database: config.database,
+                schema: config.schema,
+                role: config.role,
+                timeout: config.timeout.map(Duration::from_secs),
+            },
+        )
+        .map_err(|e| DataStoreError::ConnectionError(e.to_string()))?;
+
         Ok(SnowflakeDataStore {
-            client: SnowflakeClient::new(
-                &config.user,
-                SnowflakeAuthMethod::Password(config.password.clone()),
-                SnowflakeClientConfig {
-                    account: config.account.clone(),
-                    warehouse: config.warehouse,
-                    database: config.database,
-                    schema: config.schema,
-                    role: config.role,
-                    timeout: config.timeout.map(Duration::from_secs),
+            sessions: SessionPool::new(
+                client,
+                SessionPoolConfig {
+                    max_size: config.pool_max_size,
+                    acquire_timeout: Duration::from_secs(config.pool_acquire_timeout),
+                    keep_alive: Duration::from_secs(config.session_keep_alive),
+                    idle_timeout: Duration::from_secs(config.session_idle_timeout),
                 },
-            )
-            .map_err(|e| DataStoreError::ConnectionError(e.to_string()))?,
+            ),
         })
     }
+}
 
-    async fn connect(&self) -> Result<SnowflakeSession, DataStoreError> {
-        self.client.create_session().await.map_err(|e| {
-            DataStoreError::ConnectionError(format!("Failed to connect to Snowflake, {}", e))
-        })
+#[async_trait]
+impl SessionFactory for SnowflakeClient {
+    type Session = SnowflakeSession;
+
+    async fn create_session(&self) -> Result<SnowflakeSession, snowflake_connector_rs::Error> {
+        SnowflakeClient::create_session(self).await
+    }
+
+    async fn heartbeat(
+        &self,
+        session: &SnowflakeSession,
+    ) -> Result<(), snowflake_connector_rs::Error> {
+        session.query("SELECT 1").await.map(|_| ())
     }
 }
 
@@ -54,13 +81,30 @@ impl DataStoreMapping for SnowflakeMapping {
         &sqlparser::dialect::SnowflakeDialect {}
     }
 
-    fn map_function(&self, pg_function: &str) -> Option<String> {
-        match pg_function {
-            "now()" => Some("CURRENT_TIMESTAMP()".to_string()),
-            // Map other Postgres functions to Snowflake equivalents
-            _ => Some(pg_function.to_string()),
+    fn map_function(&self, name: &str, arity: usize) -> Option<FunctionMapping> {
+        snowflake_functions::map_function(name, arity)
+    }
+
+    fn map_expression(&self, expr: &Expr) -> Result<Option<Expr>, String> {
+        match snowflake_syntax::map_expression(expr)? {
+            Some(mapped) => Ok(Some(mapped)),
+            None => snowflake_functions::map_expression(expr),
         }
     }
+
+    fn map_query(&self, query: &mut Query) -> Result<(), String> {
+        snowflake_syntax::map_query(query)
+    }
+
+    fn map_type(&self, pg_type: &Type) -> Option<&'static str> {
+        map_type_from_pg(pg_type)
+    }
+
+    // Snowflake reads backslash escapes in single quoted strings, so quotes and
+    // backslashes are both escaped, which sqlparser then writes out as is
+    fn string_literal(&self, value: String) -> Value {
+        Value::SingleQuotedString(value.replace('\\', r"\\").replace('\'', r"\'"))
+    }
 }
 
 #[async_trait]
@@ -71,70 +115,682 @@ impl DataStoreClient for SnowflakeDataStore {
         SnowflakeMapping {}
     }
 
-    async fn execute(&self, query: &str) -> Result<Vec<Response>, DataStoreError> {
-        println!("Executing SQL: {}", query);
-        let session = self.connect().await?;
+    async fn describe(
+        &self,
+        user: &str,
+        query: &str,
+        _parameter_types: &[Type],
+    ) -> Result<Vec<FieldInfo>, DataStoreError> {
+        // Snowflake has no prepare step, so run the query without fetching any rows
+        let limited = format!("SELECT * FROM ({}) LIMIT 0", query);
+        let limited = limited.as_str();
+        let columns = self
+            .sessions
+            .run(user, |session| async move {
+                session.query(limited).await?;
+                describe_last_result(&session).await
+            })
+            .await?;
 
-        let rows = session
-            .query(query)
-            .await
-            .map_err(|e| DataStoreError::QueryError(e.to_string()))?;
+        Ok(row_desc_from_stmt(&columns, &Format::UnifiedText))
+    }
 
-        let field_info = row_desc_from_stmt(&rows, &Format::UnifiedText)
-            .map_err(|e| DataStoreError::ColumnNotFound(e.to_string()))?;
-        let field_info_arc = Arc::new(field_info);
-        let data_rows = encode_row_data(rows, field_info_arc.clone());
-        Ok(vec![Response::Query(QueryResponse::new(
+    async fn execute_streaming(
+        &self,
+        user: &str,
+        query: &str,
+        _parameters: &[Parameter],
+        format: &Format,
+    ) -> Result<Response<'static>, DataStoreError> {
+        debug!("Executing SQL: {}", query);
+        let (executor, first_chunk, columns) = self
+            .sessions
+            .run(user, |session| async move {
+                let mut executor = session.execute(query).await?;
+
+                // The column types come with the rows, so wait for the first
+                // chunk. Large results may leave the response itself empty
+                let mut first_chunk = Vec::new();
+                while first_chunk.is_empty() {
+                    match executor.fetch_next_chunk().await? {
+                        Some(rows) => first_chunk = rows,
+                        None => break,
+                    }
+                }
+                let columns = match first_chunk.is_empty() {
+                    true => describe_last_result(&session).await?,
+                    false => result_columns(&first_chunk),
+                };
+                Ok((executor, first_chunk, columns))
+            })
+            .await?;
+        let field_info_arc = Arc::new(row_desc_from_stmt(&columns, format));
+        let schema = field_info_arc.clone();
+
+        // Download the remaining chunks one at a time, as the client reads them
+        let next_chunks = stream::unfold(Some(executor), |executor| async move {
+            let mut executor = executor?;
+            match executor.fetch_next_chunk().await {
+                Ok(Some(rows)) => Some((Ok(rows), Some(executor))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+        let data_rows = stream::once(future::ready(Ok(first_chunk)))
+            .chain(next_chunks)
+            .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+            .try_flatten()
+            .map(move |row| {
+                let row = row.map_err(|e: snowflake_connector_rs::Error| {
+                    DataStoreError::QueryError(e.to_string())
+                })?;
+                Ok(encode_row(&row, &columns, &schema)?)
+            });
+        Ok(Response::Query(QueryResponse::new(
             field_info_arc,
             Box::pin(data_rows),
-        ))])
+        )))
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(self.sessions.stats())
     }
 }
 
-pub fn row_desc_from_stmt(rows: &[SnowflakeRow], format: &Format) -> PgWireResult<Vec<FieldInfo>> {
-    if rows.is_empty() {
-        return Ok(Vec::new());
+/// A result column as described by Snowflake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultColumn {
+    pub name: String,
+    pub snowflake_type: String,
+    pub precision: Option<i64>,
+    pub scale: Option<i64>,
+}
+
+impl ResultColumn {
+    pub fn pg_type(&self) -> Type {
+        map_type_to_pg(&self.snowflake_type, self.precision, self.scale)
     }
+}
 
-    let column_types = rows[0].column_types();
-    column_types
+/// The result columns, described in every row Snowflake returns.
+pub fn result_columns(rows: &[SnowflakeRow]) -> Vec<ResultColumn> {
+    let Some(row) = rows.first() else {
+        return Vec::new();
+    };
+    row.column_types()
         .into_iter()
-        .map(|col| {
-            let field_type = map_type_to_pg(col.column_type().snowflake_type());
-            Ok(FieldInfo::new(
-                col.name().to_owned(),
+        .map(|col| ResultColumn {
+            name: col.name().to_owned(),
+            snowflake_type: col.column_type().snowflake_type().to_owned(),
+            precision: col.column_type().precision(),
+            scale: col.column_type().scale(),
+        })
+        .collect()
+}
+
+/// Describes the result of the last query the session ran, for results
+/// without any row to read the column types from.
+async fn describe_last_result(
+    session: &SnowflakeSession,
+) -> Result<Vec<ResultColumn>, snowflake_connector_rs::Error> {
+    let rows = session.query("DESCRIBE RESULT LAST_QUERY_ID()").await?;
+    rows.iter()
+        .map(|row| {
+            Ok(described_column(
+                row.get("name")?,
+                &row.get::<String>("type")?,
+            ))
+        })
+        .collect()
+}
+
+/// Reads a column type as `DESCRIBE` prints it, e.g. `NUMBER(38,2)` or
+/// `TIMESTAMP_TZ(9)`.
+fn described_column(name: String, described_type: &str) -> ResultColumn {
+    let (snowflake_type, arguments) = match described_type.split_once('(') {
+        Some((snowflake_type, arguments)) => (snowflake_type, arguments.trim_end_matches(')')),
+        None => (described_type, ""),
+    };
+    let mut arguments = arguments.split(',').map(|a| a.trim().parse::<i64>().ok());
+    let (precision, scale) = match snowflake_type {
+        "NUMBER" | "DECIMAL" | "NUMERIC" => {
+            (arguments.next().flatten(), arguments.next().flatten())
+        }
+        // The fractional seconds precision, as result metadata gives it
+        "TIME" | "TIMESTAMP_NTZ" | "TIMESTAMP_LTZ" | "TIMESTAMP_TZ" => {
+            (None, arguments.next().flatten())
+        }
+        _ => (None, None),
+    };
+    ResultColumn {
+        name,
+        snowflake_type: snowflake_type.to_string(),
+        precision,
+        scale,
+    }
+}
+
+pub fn row_desc_from_stmt(columns: &[ResultColumn], format: &Format) -> Vec<FieldInfo> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| {
+            FieldInfo::new(
+                column.name.clone(),
                 None,
                 None,
-                field_type,
-                format.format_for(col.index()),
-            ))
+                column.pg_type(),
+                format.format_for(idx),
+            )
         })
         .collect()
 }
 
-pub fn encode_row_data(
-    rows: Vec<SnowflakeRow>,
-    schema: Arc<Vec<FieldInfo>>,
-) -> impl Stream<Item = PgWireResult<DataRow>> {
-    futures::stream::iter(rows.into_iter().map(move |row| {
-        let mut buffer = BytesMut::new();
-        for field in schema.iter() {
-            let value = row.get::<String>(field.name()).ok();
-            encode_value(&mut buffer, value);
+/// Encodes the row in the format of each field.
+///
+/// Values are converted into the Postgres binary format first, which is then
+/// formatted as text for text fields the same way Postgres rows are.
+pub fn encode_row(
+    row: &SnowflakeRow,
+    columns: &[ResultColumn],
+    schema: &[FieldInfo],
+) -> Result<DataRow, DataStoreError> {
+    let mut buffer = BytesMut::new();
+    for (idx, (column, field)) in columns.iter().zip(schema).enumerate() {
+        let value = row
+            .at::<Option<String>>(idx)
+            .map_err(|e| DataStoreError::QueryError(e.to_string()))?;
+        let raw = value.map(|v| to_pg_binary(column, &v)).transpose()?;
+        match (raw, field.format()) {
+            (Some(raw), FieldFormat::Text) => {
+                encode_value(&mut buffer, Some(to_text(field.datatype(), &raw)?))
+            }
+            (raw, _) => encode_raw_value(&mut buffer, raw.as_deref()),
+        }
+    }
+    Ok(DataRow::new(buffer, schema.len() as i16))
+}
+
+/// The Snowflake type each Postgres type is cast to.
+const CAST_TYPES: &[(Type, &str)] = &[
+    (Type::NUMERIC, "NUMBER"),
+    (Type::INT8, "BIGINT"),
+    (Type::INT4, "INT"),
+    (Type::INT2, "SMALLINT"),
+    (Type::FLOAT8, "DOUBLE"),
+    (Type::FLOAT4, "FLOAT"),
+    (Type::TEXT, "VARCHAR"),
+    (Type::VARCHAR, "VARCHAR"),
+    (Type::BPCHAR, "CHAR"),
+    (Type::NAME, "VARCHAR"),
+    (Type::UUID, "VARCHAR"),
+    (Type::BOOL, "BOOLEAN"),
+    (Type::DATE, "DATE"),
+    (Type::TIME, "TIME"),
+    (Type::TIMESTAMP, "TIMESTAMP_NTZ"),
+    (Type::TIMESTAMPTZ, "TIMESTAMP_TZ"),
+    (Type::BYTEA, "BINARY"),
+    (Type::JSONB, "VARIANT"),
+    (Type::JSON, "VARIANT"),
+];
+
+/// The Postgres type results of each Snowflake type other than numbers are
+/// encoded as. Snowflake integer types are all `NUMBER(38, 0)` and reported as
+/// such, so results cast to an integer are read back as `numeric`, see
+/// [`map_type_to_pg`].
+const RESULT_TYPES: &[(&str, Type)] = &[
+    ("DOUBLE", Type::FLOAT8),
+    ("FLOAT", Type::FLOAT8),
+    ("REAL", Type::FLOAT8),
+    ("FLOAT4", Type::FLOAT8),
+    ("FLOAT8", Type::FLOAT8),
+    ("DOUBLE PRECISION", Type::FLOAT8),
+    ("VARCHAR", Type::TEXT),
+    ("TEXT", Type::TEXT),
+    ("CHAR", Type::TEXT),
+    ("CHARACTER", Type::TEXT),
+    ("STRING", Type::TEXT),
+    ("BOOLEAN", Type::BOOL),
+    ("DATE", Type::DATE),
+    ("TIME", Type::TIME),
+    ("TIMESTAMP_NTZ", Type::TIMESTAMP),
+    ("TIMESTAMP", Type::TIMESTAMP),
+    ("DATETIME", Type::TIMESTAMP),
+    ("TIMESTAMP_TZ", Type::TIMESTAMPTZ),
+    ("TIMESTAMP_LTZ", Type::TIMESTAMPTZ),
+    ("BINARY", Type::BYTEA),
+    ("VARBINARY", Type::BYTEA),
+    ("VARIANT", Type::JSONB),
+    ("OBJECT", Type::JSONB),
+    ("ARRAY", Type::JSONB),
+];
+
+/// The Snowflake type a Postgres type is cast to, `ARRAY` for any array.
+fn map_type_from_pg(pg_type: &Type) -> Option<&'static str> {
+    if let Kind::Array(_) = pg_type.kind() {
+        return Some("ARRAY");
+    }
+    CAST_TYPES
+        .iter()
+        .find(|(pg, _)| pg == pg_type)
+        .map(|(_, snowflake_type)| *snowflake_type)
+}
+
+/// Maps the type names found in result metadata, e.g. `fixed`, and in
+/// `DESCRIBE` output, e.g. `NUMBER`, to the closest Postgres type.
+///
+/// Numbers without a scale fitting in 18 digits are `int8`, any other number
+/// is `numeric` so no digit is lost.
+fn map_type_to_pg(snowflake_type: &str, precision: Option<i64>, scale: Option<i64>) -> Type {
+    let snowflake_type = snowflake_type.to_uppercase();
+    match snowflake_type.as_str() {
+        "FIXED" | "NUMBER" | "DECIMAL" | "NUMERIC" => match (precision, scale) {
+            (Some(precision), Some(0) | None) if precision <= 18 => Type::INT8,
+            _ => Type::NUMERIC,
+        },
+        snowflake_type => RESULT_TYPES
+            .iter()
+            .find(|(name, _)| *name == snowflake_type)
+            .map(|(_, pg_type)| pg_type.clone())
+            // Geospatial and vector values are passed on as Snowflake prints them
+            .unwrap_or(Type::TEXT),
+    }
+}
+
+/// Seconds between 1970-01-01 and the Postgres epoch, 2000-01-01.
+const POSTGRES_EPOCH_SECONDS: i64 = 946_684_800;
+
+/// Converts a value as Snowflake sends it in JSON results into the Postgres
+/// binary format of the column's type.
+///
+/// Dates are sent as days and times as seconds, timestamps as seconds since
+/// 1970-01-01 UTC, followed for `TIMESTAMP_TZ` by the time zone offset the
+/// value was written with, which Postgres does not keep. Binary values are
+/// hexadecimal.
+fn to_pg_binary(column: &ResultColumn, value: &str) -> Result<Vec<u8>, DataStoreError> {
+    let invalid = || {
+        DataStoreError::QueryError(format!(
+            "Invalid {} value from Snowflake: \"{}\"",
+            column.snowflake_type, value
+        ))
+    };
+    let pg_type = column.pg_type();
+    match pg_type {
+        Type::BOOL => match value {
+            "1" | "true" | "TRUE" => Ok(vec![1]),
+            "0" | "false" | "FALSE" => Ok(vec![0]),
+            _ => Err(invalid()),
+        },
+        Type::DATE => {
+            let days = value.parse::<i64>().map_err(|_| invalid())?;
+            let days =
+                i32::try_from(days - POSTGRES_EPOCH_SECONDS / 86_400).map_err(|_| invalid())?;
+            Ok(days.to_be_bytes().to_vec())
+        }
+        Type::TIME => Ok(parse_micros(value)
+            .ok_or_else(invalid)?
+            .to_be_bytes()
+            .to_vec()),
+        Type::TIMESTAMP | Type::TIMESTAMPTZ => {
+            let seconds = value.split_whitespace().next().unwrap_or_default();
+            let micros = parse_micros(seconds).ok_or_else(invalid)?;
+            let micros = micros
+                .checked_sub(POSTGRES_EPOCH_SECONDS * 1_000_000)
+                .ok_or_else(invalid)?;
+            Ok(micros.to_be_bytes().to_vec())
+        }
+        Type::BYTEA => to_binary(&pg_type, &format!("\\x{}", value)).map_err(|_| invalid()),
+        Type::JSONB => {
+            let json = serde_json::from_str(value).map_err(|_| invalid())?;
+            let mut text = String::new();
+            write_jsonb(&json, &mut text);
+            to_binary(&pg_type, &text)
+        }
+        _ => to_binary(&pg_type, value).map_err(|_| invalid()),
+    }
+}
+
+/// Parses seconds with up to nine fractional digits into microseconds,
+/// rounding to the nearest microsecond like Postgres does.
+fn parse_micros(seconds: &str) -> Option<i64> {
+    let (negative, unsigned) = match seconds.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, seconds),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{:0<9}", fraction).parse::<i64>().ok()?;
+    let micros = whole
+        .parse::<i64>()
+        .ok()?
+        .checked_mul(1_000_000)?
+        .checked_add((nanos + 500) / 1000)?;
+    Some(if negative { -micros } else { micros })
+}
+
+/// Writes JSON the way Postgres prints `jsonb`, with a space after commas and
+/// colons, and object keys ordered by length then bytes.
+fn write_jsonb(value: &serde_json::Value, text: &mut String) {
+    match value {
+        serde_json::Value::Array(values) => {
+            text.push('[');
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    text.push_str(", ");
+                }
+                write_jsonb(value, text);
+            }
+            text.push(']');
+        }
+        serde_json::Value::Object(entries) => {
+            let mut entries = entries.iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            text.push('{');
+            for (idx, (key, value)) in entries.into_iter().enumerate() {
+                if idx > 0 {
+                    text.push_str(", ");
+                }
+                text.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                text.push_str(": ");
+                write_jsonb(value, text);
+            }
+            text.push('}');
+        }
+        value => text.push_str(&value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::semantic_model::local_store::LocalSemanticModelStore;
+    use crate::sql_parser::SqlParser;
+    use rstest::rstest;
+    use std::collections::HashSet;
+
+    // Result metadata and rows as Snowflake returns them in JSON results
+    const FIXTURE: &str = r#"{
+        "rowtype": [
+            {"name": "ID", "type": "fixed", "precision": 18, "scale": 0},
+            {"name": "AMOUNT", "type": "fixed", "precision": 38, "scale": 2},
+            {"name": "RATIO", "type": "real", "precision": null, "scale": null},
+            {"name": "NAME", "type": "text", "precision": null, "scale": null},
+            {"name": "FLAG", "type": "boolean", "precision": null, "scale": null},
+            {"name": "D", "type": "date", "precision": null, "scale": null},
+            {"name": "T", "type": "time", "precision": 0, "scale": 9},
+            {"name": "TS", "type": "timestamp_ntz", "precision": 0, "scale": 9},
+            {"name": "TS_LTZ", "type": "timestamp_ltz", "precision": 0, "scale": 9},
+            {"name": "TS_TZ", "type": "timestamp_tz", "precision": 0, "scale": 9},
+            {"name": "BYTES", "type": "binary", "precision": null, "scale": null},
+            {"name": "DOC", "type": "variant", "precision": null, "scale": null}
+        ],
+        "rowset": [
+            [
+                "1", "1234.50", "1.5", "héllo", "1", "19782", "49507.250000000",
+                "1717230600.000000000", "1717223400.500000000", "1717230600.000000000 1560",
+                "DEADBEEF", "{\n  \"b\": [true, null],\n  \"a\": 1\n}"
+            ],
+            [
+                "-9007199254740993", "123456789012345678901234567890123456.78", "-inf", "",
+                "0", "-1", "0.000000500", "-0.500000000", "0.000000000", "0.000000000 1440",
+                "", "[\n  \"x\",\n  {\n    \"bb\": 2,\n    \"c\": \"y\\\"z\"\n  }\n]"
+            ],
+            [null, null, null, null, null, null, null, null, null, null, null, null]
+        ]
+    }"#;
+
+    #[test]
+    fn test_fixture_rows() {
+        let fixture: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        let columns = fixture["rowtype"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|column| ResultColumn {
+                name: column["name"].as_str().unwrap().to_string(),
+                snowflake_type: column["type"].as_str().unwrap().to_string(),
+                precision: column["precision"].as_i64(),
+                scale: column["scale"].as_i64(),
+            })
+            .collect::<Vec<_>>();
+
+        let types = columns
+            .iter()
+            .map(ResultColumn::pg_type)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                Type::INT8,
+                Type::NUMERIC,
+                Type::FLOAT8,
+                Type::TEXT,
+                Type::BOOL,
+                Type::DATE,
+                Type::TIME,
+                Type::TIMESTAMP,
+                Type::TIMESTAMPTZ,
+                Type::TIMESTAMPTZ,
+                Type::BYTEA,
+                Type::JSONB,
+            ]
+        );
+
+        let rows = fixture["rowset"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                row.as_array()
+                    .unwrap()
+                    .iter()
+                    .zip(&columns)
+                    .map(|(value, column)| {
+                        value.as_str().map(|value| {
+                            let raw = to_pg_binary(column, value).unwrap();
+                            to_text(&column.pg_type(), &raw).unwrap()
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let expected = [
+            vec![
+                "1",
+                "1234.50",
+                "1.5",
+                "héllo",
+                "t",
+                "2024-02-29",
+                "13:45:07.25",
+                "2024-06-01 08:30:00",
+                "2024-06-01 06:30:00.5+00",
+                "2024-06-01 08:30:00+00",
+                "\\xdeadbeef",
+                r#"{"a": 1, "b": [true, null]}"#,
+            ],
+            vec![
+                "-9007199254740993",
+                "123456789012345678901234567890123456.78",
+                "-Infinity",
+                "",
+                "f",
+                "1969-12-31",
+                "00:00:00.000001",
+                "1969-12-31 23:59:59.5",
+                "1970-01-01 00:00:00+00",
+                "1970-01-01 00:00:00+00",
+                "\\x",
+                r#"["x", {"c": "y\"z", "bb": 2}]"#,
+            ],
+        ];
+        for (row, expected) in rows.iter().zip(expected) {
+            let expected = expected.into_iter().map(|v| Some(v.to_string()));
+            assert_eq!(row, &expected.collect::<Vec<_>>());
+        }
+        assert!(rows[2].iter().all(Option::is_none));
+    }
+
+    #[rstest]
+    #[case::integer("fixed", Some(18), Some(0), Type::INT8)]
+    #[case::wide_integer("fixed", Some(38), Some(0), Type::NUMERIC)]
+    #[case::decimal("fixed", Some(38), Some(2), Type::NUMERIC)]
+    #[case::described_decimal("NUMBER", Some(10), Some(2), Type::NUMERIC)]
+    #[case::unknown_precision("fixed", None, None, Type::NUMERIC)]
+    #[case::float("real", None, None, Type::FLOAT8)]
+    #[case::time("time", None, Some(9), Type::TIME)]
+    #[case::timestamp_tz("timestamp_tz", None, Some(9), Type::TIMESTAMPTZ)]
+    #[case::binary("binary", None, None, Type::BYTEA)]
+    #[case::object("object", None, None, Type::JSONB)]
+    #[case::geography("geography", None, None, Type::TEXT)]
+    #[case::described_integer("NUMBER", Some(38), Some(0), Type::NUMERIC)]
+    #[case::double("DOUBLE", None, None, Type::FLOAT8)]
+    #[case::varchar("VARCHAR", None, None, Type::TEXT)]
+    #[case::timestamp_ntz("TIMESTAMP_NTZ", None, Some(9), Type::TIMESTAMP)]
+    #[case::variant("variant", None, None, Type::JSONB)]
+    fn test_map_type_to_pg(
+        #[case] snowflake_type: &str,
+        #[case] precision: Option<i64>,
+        #[case] scale: Option<i64>,
+        #[case] expected: Type,
+    ) {
+        assert_eq!(map_type_to_pg(snowflake_type, precision, scale), expected);
+    }
+
+    #[test]
+    fn test_type_tables_have_unique_keys() {
+        let pg_types: HashSet<_> = CAST_TYPES.iter().map(|(pg, _)| pg).collect();
+        assert_eq!(pg_types.len(), CAST_TYPES.len());
+        let names: HashSet<_> = RESULT_TYPES.iter().map(|(name, _)| name).collect();
+        assert_eq!(names.len(), RESULT_TYPES.len());
+    }
+
+    // Each Postgres type, the Snowflake type it is cast to, and the result
+    // metadata Snowflake returns for the cast
+    #[rstest]
+    #[case::numeric(Type::NUMERIC, "NUMBER", ("fixed", Some(38), Some(0)), Type::NUMERIC)]
+    #[case::int8(Type::INT8, "BIGINT", ("fixed", Some(38), Some(0)), Type::NUMERIC)]
+    #[case::int4(Type::INT4, "INT", ("fixed", Some(38), Some(0)), Type::NUMERIC)]
+    #[case::int2(Type::INT2, "SMALLINT", ("fixed", Some(38), Some(0)), Type::NUMERIC)]
+    #[case::float8(Type::FLOAT8, "DOUBLE", ("real", None, None), Type::FLOAT8)]
+    #[case::float4(Type::FLOAT4, "FLOAT", ("real", None, None), Type::FLOAT8)]
+    #[case::text(Type::TEXT, "VARCHAR", ("text", None, None), Type::TEXT)]
+    #[case::varchar(Type::VARCHAR, "VARCHAR", ("text", None, None), Type::TEXT)]
+    #[case::bpchar(Type::BPCHAR, "CHAR", ("text", None, None), Type::TEXT)]
+    #[case::name(Type::NAME, "VARCHAR", ("text", None, None), Type::TEXT)]
+    #[case::uuid(Type::UUID, "VARCHAR", ("text", None, None), Type::TEXT)]
+    #[case::bool(Type::BOOL, "BOOLEAN", ("boolean", None, None), Type::BOOL)]
+    #[case::date(Type::DATE, "DATE", ("date", None, None), Type::DATE)]
+    #[case::time(Type::TIME, "TIME", ("time", Some(0), Some(9)), Type::TIME)]
+    #[case::timestamp(Type::TIMESTAMP, "TIMESTAMP_NTZ", ("timestamp_ntz", Some(0), Some(9)), Type::TIMESTAMP)]
+    #[case::timestamptz(Type::TIMESTAMPTZ, "TIMESTAMP_TZ", ("timestamp_tz", Some(0), Some(9)), Type::TIMESTAMPTZ)]
+    #[case::bytea(Type::BYTEA, "BINARY", ("binary", None, None), Type::BYTEA)]
+    #[case::jsonb(Type::JSONB, "VARIANT", ("variant", None, None), Type::JSONB)]
+    #[case::json(Type::JSON, "VARIANT", ("variant", None, None), Type::JSONB)]
+    #[case::array(Type::INT4_ARRAY, "ARRAY", ("array", None, None), Type::JSONB)]
+    fn test_map_type_round_trip(
+        #[case] pg_type: Type,
+        #[case] snowflake_type: &str,
+        #[case] metadata: (&str, Option<i64>, Option<i64>),
+        #[case] read_back: Type,
+    ) {
+        assert_eq!(map_type_from_pg(&pg_type), Some(snowflake_type));
+        let (result_type, precision, scale) = metadata;
+        assert_eq!(map_type_to_pg(result_type, precision, scale), read_back);
+    }
+
+    #[rstest]
+    #[case::text("CAST(x AS text)", "CAST(x AS VARCHAR)")]
+    #[case::int4("x::int4", "x::INT")]
+    #[case::int8("x::int8", "x::BIGINT")]
+    #[case::smallint("x::smallint", "x::SMALLINT")]
+    #[case::serial("x::serial", "x::INT")]
+    #[case::bigserial("x::bigserial", "x::BIGINT")]
+    #[case::numeric("x::numeric(10, 2)", "x::NUMBER(10, 2)")]
+    #[case::real("x::real", "x::FLOAT")]
+    #[case::double("x::double precision", "x::DOUBLE")]
+    #[case::bool("x::bool", "x::BOOLEAN")]
+    #[case::varchar("x::varchar(10)", "x::VARCHAR(10)")]
+    #[case::char("x::char(2)", "x::CHAR(2)")]
+    #[case::timestamp("x::timestamp", "x::TIMESTAMP_NTZ")]
+    #[case::timestamptz("x::timestamptz", "x::TIMESTAMP_TZ")]
+    #[case::timestamptz_precision("x::timestamp(3) with time zone", "x::TIMESTAMP_TZ(3)")]
+    #[case::bytea("x::bytea", "x::BINARY")]
+    #[case::json("x::json", "x::VARIANT")]
+    #[case::jsonb("x::jsonb", "x::VARIANT")]
+    #[case::uuid("x::uuid", "x::VARCHAR")]
+    #[case::array("x::int4[]", "x::ARRAY")]
+    #[case::date("x::date", "x::DATE")]
+    #[case::unmapped("x::regclass", "x::REGCLASS")]
+    #[case::nested("CAST(x::int4 AS text)", "CAST(x::INT AS VARCHAR)")]
+    fn test_cast_translation(#[case] postgres: &str, #[case] snowflake: &str) {
+        let sql_parser = SqlParser::new(SnowflakeMapping {}, LocalSemanticModelStore::mock());
+        let sql = format!("SELECT {} AS value FROM dm_employees", postgres);
+        let expected = format!("SELECT {} AS value FROM dm_employees", snowflake);
+        assert_eq!(sql_parser.transform(&sql), Ok(vec![expected]));
+    }
+
+    #[rstest]
+    #[case::plain("name", "'name'")]
+    #[case::quote("it's", r"'it\'s'")]
+    #[case::doubled_quotes("a''b", r"'a\'\'b'")]
+    #[case::escaped_quote(r"\'; DROP TABLE t --", r"'\\\'; DROP TABLE t --'")]
+    #[case::trailing_backslash(r"a\", r"'a\\'")]
+    fn test_string_literal(#[case] value: &str, #[case] expected: &str) {
+        let literal = SnowflakeMapping {}.string_literal(value.to_string());
+        assert_eq!(literal.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case::integer("NUMBER(18,0)", "NUMBER", Some(18), Some(0), Type::INT8)]
+    #[case::decimal("NUMBER(38,2)", "NUMBER", Some(38), Some(2), Type::NUMERIC)]
+    #[case::varchar("VARCHAR(16777216)", "VARCHAR", None, None, Type::TEXT)]
+    #[case::float("FLOAT", "FLOAT", None, None, Type::FLOAT8)]
+    #[case::timestamp_ltz("TIMESTAMP_LTZ(9)", "TIMESTAMP_LTZ", None, Some(9), Type::TIMESTAMPTZ)]
+    #[case::time("TIME(3)", "TIME", None, Some(3), Type::TIME)]
+    #[case::binary("BINARY(8388608)", "BINARY", None, None, Type::BYTEA)]
+    fn test_described_column(
+        #[case] described_type: &str,
+        #[case] snowflake_type: &str,
+        #[case] precision: Option<i64>,
+        #[case] scale: Option<i64>,
+        #[case] pg_type: Type,
+    ) {
+        let column = described_column("C".to_string(), described_type);
+        assert_eq!(
+            column,
+            ResultColumn {
+                name: "C".to_string(),
+                snowflake_type: snowflake_type.to_string(),
+                precision,
+                scale,
+            }
+        );
+        assert_eq!(column.pg_type(), pg_type);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let column = |snowflake_type: &str| ResultColumn {
+            name: "C".to_string(),
+            snowflake_type: snowflake_type.to_string(),
+            precision: Some(18),
+            scale: Some(0),
+        };
+        for (snowflake_type, value) in [
+            ("fixed", "1.5"),
+            ("date", "2024-01-01"),
+            ("timestamp_ntz", "1.0000000001"),
+            ("binary", "XY"),
+            ("variant", "{"),
+        ] {
+            assert!(matches!(
+                to_pg_binary(&column(snowflake_type), value),
+                Err(DataStoreError::QueryError(_))
+            ));
         }
-        Ok(DataRow::new(buffer, schema.len() as i16))
-    }))
-}
-
-fn map_type_to_pg(snowflake_type: &str) -> Type {
-    match snowflake_type.to_uppercase().as_str() {
-        "NUMBER" | "INT" | "INTEGER" => Type::INT8,
-        "FLOAT" => Type::FLOAT8,
-        "VARCHAR" | "TEXT" | "STRING" => Type::TEXT,
-        "BOOLEAN" => Type::BOOL,
-        "DATE" => Type::DATE,
-        "TIMESTAMP" | "TIMESTAMP_NTZ" => Type::TIMESTAMP,
-        "VARIANT" | "OBJECT" | "ARRAY" => Type::JSONB,
-        _ => Type::UNKNOWN,
     }
 }
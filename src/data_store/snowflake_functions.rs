@@ -0,0 +1,697 @@
+//! Translation of Postgres functions to their Snowflake equivalents.
+//!
+//! Functions are listed in [`FUNCTIONS`] by name and number of arguments.
+//! Functions Snowflake spells the same and runs with the same semantics, such
+//! as `coalesce` or `upper`, are not listed and pass through as is.
+use super::FunctionMapping::{self, Rename, Rewrite, RewriteCall, Unsupported};
+use sqlparser::ast::*;
+
+/// Postgres functions by lowercase name and number of arguments, `None` matching
+/// any number.
+const FUNCTIONS: &[(&str, Option<usize>, FunctionMapping)] = &[
+    // Date and time
+    ("now", Some(0), Rename("CURRENT_TIMESTAMP")),
+    (
+        "transaction_timestamp",
+        Some(0),
+        Rename("CURRENT_TIMESTAMP"),
+    ),
+    ("statement_timestamp", Some(0), Rename("CURRENT_TIMESTAMP")),
+    ("clock_timestamp", Some(0), Rename("CURRENT_TIMESTAMP")),
+    ("date_trunc", Some(2), Rename("DATE_TRUNC")),
+    ("date_part", Some(2), Rewrite(date_part)),
+    ("make_date", Some(3), Rename("DATE_FROM_PARTS")),
+    ("timezone", Some(2), Rename("CONVERT_TIMEZONE")),
+    ("to_char", Some(2), Rewrite(to_char)),
+    ("to_date", Some(2), Rewrite(to_date)),
+    ("to_timestamp", Some(2), Rewrite(to_timestamp)),
+    (
+        "age",
+        None,
+        Unsupported("as it returns an interval, use DATEDIFF instead"),
+    ),
+    // Strings
+    ("btrim", None, Rename("TRIM")),
+    ("char_length", Some(1), Rename("LENGTH")),
+    ("character_length", Some(1), Rename("LENGTH")),
+    ("strpos", Some(2), Rewrite(strpos)),
+    ("concat", None, Rewrite(concat)),
+    ("concat_ws", None, Rewrite(concat_ws)),
+    ("split_part", Some(3), Rename("SPLIT_PART")),
+    ("string_to_array", Some(2), Rename("SPLIT")),
+    ("regexp_replace", Some(3), Rewrite(regexp_replace)),
+    ("regexp_replace", Some(4), Rewrite(regexp_replace)),
+    (
+        "regexp_matches",
+        None,
+        Unsupported("as it returns a set of rows, use REGEXP_SUBSTR_ALL instead"),
+    ),
+    ("encode", Some(2), Rewrite(encode)),
+    ("decode", Some(2), Rewrite(decode)),
+    ("sha256", Some(1), Rewrite(sha256)),
+    ("gen_random_uuid", Some(0), Rename("UUID_STRING")),
+    // Math
+    ("random", Some(0), Rewrite(random)),
+    ("log", Some(1), Rewrite(log10)),
+    ("ceiling", Some(1), Rename("CEIL")),
+    ("div", Some(2), Rewrite(div)),
+    // Postgres ignores NULL arguments, Snowflake's GREATEST and LEAST return NULL
+    ("greatest", None, Rename("GREATEST_IGNORE_NULLS")),
+    ("least", None, Rename("LEAST_IGNORE_NULLS")),
+    // Aggregates
+    ("string_agg", Some(2), RewriteCall(string_agg)),
+    ("array_agg", Some(1), RewriteCall(array_agg)),
+    ("json_agg", Some(1), RewriteCall(array_agg)),
+    ("jsonb_agg", Some(1), RewriteCall(array_agg)),
+    ("json_object_agg", Some(2), Rename("OBJECT_AGG")),
+    ("jsonb_object_agg", Some(2), Rename("OBJECT_AGG")),
+    ("bool_and", Some(1), Rename("BOOLAND_AGG")),
+    ("every", Some(1), Rename("BOOLAND_AGG")),
+    ("bool_or", Some(1), Rename("BOOLOR_AGG")),
+    ("bit_and", Some(1), Rename("BITAND_AGG")),
+    ("bit_or", Some(1), Rename("BITOR_AGG")),
+    // JSON and arrays
+    (
+        "json_build_object",
+        None,
+        Rename("OBJECT_CONSTRUCT_KEEP_NULL"),
+    ),
+    (
+        "jsonb_build_object",
+        None,
+        Rename("OBJECT_CONSTRUCT_KEEP_NULL"),
+    ),
+    ("json_build_array", None, Rename("ARRAY_CONSTRUCT")),
+    ("jsonb_build_array", None, Rename("ARRAY_CONSTRUCT")),
+    ("to_json", Some(1), Rename("TO_VARIANT")),
+    ("to_jsonb", Some(1), Rename("TO_VARIANT")),
+    ("json_array_length", Some(1), Rename("ARRAY_SIZE")),
+    ("jsonb_array_length", Some(1), Rename("ARRAY_SIZE")),
+    ("cardinality", Some(1), Rename("ARRAY_SIZE")),
+    ("array_length", Some(2), Rewrite(array_length)),
+    ("array_position", Some(2), Rewrite(array_position)),
+    // Set returning functions
+    (
+        "generate_series",
+        None,
+        Unsupported("as it returns a set of rows, use TABLE(GENERATOR(ROWCOUNT => n)) instead"),
+    ),
+    (
+        "unnest",
+        None,
+        Unsupported("as it returns a set of rows, use LATERAL FLATTEN instead"),
+    ),
+];
+
+/// Looks up the Snowflake translation of a Postgres function.
+pub fn map_function(name: &str, arity: usize) -> Option<FunctionMapping> {
+    FUNCTIONS
+        .iter()
+        .find(|(function, args, _)| *function == name && args.is_none_or(|args| args == arity))
+        .map(|(_, _, mapping)| *mapping)
+}
+
+/// Translates `EXTRACT` fields Snowflake names differently.
+pub fn map_expression(expr: &Expr) -> Result<Option<Expr>, String> {
+    let Expr::Extract {
+        field,
+        syntax,
+        expr,
+    } = expr
+    else {
+        return Ok(None);
+    };
+    let field = match field {
+        DateTimeField::Epoch => return Ok(Some(epoch(*expr.clone()))),
+        DateTimeField::Dow => "dayofweek",
+        DateTimeField::Doy => "dayofyear",
+        DateTimeField::Isodow => "dayofweekiso",
+        DateTimeField::Isoyear => "yearofweekiso",
+        DateTimeField::Week(None) => "weekiso",
+        _ => return Ok(None),
+    };
+    Ok(Some(Expr::Extract {
+        field: DateTimeField::Custom(Ident::new(field)),
+        syntax: syntax.clone(),
+        expr: expr.clone(),
+    }))
+}
+
+/// Seconds since the epoch with their fraction, as Postgres returns them.
+fn epoch(expr: Expr) -> Expr {
+    Expr::BinaryOp {
+        left: Box::new(call(
+            "DATE_PART",
+            vec![identifier("epoch_microsecond"), expr],
+        )),
+        op: BinaryOperator::Divide,
+        right: Box::new(number("1000000")),
+    }
+}
+
+fn date_part(args: Vec<Expr>) -> Result<Expr, String> {
+    let [field, expr] = args_array(args);
+    let field = string_literal(&field)
+        .ok_or("date_part only supports a literal field")?
+        .to_lowercase();
+    let field = match field.as_str() {
+        "epoch" => return Ok(epoch(expr)),
+        "dow" => "dayofweek",
+        "doy" => "dayofyear",
+        "isodow" => "dayofweekiso",
+        "isoyear" => "yearofweekiso",
+        "week" => "weekiso",
+        field => field,
+    };
+    Ok(call("DATE_PART", vec![identifier(field), expr]))
+}
+
+fn to_char(args: Vec<Expr>) -> Result<Expr, String> {
+    formatted("TO_CHAR", args)
+}
+
+fn to_date(args: Vec<Expr>) -> Result<Expr, String> {
+    formatted("TO_DATE", args)
+}
+
+fn to_timestamp(args: Vec<Expr>) -> Result<Expr, String> {
+    formatted("TO_TIMESTAMP", args)
+}
+
+/// Calls `name` with the Postgres format template translated to Snowflake's.
+fn formatted(name: &str, args: Vec<Expr>) -> Result<Expr, String> {
+    let [expr, format] = args_array(args);
+    let format = string_literal(&format)
+        .ok_or_else(|| format!("{} only supports a literal format", name.to_lowercase()))?;
+    Ok(call(name, vec![expr, string(&convert_format(format)?)]))
+}
+
+/// Postgres template patterns and their Snowflake equivalent, `None` for
+/// patterns Snowflake has none for. Longer patterns come first.
+const FORMAT_PATTERNS: &[(&str, Option<&str>)] = &[
+    ("Y,YYY", None),
+    ("SSSSS", None),
+    ("MONTH", Some("MMMM")),
+    ("SSSS", None),
+    ("HH24", Some("HH24")),
+    ("HH12", Some("HH12")),
+    ("YYYY", Some("YYYY")),
+    ("IYYY", None),
+    ("IDDD", None),
+    ("A.M.", None),
+    ("P.M.", None),
+    ("A.D.", None),
+    ("B.C.", None),
+    ("DAY", None),
+    ("DDD", None),
+    ("MON", Some("MON")),
+    ("YYY", None),
+    ("IYY", None),
+    ("TZH", Some("TZH")),
+    ("TZM", Some("TZM")),
+    ("FF1", Some("FF1")),
+    ("FF2", Some("FF2")),
+    ("FF3", Some("FF3")),
+    ("FF4", Some("FF4")),
+    ("FF5", Some("FF5")),
+    ("FF6", Some("FF6")),
+    ("HH", Some("HH12")),
+    ("MI", Some("MI")),
+    ("SS", Some("SS")),
+    ("MS", Some("FF3")),
+    ("US", Some("FF6")),
+    ("AM", Some("AM")),
+    ("PM", Some("PM")),
+    ("YY", Some("YY")),
+    ("MM", Some("MM")),
+    ("DD", Some("DD")),
+    ("DY", Some("DY")),
+    ("IY", None),
+    ("ID", None),
+    ("IW", None),
+    ("WW", None),
+    ("CC", None),
+    ("RM", None),
+    ("AD", None),
+    ("BC", None),
+    ("FM", None),
+    ("FX", None),
+    ("TM", None),
+    ("TH", None),
+    ("TZ", None),
+    ("OF", None),
+    ("Y", None),
+    ("I", None),
+    ("D", None),
+    ("W", None),
+    ("J", None),
+    ("Q", None),
+];
+
+/// Patterns whose output follows their case, e.g. `Mon` prints `Jan` and `MON`
+/// prints `JAN`, in both Postgres and Snowflake.
+const CASED_PATTERNS: &[&str] = &["MONTH", "MON", "DY"];
+
+/// Translates a Postgres date and time format template to a Snowflake format.
+///
+/// Quoted text and separators are kept as is, letters outside quotes must
+/// be a pattern Snowflake supports.
+fn convert_format(format: &str) -> Result<String, String> {
+    let mut converted = String::new();
+    let mut rest = format;
+    'outer: while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let end = rest[1..].find('"').map_or(rest.len(), |end| end + 2);
+            converted.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        for (pattern, replacement) in FORMAT_PATTERNS {
+            let Some(prefix) = rest.get(..pattern.len()) else {
+                continue;
+            };
+            if prefix.eq_ignore_ascii_case(pattern) {
+                let replacement = replacement.ok_or_else(|| unsupported_pattern(prefix))?;
+                match CASED_PATTERNS.contains(pattern) {
+                    true => converted.push_str(&with_case_of(prefix, replacement)),
+                    false => converted.push_str(replacement),
+                }
+                rest = &rest[pattern.len()..];
+                continue 'outer;
+            }
+        }
+        if c.is_ascii_alphabetic() {
+            return Err(unsupported_pattern(&rest[..1]));
+        }
+        converted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    Ok(converted)
+}
+
+fn unsupported_pattern(pattern: &str) -> String {
+    format!(
+        "the {} format pattern is not supported by Snowflake",
+        pattern
+    )
+}
+
+/// Writes `replacement` in lowercase, capitalized or uppercase like `pattern`.
+fn with_case_of(pattern: &str, replacement: &str) -> String {
+    let mut chars = pattern.chars();
+    match (chars.next(), chars.next()) {
+        (Some(first), _) if first.is_ascii_lowercase() => replacement.to_lowercase(),
+        (_, Some(second)) if second.is_ascii_lowercase() => {
+            replacement[..1].to_string() + &replacement[1..].to_lowercase()
+        }
+        _ => replacement.to_string(),
+    }
+}
+
+fn strpos(args: Vec<Expr>) -> Result<Expr, String> {
+    let [string, substring] = args_array(args);
+    Ok(call("POSITION", vec![substring, string]))
+}
+
+/// Postgres skips NULL arguments, where Snowflake's CONCAT returns NULL.
+fn concat(args: Vec<Expr>) -> Result<Expr, String> {
+    Ok(call(
+        "ARRAY_TO_STRING",
+        vec![call("ARRAY_CONSTRUCT_COMPACT", args), string("")],
+    ))
+}
+
+fn concat_ws(mut args: Vec<Expr>) -> Result<Expr, String> {
+    if args.is_empty() {
+        return Err("concat_ws needs a separator".to_string());
+    }
+    let separator = args.remove(0);
+    Ok(call(
+        "ARRAY_TO_STRING",
+        vec![call("ARRAY_CONSTRUCT_COMPACT", args), separator],
+    ))
+}
+
+/// Postgres replaces the first match unless the `g` flag is given, where Snowflake
+/// replaces every match unless told which occurrence to replace.
+fn regexp_replace(mut args: Vec<Expr>) -> Result<Expr, String> {
+    let mut position = number("1");
+    let mut occurrence = number("1");
+    let mut parameters = None;
+    if args.len() == 4 {
+        match args.pop() {
+            Some(start @ Expr::Value(Value::Number(..))) => position = start,
+            Some(flags) => {
+                let flags =
+                    string_literal(&flags).ok_or("regexp_replace only supports literal flags")?;
+                if let Some(flag) = flags.chars().find(|c| !"gicms".contains(*c)) {
+                    return Err(format!(
+                        "the regexp_replace flag {} is not supported by Snowflake",
+                        flag
+                    ));
+                }
+                if flags.contains('g') {
+                    occurrence = number("0");
+                }
+                let flags = flags.replace('g', "");
+                parameters = (!flags.is_empty()).then(|| string(&flags));
+            }
+            None => unreachable!(),
+        }
+    }
+    args.extend([position, occurrence]);
+    args.extend(parameters);
+    Ok(call("REGEXP_REPLACE", args))
+}
+
+fn encode(args: Vec<Expr>) -> Result<Expr, String> {
+    let [data, format] = args_array(args);
+    match string_literal(&format).map(str::to_lowercase).as_deref() {
+        // Postgres encodes hex in lowercase
+        Some("hex") => Ok(call("HEX_ENCODE", vec![data, number("0")])),
+        Some("base64") => Ok(call("BASE64_ENCODE", vec![data])),
+        _ => Err("encode only supports the literal formats 'hex' and 'base64'".to_string()),
+    }
+}
+
+/// Snowflake's DECODE is a CASE expression, so decoding must be translated.
+fn decode(args: Vec<Expr>) -> Result<Expr, String> {
+    let [data, format] = args_array(args);
+    match string_literal(&format).map(str::to_lowercase).as_deref() {
+        Some("hex") => Ok(call("HEX_DECODE_BINARY", vec![data])),
+        Some("base64") => Ok(call("BASE64_DECODE_BINARY", vec![data])),
+        _ => Err("decode only supports the literal formats 'hex' and 'base64'".to_string()),
+    }
+}
+
+fn sha256(args: Vec<Expr>) -> Result<Expr, String> {
+    let [data] = args_array(args);
+    Ok(call("SHA2_BINARY", vec![data, number("256")]))
+}
+
+/// Snowflake's RANDOM returns a 64-bit integer, Postgres a float in `[0, 1)`.
+fn random(_args: Vec<Expr>) -> Result<Expr, String> {
+    let float = |value| Expr::Cast {
+        kind: CastKind::DoubleColon,
+        expr: Box::new(number(value)),
+        data_type: DataType::Float(None),
+        format: None,
+    };
+    Ok(call(
+        "UNIFORM",
+        vec![float("0"), float("1"), call("RANDOM", vec![])],
+    ))
+}
+
+/// Postgres' single argument LOG is in base 10.
+fn log10(args: Vec<Expr>) -> Result<Expr, String> {
+    let [value] = args_array(args);
+    Ok(call("LOG", vec![number("10"), value]))
+}
+
+fn div(args: Vec<Expr>) -> Result<Expr, String> {
+    let [dividend, divisor] = args_array(args);
+    let quotient = Expr::BinaryOp {
+        left: Box::new(dividend),
+        op: BinaryOperator::Divide,
+        right: Box::new(divisor),
+    };
+    Ok(call("TRUNC", vec![quotient]))
+}
+
+fn string_agg(func: Function) -> Result<Expr, String> {
+    order_within_group(func, "LISTAGG")
+}
+
+fn array_agg(func: Function) -> Result<Expr, String> {
+    order_within_group(func, "ARRAY_AGG")
+}
+
+/// Moves the `ORDER BY` of an aggregate into `WITHIN GROUP`, where Snowflake
+/// expects it.
+fn order_within_group(mut func: Function, name: &str) -> Result<Expr, String> {
+    let name_in_error = func.name.to_string().to_lowercase();
+    if let FunctionArguments::List(list) = &mut func.args {
+        for clause in std::mem::take(&mut list.clauses) {
+            match clause {
+                FunctionArgumentClause::OrderBy(order_by) => func.within_group = order_by,
+                clause => {
+                    return Err(format!(
+                        "{} does not support {} in Snowflake",
+                        name_in_error, clause
+                    ))
+                }
+            }
+        }
+    }
+    func.name = ObjectName(vec![Ident::new(name)]);
+    Ok(Expr::Function(func))
+}
+
+/// Snowflake arrays have a single dimension.
+fn array_length(args: Vec<Expr>) -> Result<Expr, String> {
+    let [array, dimension] = args_array(args);
+    match dimension {
+        Expr::Value(Value::Number(n, _)) if n == "1" => Ok(call("ARRAY_SIZE", vec![array])),
+        _ => Err("array_length only supports the dimension 1".to_string()),
+    }
+}
+
+/// Snowflake takes the element first and counts positions from 0.
+fn array_position(args: Vec<Expr>) -> Result<Expr, String> {
+    let [array, element] = args_array(args);
+    Ok(Expr::BinaryOp {
+        left: Box::new(call("ARRAY_POSITION", vec![element, array])),
+        op: BinaryOperator::Plus,
+        right: Box::new(number("1")),
+    })
+}
+
+/// The arguments of a call, whose number [`FUNCTIONS`] already checked.
+fn args_array<const N: usize>(args: Vec<Expr>) -> [Expr; N] {
+    args.try_into()
+        .unwrap_or_else(|args: Vec<Expr>| panic!("expected {} arguments, got {}", N, args.len()))
+}
+
+pub(super) fn call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::Function(Function {
+        name: ObjectName(vec![Ident::new(name)]),
+        parameters: FunctionArguments::None,
+        args: FunctionArguments::List(FunctionArgumentList {
+            duplicate_treatment: None,
+            args: args
+                .into_iter()
+                .map(|arg| FunctionArg::Unnamed(FunctionArgExpr::Expr(arg)))
+                .collect(),
+            clauses: vec![],
+        }),
+        filter: None,
+        null_treatment: None,
+        over: None,
+        within_group: vec![],
+    })
+}
+
+pub(super) fn identifier(name: &str) -> Expr {
+    Expr::Identifier(Ident::new(name))
+}
+
+pub(super) fn number(value: &str) -> Expr {
+    Expr::Value(Value::Number(value.to_string(), false))
+}
+
+pub(super) fn string(value: &str) -> Expr {
+    Expr::Value(Value::SingleQuotedString(value.to_string()))
+}
+
+pub(super) fn string_literal(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Value(Value::SingleQuotedString(s)) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::convert_format;
+    use crate::data_store::snowflake::SnowflakeMapping;
+    use crate::semantic_model::local_store::LocalSemanticModelStore;
+    use crate::sql_parser::{SqlError, SqlParser};
+    use rstest::rstest;
+
+    fn transform(expr: &str) -> Result<String, SqlError> {
+        let sql_parser = SqlParser::new(SnowflakeMapping {}, LocalSemanticModelStore::mock());
+        let sql = format!("SELECT {} AS value FROM dm_employees", expr);
+        let mut statements = sql_parser.transform(&sql)?;
+        let statement = statements.remove(0);
+        let value = statement
+            .strip_prefix("SELECT ")
+            .and_then(|s| s.strip_suffix(" AS value FROM dm_employees"))
+            .unwrap();
+        Ok(value.to_string())
+    }
+
+    #[rstest]
+    #[case::now("now()", "CURRENT_TIMESTAMP()")]
+    #[case::clock_timestamp("clock_timestamp()", "CURRENT_TIMESTAMP()")]
+    #[case::date_trunc("date_trunc('month', d)", "DATE_TRUNC('month', d)")]
+    #[case::date_part("date_part('year', d)", "DATE_PART(year, d)")]
+    #[case::date_part_dow("date_part('dow', d)", "DATE_PART(dayofweek, d)")]
+    #[case::date_part_epoch("date_part('epoch', d)", "DATE_PART(epoch_microsecond, d) / 1000000")]
+    #[case::extract_epoch("extract(epoch FROM d)", "DATE_PART(epoch_microsecond, d) / 1000000")]
+    #[case::extract_isodow("extract(isodow FROM d)", "EXTRACT(dayofweekiso FROM d)")]
+    #[case::extract_year("extract(year FROM d)", "EXTRACT(YEAR FROM d)")]
+    #[case::make_date("make_date(2024, 1, 31)", "DATE_FROM_PARTS(2024, 1, 31)")]
+    #[case::timezone("timezone('UTC', d)", "CONVERT_TIMEZONE('UTC', d)")]
+    #[case::to_char(
+        "to_char(d, 'YYYY-MM-DD HH24:MI:SS.MS')",
+        "TO_CHAR(d, 'YYYY-MM-DD HH24:MI:SS.FF3')"
+    )]
+    #[case::to_char_names(
+        "to_char(d, 'Dy, DD Mon YYYY HH:MI am')",
+        "TO_CHAR(d, 'Dy, DD Mon YYYY HH12:MI AM')"
+    )]
+    #[case::to_char_month("to_char(d, 'Month')", "TO_CHAR(d, 'Mmmm')")]
+    #[case::to_char_upper_month("to_char(d, 'MONTH mon')", "TO_CHAR(d, 'MMMM mon')")]
+    #[case::to_char_quoted("to_char(d, '\"Day\" DD')", "TO_CHAR(d, '\"Day\" DD')")]
+    #[case::to_date("to_date(s, 'DD/MM/YYYY')", "TO_DATE(s, 'DD/MM/YYYY')")]
+    #[case::to_timestamp(
+        "to_timestamp(s, 'YYYY-MM-DD HH24:MI:SS.US')",
+        "TO_TIMESTAMP(s, 'YYYY-MM-DD HH24:MI:SS.FF6')"
+    )]
+    #[case::to_timestamp_epoch("to_timestamp(1700000000)", "to_timestamp(1700000000)")]
+    #[case::btrim("btrim(s, 'x')", "TRIM(s, 'x')")]
+    #[case::char_length("char_length(s)", "LENGTH(s)")]
+    #[case::strpos("strpos(s, 'x')", "POSITION('x', s)")]
+    #[case::position("position('x' IN s)", "POSITION('x' IN s)")]
+    #[case::concat(
+        "concat(a, 1, NULL)",
+        "ARRAY_TO_STRING(ARRAY_CONSTRUCT_COMPACT(a, 1, NULL), '')"
+    )]
+    #[case::concat_ws(
+        "concat_ws(', ', a, b)",
+        "ARRAY_TO_STRING(ARRAY_CONSTRUCT_COMPACT(a, b), ', ')"
+    )]
+    #[case::split_part("split_part(s, ',', 2)", "SPLIT_PART(s, ',', 2)")]
+    #[case::string_to_array("string_to_array(s, ',')", "SPLIT(s, ',')")]
+    #[case::regexp_replace("regexp_replace(s, 'a+', 'b')", "REGEXP_REPLACE(s, 'a+', 'b', 1, 1)")]
+    #[case::regexp_replace_global(
+        "regexp_replace(s, 'a+', 'b', 'g')",
+        "REGEXP_REPLACE(s, 'a+', 'b', 1, 0)"
+    )]
+    #[case::regexp_replace_flags(
+        "regexp_replace(s, 'a+', 'b', 'gi')",
+        "REGEXP_REPLACE(s, 'a+', 'b', 1, 0, 'i')"
+    )]
+    #[case::regexp_replace_start(
+        "regexp_replace(s, 'a+', 'b', 3)",
+        "REGEXP_REPLACE(s, 'a+', 'b', 3, 1)"
+    )]
+    #[case::encode_hex("encode(b, 'hex')", "HEX_ENCODE(b, 0)")]
+    #[case::encode_base64("encode(b, 'base64')", "BASE64_ENCODE(b)")]
+    #[case::decode_hex("decode(s, 'hex')", "HEX_DECODE_BINARY(s)")]
+    #[case::sha256("sha256(b)", "SHA2_BINARY(b, 256)")]
+    #[case::gen_random_uuid("gen_random_uuid()", "UUID_STRING()")]
+    #[case::random("random()", "UNIFORM(0::FLOAT, 1::FLOAT, RANDOM())")]
+    #[case::log("log(x)", "LOG(10, x)")]
+    #[case::log_base("log(2, x)", "log(2, x)")]
+    #[case::ceiling("ceiling(x)", "CEIL(x)")]
+    #[case::div("div(a, b)", "TRUNC(a / b)")]
+    #[case::greatest("greatest(a, b, c)", "GREATEST_IGNORE_NULLS(a, b, c)")]
+    #[case::least("least(a, b)", "LEAST_IGNORE_NULLS(a, b)")]
+    #[case::string_agg("string_agg(s, ',')", "LISTAGG(s, ',')")]
+    #[case::string_agg_ordered(
+        "string_agg(DISTINCT s, ',' ORDER BY s DESC)",
+        "LISTAGG(DISTINCT s, ',') WITHIN GROUP (ORDER BY s DESC)"
+    )]
+    #[case::array_agg_ordered("array_agg(s ORDER BY d)", "ARRAY_AGG(s) WITHIN GROUP (ORDER BY d)")]
+    #[case::json_agg("json_agg(s)", "ARRAY_AGG(s)")]
+    #[case::json_object_agg("json_object_agg(k, v)", "OBJECT_AGG(k, v)")]
+    #[case::bool_and("bool_and(flag)", "BOOLAND_AGG(flag)")]
+    #[case::every("every(flag)", "BOOLAND_AGG(flag)")]
+    #[case::bool_or_window(
+        "bool_or(flag) OVER (PARTITION BY a)",
+        "BOOLOR_AGG(flag) OVER (PARTITION BY a)"
+    )]
+    #[case::bit_and("bit_and(x)", "BITAND_AGG(x)")]
+    #[case::json_build_object(
+        "json_build_object('a', 1, 'b', NULL)",
+        "OBJECT_CONSTRUCT_KEEP_NULL('a', 1, 'b', NULL)"
+    )]
+    #[case::jsonb_build_array("jsonb_build_array(1, 2)", "ARRAY_CONSTRUCT(1, 2)")]
+    #[case::to_jsonb("to_jsonb(s)", "TO_VARIANT(s)")]
+    #[case::jsonb_array_length("jsonb_array_length(j)", "ARRAY_SIZE(j)")]
+    #[case::array_length("array_length(arr, 1)", "ARRAY_SIZE(arr)")]
+    #[case::cardinality("cardinality(arr)", "ARRAY_SIZE(arr)")]
+    #[case::array_position("array_position(arr, 'x')", "ARRAY_POSITION('x', arr) + 1")]
+    #[case::nested(
+        "coalesce(to_char(now(), 'YYYY'), concat(a, b))",
+        "coalesce(TO_CHAR(CURRENT_TIMESTAMP(), 'YYYY'), ARRAY_TO_STRING(ARRAY_CONSTRUCT_COMPACT(a, b), ''))"
+    )]
+    #[case::unchanged("upper(coalesce(s, 'x'))", "upper(coalesce(s, 'x'))")]
+    #[case::user_defined("util.greatest(a, b)", "util.greatest(a, b)")]
+    #[case::case_statement(
+        "CASE WHEN flag THEN now() ELSE NULL END",
+        "CASE WHEN flag THEN CURRENT_TIMESTAMP() ELSE NULL END"
+    )]
+    fn test_function_translation(#[case] postgres: &str, #[case] snowflake: &str) {
+        assert_eq!(transform(postgres), Ok(snowflake.to_string()));
+    }
+
+    #[rstest]
+    #[case::age("age(d)")]
+    #[case::generate_series("generate_series(1, 10)")]
+    #[case::unnest("unnest(arr)")]
+    #[case::regexp_matches("regexp_matches(s, 'a+', 'g')")]
+    fn test_unsupported_functions(#[case] postgres: &str) {
+        assert!(matches!(
+            transform(postgres),
+            Err(SqlError::UnsupportedSqlConstruct(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::date_part_field("date_part(field, d)")]
+    #[case::to_char_format("to_char(d, fmt)")]
+    #[case::to_char_pattern("to_char(d, 'FMDD')")]
+    #[case::to_char_day("to_char(d, 'Day')")]
+    #[case::to_char_day_of_week("to_char(d, 'D')")]
+    #[case::to_char_quarter("to_char(d, 'Q')")]
+    #[case::to_char_week_of_year("to_char(d, 'WW')")]
+    #[case::to_char_julian_day("to_char(d, 'J')")]
+    #[case::to_char_century("to_char(d, 'CC')")]
+    #[case::to_char_unknown_letter("to_char(d, 'YYYY-MM-DDTHH24')")]
+    #[case::regexp_replace_flag("regexp_replace(s, 'a', 'b', 'x')")]
+    #[case::encode_format("encode(b, 'escape')")]
+    #[case::decode_format("decode(s, fmt)")]
+    #[case::array_length_dimension("array_length(arr, 2)")]
+    #[case::string_agg_limit("string_agg(s, ',' LIMIT 1)")]
+    #[case::named_arguments("to_char(d, format => 'YYYY')")]
+    fn test_invalid_arguments(#[case] postgres: &str) {
+        assert!(matches!(
+            transform(postgres),
+            Err(SqlError::InvalidFunctionArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_convert_format() {
+        assert_eq!(
+            convert_format("YYYY-MM-DD\"T\"HH24:MI:SS.US"),
+            Ok("YYYY-MM-DD\"T\"HH24:MI:SS.FF6".to_string())
+        );
+        assert_eq!(convert_format("9,999.99"), Ok("9,999.99".to_string()));
+        assert_eq!(
+            convert_format("\"unterminated"),
+            Ok("\"unterminated".to_string())
+        );
+        assert_eq!(
+            convert_format("DDD"),
+            Err("the DDD format pattern is not supported by Snowflake".to_string())
+        );
+        assert_eq!(
+            convert_format("YYYY q"),
+            Err("the q format pattern is not supported by Snowflake".to_string())
+        );
+    }
+}
@@ -0,0 +1,793 @@
+//! Translation of Postgres only syntax to Snowflake: `DISTINCT ON`, casts to
+//! `INTERVAL`, interval literals, JSON operators, `ANY` and `ALL` over arrays,
+//! and aggregate `FILTER` clauses.
+use super::snowflake_functions::{call, number, string, string_literal};
+use sqlparser::ast::*;
+
+/// Rewrites the Postgres only expressions Snowflake has another syntax for.
+pub fn map_expression(expr: &Expr) -> Result<Option<Expr>, String> {
+    match expr {
+        Expr::Cast {
+            expr,
+            data_type: DataType::Interval,
+            ..
+        } => {
+            let value = string_literal(expr)
+                .ok_or("Snowflake can only cast string literals to INTERVAL")?;
+            Ok(Some(interval(convert_interval(value)?)))
+        }
+        Expr::Interval(value) => map_interval(value).map(Some),
+        Expr::BinaryOp { left, op, right } => map_json_operator(left, op, right),
+        Expr::AnyOp {
+            left,
+            compare_op,
+            right,
+        } => map_array_comparison(left, compare_op, right, Quantifier::Any).map(Some),
+        Expr::AllOp {
+            left,
+            compare_op,
+            right,
+        } => map_array_comparison(left, compare_op, right, Quantifier::All).map(Some),
+        Expr::Like { .. } | Expr::ILike { .. } => map_like_quantifier(expr),
+        Expr::Function(func) if func.filter.is_some() => map_filter(func).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Rewrites `DISTINCT ON` to keep the first row of each group with `QUALIFY`,
+/// ordered by the `ORDER BY` of the query.
+pub fn map_query(query: &mut Query) -> Result<(), String> {
+    let order_by = match &query.order_by {
+        Some(order_by) => order_by.exprs.clone(),
+        None => vec![],
+    };
+    match query.body.as_mut() {
+        SetExpr::Select(select) => distinct_on(select, &order_by),
+        // The ORDER BY of a set operation sorts its result rather than each SELECT
+        body => {
+            let mut selects = vec![body];
+            while let Some(body) = selects.pop() {
+                match body {
+                    SetExpr::Select(select) => distinct_on(select, &[])?,
+                    SetExpr::SetOperation { left, right, .. } => {
+                        selects.extend([left.as_mut(), right.as_mut()])
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn distinct_on(select: &mut Select, order_by: &[OrderByExpr]) -> Result<(), String> {
+    let Some(Distinct::On(partition_by)) = &select.distinct else {
+        return Ok(());
+    };
+    let partition_by = partition_by
+        .iter()
+        .map(|expr| projection_expr(&select.projection, expr))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut order_by = order_by
+        .iter()
+        .map(|order_by| {
+            Ok(OrderByExpr {
+                expr: projection_expr(&select.projection, &order_by.expr)?,
+                ..order_by.clone()
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    // Snowflake's ROW_NUMBER needs an order, any row of a group is kept without one
+    if order_by.is_empty() {
+        order_by = partition_by
+            .iter()
+            .map(|expr| OrderByExpr {
+                expr: expr.clone(),
+                asc: None,
+                nulls_first: None,
+                with_fill: None,
+            })
+            .collect();
+    }
+
+    let Expr::Function(mut row_number) = call("ROW_NUMBER", vec![]) else {
+        unreachable!()
+    };
+    row_number.over = Some(WindowType::WindowSpec(WindowSpec {
+        window_name: None,
+        partition_by,
+        order_by,
+        window_frame: None,
+    }));
+    let first_row = Expr::BinaryOp {
+        left: Box::new(Expr::Function(row_number)),
+        op: BinaryOperator::Eq,
+        right: Box::new(number("1")),
+    };
+    select.distinct = None;
+    select.qualify = Some(match select.qualify.take() {
+        Some(qualify) => Expr::BinaryOp {
+            left: Box::new(qualify),
+            op: BinaryOperator::And,
+            right: Box::new(first_row),
+        },
+        None => first_row,
+    });
+    Ok(())
+}
+
+/// Resolves `ORDER BY 2` or `DISTINCT ON (2)` to the second item of the
+/// projection, which has no meaning in a window.
+fn projection_expr(projection: &[SelectItem], expr: &Expr) -> Result<Expr, String> {
+    let Expr::Value(Value::Number(position, _)) = expr else {
+        return Ok(expr.clone());
+    };
+    let item = position
+        .parse::<usize>()
+        .ok()
+        .and_then(|position| projection.get(position.checked_sub(1)?));
+    match item {
+        Some(SelectItem::UnnamedExpr(expr)) => Ok(expr.clone()),
+        Some(SelectItem::ExprWithAlias { alias, .. }) => Ok(Expr::Identifier(alias.clone())),
+        _ => Err(format!(
+            "position {} must refer to an expression of the SELECT list to be used with DISTINCT ON",
+            position
+        )),
+    }
+}
+
+fn interval(value: String) -> Expr {
+    Expr::Interval(Interval {
+        value: Box::new(Expr::Value(Value::SingleQuotedString(value))),
+        leading_field: None,
+        leading_precision: None,
+        last_field: None,
+        fractional_seconds_precision: None,
+    })
+}
+
+/// Snowflake intervals name the unit of each amount in the literal, as in
+/// `INTERVAL '1 year, 2 months'`.
+fn map_interval(value: &Interval) -> Result<Expr, String> {
+    let literal =
+        string_literal(&value.value).ok_or("Snowflake only supports INTERVAL literals")?;
+    let unsupported = || format!("INTERVAL '{}' is not supported by Snowflake", literal);
+    match value {
+        Interval {
+            leading_field: None,
+            leading_precision: None,
+            last_field: None,
+            fractional_seconds_precision: None,
+            ..
+        } => Ok(interval(convert_interval(literal)?)),
+        Interval {
+            leading_field: Some(field),
+            leading_precision: None,
+            last_field: None,
+            fractional_seconds_precision: None,
+            ..
+        } => {
+            let unit = match field {
+                DateTimeField::Year => "year",
+                DateTimeField::Month => "month",
+                DateTimeField::Week(None) => "week",
+                DateTimeField::Day => "day",
+                DateTimeField::Hour => "hour",
+                DateTimeField::Minute => "minute",
+                DateTimeField::Second => "second",
+                _ => return Err(unsupported()),
+            };
+            if literal.trim().parse::<f64>().is_err() {
+                return Err(unsupported());
+            }
+            Ok(interval(amount(literal.trim(), unit)))
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Translates a Postgres interval such as `1 year 2 mons 03:04:00 ago` to the
+/// Snowflake `-1 year, -2 months, -3 hours, -4 minutes`.
+fn convert_interval(value: &str) -> Result<String, String> {
+    let unsupported = || format!("INTERVAL '{}' is not supported by Snowflake", value);
+    let mut tokens = Vec::new();
+    for token in value.split_whitespace() {
+        // Postgres accepts units attached to their amount, as in `1day`
+        match token.find(|c: char| c.is_ascii_alphabetic()) {
+            Some(split) if split > 0 => tokens.extend([&token[..split], &token[split..]]),
+            _ => tokens.push(token),
+        }
+    }
+    let ago = tokens
+        .last()
+        .is_some_and(|token| token.eq_ignore_ascii_case("ago"));
+    if ago {
+        tokens.pop();
+    }
+    if tokens.first() == Some(&"@") {
+        tokens.remove(0);
+    }
+
+    let mut amounts = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            let (sign, time) = match token.strip_prefix('-') {
+                Some(time) => ("-", time),
+                None => ("", token.strip_prefix('+').unwrap_or(token)),
+            };
+            let parts: Vec<&str> = time.split(':').collect();
+            if parts.len() > 3 || parts.iter().any(|part| part.parse::<f64>().is_err()) {
+                return Err(unsupported());
+            }
+            let time: Vec<_> = parts
+                .into_iter()
+                .zip(["hour", "minute", "second"])
+                .filter(|(part, _)| part.parse::<f64>() != Ok(0.0))
+                .map(|(part, unit)| {
+                    let part = part.trim_start_matches('0');
+                    let zero = if part.starts_with('.') { "0" } else { "" };
+                    (format!("{}{}{}", sign, zero, part), unit)
+                })
+                .collect();
+            match time.is_empty() {
+                true => amounts.push(("0".to_string(), "second")),
+                false => amounts.extend(time),
+            }
+            continue;
+        }
+        if token.parse::<f64>().is_err() {
+            return Err(unsupported());
+        }
+        // An amount without a unit is in seconds
+        let unit = match tokens.next_if(|unit| unit.parse::<f64>().is_err()) {
+            Some(unit) => interval_unit(unit).ok_or_else(unsupported)?,
+            None => "second",
+        };
+        amounts.push((token.to_string(), unit));
+    }
+    if amounts.is_empty() {
+        return Err(unsupported());
+    }
+
+    let amounts = amounts.into_iter().map(|(value, unit)| {
+        let value = match (ago, value.strip_prefix('-')) {
+            (false, _) => value,
+            (true, Some(positive)) => positive.to_string(),
+            (true, None) => format!("-{}", value.trim_start_matches('+')),
+        };
+        amount(&value, unit)
+    });
+    Ok(amounts.collect::<Vec<_>>().join(", "))
+}
+
+fn amount(value: &str, unit: &str) -> String {
+    let plural = if value.trim_start_matches(['-', '+']) == "1" {
+        ""
+    } else {
+        "s"
+    };
+    format!("{} {}{}", value, unit, plural)
+}
+
+/// The unit of a Postgres interval amount. Decades, centuries and millennia
+/// have no Snowflake equivalent.
+fn interval_unit(unit: &str) -> Option<&'static str> {
+    let unit = match unit.to_lowercase().as_str() {
+        "microsecond" | "microseconds" | "us" | "usec" | "usecs" => "microsecond",
+        "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" => "millisecond",
+        "second" | "seconds" | "s" | "sec" | "secs" => "second",
+        "minute" | "minutes" | "m" | "min" | "mins" => "minute",
+        "hour" | "hours" | "h" | "hr" | "hrs" => "hour",
+        "day" | "days" | "d" => "day",
+        "week" | "weeks" | "w" => "week",
+        "month" | "months" | "mon" | "mons" => "month",
+        "year" | "years" | "y" | "yr" | "yrs" => "year",
+        _ => return None,
+    };
+    Some(unit)
+}
+
+/// Rewrites `->`, `->>`, `#>` and `#>>` to `GET_PATH`, casting the value to text
+/// for the operators returning text.
+fn map_json_operator(
+    left: &Expr,
+    op: &BinaryOperator,
+    right: &Expr,
+) -> Result<Option<Expr>, String> {
+    let (path, as_text) = match op {
+        BinaryOperator::Arrow | BinaryOperator::LongArrow => {
+            let element = match right {
+                Expr::Value(Value::Number(index, _)) => index.clone(),
+                Expr::Value(Value::SingleQuotedString(key)) => key.clone(),
+                Expr::UnaryOp {
+                    op: UnaryOperator::Minus,
+                    expr,
+                } if matches!(expr.as_ref(), Expr::Value(Value::Number(..))) => {
+                    format!("-{}", expr)
+                }
+                // Keys computed by the query are looked up one at a time
+                key => {
+                    let value = call("GET", vec![left.clone(), key.clone()]);
+                    return Ok(Some(json_value(value, op == &BinaryOperator::LongArrow)));
+                }
+            };
+            (json_path(&[element])?, op == &BinaryOperator::LongArrow)
+        }
+        BinaryOperator::HashArrow | BinaryOperator::HashLongArrow => {
+            let elements = string_literal(right)
+                .and_then(parse_text_array)
+                .ok_or("Snowflake only supports #> and #>> with a literal path such as '{a,0}'")?;
+            (json_path(&elements)?, op == &BinaryOperator::HashLongArrow)
+        }
+        _ => return Ok(None),
+    };
+
+    // `j -> 'a' -> 'b'` reads a single path
+    let value = match get_path(left) {
+        Some((value, prefix)) => {
+            let separator = if path.starts_with('[') { "" } else { "." };
+            let path = format!("{}{}{}", prefix, separator, path);
+            call("GET_PATH", vec![value.clone(), string(&path)])
+        }
+        None => call("GET_PATH", vec![left.clone(), string(&path)]),
+    };
+    Ok(Some(json_value(value, as_text)))
+}
+
+/// The value and path of a `GET_PATH` call built from a JSON operator.
+fn get_path(expr: &Expr) -> Option<(&Expr, &str)> {
+    let Expr::Function(func) = expr else {
+        return None;
+    };
+    let FunctionArguments::List(list) = &func.args else {
+        return None;
+    };
+    match &list.args[..] {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(value)), FunctionArg::Unnamed(FunctionArgExpr::Expr(path))]
+            if func.name.to_string() == "GET_PATH" =>
+        {
+            Some((value, string_literal(path)?))
+        }
+        _ => None,
+    }
+}
+
+fn json_value(value: Expr, as_text: bool) -> Expr {
+    if !as_text {
+        return value;
+    }
+    Expr::Cast {
+        kind: CastKind::DoubleColon,
+        expr: Box::new(value),
+        data_type: DataType::Varchar(None),
+        format: None,
+    }
+}
+
+/// Builds a Snowflake path such as `a."b c"[0]` from object keys and array
+/// indexes.
+fn json_path(elements: &[String]) -> Result<String, String> {
+    let mut path = String::new();
+    for element in elements {
+        if !element.is_empty() && element.bytes().all(|b| b.is_ascii_digit()) {
+            path.push_str(&format!("[{}]", element));
+            continue;
+        }
+        if element.starts_with('-') && element[1..].parse::<u64>().is_ok() {
+            return Err(format!(
+                "Snowflake does not support the negative JSON array index {}",
+                element
+            ));
+        }
+        if element.contains('"') {
+            return Err(format!(
+                "Snowflake does not support the JSON key {} in paths",
+                element
+            ));
+        }
+        if !path.is_empty() {
+            path.push('.');
+        }
+        let is_identifier = element
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && element
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_identifier {
+            path.push_str(element);
+        } else {
+            path.push_str(&format!("\"{}\"", element));
+        }
+    }
+    Ok(path)
+}
+
+/// Parses a Postgres text array literal such as `{a,"b c",0}`.
+fn parse_text_array(literal: &str) -> Option<Vec<String>> {
+    let inner = literal.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut elements = Vec::new();
+    let mut chars = inner.chars().peekable();
+    while chars.peek().is_some() {
+        let mut element = String::new();
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => element.push(chars.next()?),
+                    c => element.push(c),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                element.push(c);
+            }
+            element = element.trim().to_string();
+        }
+        elements.push(element);
+        match chars.next() {
+            Some(',') | None => {}
+            Some(_) => return None,
+        }
+    }
+    Some(elements)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Quantifier {
+    Any,
+    All,
+}
+
+/// Rewrites `x = ANY(ARRAY[...])` to `IN`, and comparisons with the other
+/// operators to one comparison per element. `= ANY` and `<> ALL` over array
+/// values use `ARRAY_CONTAINS`.
+fn map_array_comparison(
+    left: &Expr,
+    op: &BinaryOperator,
+    right: &Expr,
+    quantifier: Quantifier,
+) -> Result<Expr, String> {
+    let in_list = match (quantifier, op) {
+        (Quantifier::Any, BinaryOperator::Eq) => Some(false),
+        (Quantifier::All, BinaryOperator::NotEq) => Some(true),
+        _ => None,
+    };
+    let Expr::Array(Array { elem, .. }) = right else {
+        let Some(negated) = in_list else {
+            return Err(format!(
+                "Snowflake only supports {} over array literals",
+                quantified_operator(op, quantifier)
+            ));
+        };
+        let value = Expr::Cast {
+            kind: CastKind::DoubleColon,
+            expr: Box::new(left.clone()),
+            data_type: DataType::Custom(ObjectName(vec![Ident::new("VARIANT")]), vec![]),
+            format: None,
+        };
+        let contains = call("ARRAY_CONTAINS", vec![value, right.clone()]);
+        return Ok(match negated {
+            false => contains,
+            true => Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr: Box::new(contains),
+            },
+        });
+    };
+
+    match in_list {
+        Some(negated) if !elem.is_empty() => Ok(Expr::InList {
+            expr: Box::new(left.clone()),
+            list: elem.clone(),
+            negated,
+        }),
+        _ => {
+            let comparisons = elem.iter().map(|element| Expr::BinaryOp {
+                left: Box::new(left.clone()),
+                op: op.clone(),
+                right: Box::new(element.clone()),
+            });
+            Ok(combine(comparisons.collect(), quantifier))
+        }
+    }
+}
+
+fn quantified_operator(op: &BinaryOperator, quantifier: Quantifier) -> String {
+    match quantifier {
+        Quantifier::Any => format!("{} ANY", op),
+        Quantifier::All => format!("{} ALL", op),
+    }
+}
+
+/// Joins conditions with `OR` for `ANY` or with `AND` for `ALL`, which holds
+/// for no conditions only with `ALL`.
+fn combine(conditions: Vec<Expr>, quantifier: Quantifier) -> Expr {
+    let op = match quantifier {
+        Quantifier::Any => BinaryOperator::Or,
+        Quantifier::All => BinaryOperator::And,
+    };
+    let combined = conditions.into_iter().reduce(|left, right| Expr::BinaryOp {
+        left: Box::new(left),
+        op: op.clone(),
+        right: Box::new(right),
+    });
+    match combined {
+        Some(combined) => Expr::Nested(Box::new(combined)),
+        None => Expr::Value(Value::Boolean(quantifier == Quantifier::All)),
+    }
+}
+
+/// Rewrites `s ILIKE ANY(ARRAY['a%', 'b%'])` to Snowflake's `s ILIKE ANY('a%', 'b%')`.
+///
+/// Snowflake has `LIKE ANY`, `LIKE ALL` and `ILIKE ANY`, other quantified
+/// patterns are matched one at a time.
+fn map_like_quantifier(expr: &Expr) -> Result<Option<Expr>, String> {
+    let (Expr::Like {
+        negated,
+        expr: subject,
+        pattern,
+        escape_char,
+    }
+    | Expr::ILike {
+        negated,
+        expr: subject,
+        pattern,
+        escape_char,
+    }) = expr
+    else {
+        return Ok(None);
+    };
+    let ilike = matches!(expr, Expr::ILike { .. });
+    let Expr::Function(func) = pattern.as_ref() else {
+        return Ok(None);
+    };
+    let quantifier = match func.name.to_string().to_uppercase().as_str() {
+        "ANY" | "SOME" => Quantifier::Any,
+        "ALL" => Quantifier::All,
+        _ => return Ok(None),
+    };
+    let operator = if ilike { "ILIKE" } else { "LIKE" };
+    let patterns = match &func.args {
+        FunctionArguments::List(FunctionArgumentList { args, .. }) => match &args[..] {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Array(array)))] => array.elem.clone(),
+            _ => {
+                return Err(format!(
+                    "Snowflake only supports {} {} over array literals",
+                    operator,
+                    func.name.to_string().to_uppercase()
+                ))
+            }
+        },
+        _ => return Ok(None),
+    };
+
+    let like = |pattern: Expr| match ilike {
+        false => Expr::Like {
+            negated: *negated,
+            expr: subject.clone(),
+            pattern: Box::new(pattern),
+            escape_char: escape_char.clone(),
+        },
+        true => Expr::ILike {
+            negated: *negated,
+            expr: subject.clone(),
+            pattern: Box::new(pattern),
+            escape_char: escape_char.clone(),
+        },
+    };
+    let chained = *negated || patterns.is_empty() || (ilike && quantifier == Quantifier::All);
+    if !chained {
+        let name = match quantifier {
+            Quantifier::Any => "ANY",
+            Quantifier::All => "ALL",
+        };
+        return Ok(Some(like(call(name, patterns))));
+    }
+    Ok(Some(combine(
+        patterns.into_iter().map(like).collect(),
+        quantifier,
+    )))
+}
+
+/// Rewrites `agg(x) FILTER (WHERE c)` to `agg(CASE WHEN c THEN x END)`, as the
+/// aggregates skip NULLs, and `count(*) FILTER (WHERE c)` to `COUNT_IF(c)`.
+fn map_filter(func: &Function) -> Result<Expr, String> {
+    let mut func = func.clone();
+    let Some(filter) = func.filter.take() else {
+        return Ok(Expr::Function(func));
+    };
+    let name = func.name.to_string().to_lowercase();
+    let FunctionArguments::List(list) = &mut func.args else {
+        return Err(format!("FILTER on {} is not supported by Snowflake", name));
+    };
+    match &mut list.args[..] {
+        [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] if name == "count" => {
+            let Expr::Function(mut count_if) = call("COUNT_IF", vec![*filter]) else {
+                unreachable!()
+            };
+            count_if.over = func.over;
+            Ok(Expr::Function(count_if))
+        }
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg)), ..] => {
+            *arg = Expr::Case {
+                operand: None,
+                conditions: vec![*filter],
+                results: vec![arg.clone()],
+                else_result: None,
+            };
+            Ok(Expr::Function(func))
+        }
+        _ => Err(format!("FILTER on {} is not supported by Snowflake", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{convert_interval, parse_text_array};
+    use crate::data_store::snowflake::SnowflakeMapping;
+    use crate::semantic_model::local_store::LocalSemanticModelStore;
+    use crate::sql_parser::{SqlError, SqlParser};
+    use rstest::rstest;
+
+    fn transform(sql: &str) -> Result<String, SqlError> {
+        let sql_parser = SqlParser::new(SnowflakeMapping {}, LocalSemanticModelStore::mock());
+        Ok(sql_parser.transform(sql)?.remove(0))
+    }
+
+    #[rstest]
+    #[case::distinct_on(
+        "SELECT DISTINCT ON (department_level_1) department_level_1, id FROM dm_employees ORDER BY department_level_1, id DESC",
+        "SELECT department_level_1, id FROM dm_employees QUALIFY ROW_NUMBER() OVER (PARTITION BY department_level_1 ORDER BY department_level_1, id DESC) = 1 ORDER BY department_level_1, id DESC"
+    )]
+    #[case::distinct_on_unordered(
+        "SELECT DISTINCT ON (department_level_1) department_level_1, id FROM dm_employees",
+        "SELECT department_level_1, id FROM dm_employees QUALIFY ROW_NUMBER() OVER (PARTITION BY department_level_1 ORDER BY department_level_1) = 1"
+    )]
+    #[case::distinct_on_positions(
+        "SELECT DISTINCT ON (1) department_level_1 AS d, id FROM dm_employees ORDER BY 1, 2",
+        "SELECT department_level_1 AS d, id FROM dm_employees QUALIFY ROW_NUMBER() OVER (PARTITION BY d ORDER BY d, id) = 1 ORDER BY 1, 2"
+    )]
+    #[case::distinct_on_measure(
+        "SELECT DISTINCT ON (department_level_1) department_level_1, MEASURE(dm_employees.headcount) FROM dm_employees GROUP BY department_level_1",
+        "SELECT department_level_1, COUNT(dm_employees.id) AS headcount FROM dm_employees GROUP BY department_level_1 QUALIFY ROW_NUMBER() OVER (PARTITION BY department_level_1 ORDER BY department_level_1) = 1"
+    )]
+    #[case::distinct_on_subquery(
+        "SELECT id FROM (SELECT DISTINCT ON (id) id, department_level_1 FROM dm_employees) AS e",
+        "SELECT id FROM (SELECT id, department_level_1 FROM dm_employees QUALIFY ROW_NUMBER() OVER (PARTITION BY id ORDER BY id) = 1) AS e"
+    )]
+    #[case::distinct_on_union(
+        "SELECT DISTINCT ON (id) id FROM dm_employees UNION ALL SELECT id FROM dm_employees ORDER BY id",
+        "SELECT id FROM dm_employees QUALIFY ROW_NUMBER() OVER (PARTITION BY id ORDER BY id) = 1 UNION ALL SELECT id FROM dm_employees ORDER BY id"
+    )]
+    #[case::distinct(
+        "SELECT DISTINCT department_level_1 FROM dm_employees",
+        "SELECT DISTINCT department_level_1 FROM dm_employees"
+    )]
+    fn test_query_translation(#[case] postgres: &str, #[case] snowflake: &str) {
+        assert_eq!(transform(postgres), Ok(snowflake.to_string()));
+    }
+
+    #[rstest]
+    #[case::cast("id::TEXT", "id::VARCHAR")]
+    #[case::cast_interval("'1 day'::interval", "INTERVAL '1 day'")]
+    #[case::cast_function_interval("CAST('2 hours' AS INTERVAL)", "INTERVAL '2 hours'")]
+    #[case::interval("INTERVAL '1 year'", "INTERVAL '1 year'")]
+    #[case::interval_units(
+        "INTERVAL '1 year 2 mons 3 days'",
+        "INTERVAL '1 year, 2 months, 3 days'"
+    )]
+    #[case::interval_field("INTERVAL '3' DAY", "INTERVAL '3 days'")]
+    #[case::interval_arithmetic(
+        "effective_date - INTERVAL '1 week'",
+        "effective_date - INTERVAL '1 week'"
+    )]
+    #[case::arrow("j -> 'a'", "GET_PATH(j, 'a')")]
+    #[case::arrow_index("j -> 0", "GET_PATH(j, '[0]')")]
+    #[case::arrow_chain("j -> 'a' -> 0 ->> 'b c'", "GET_PATH(j, 'a[0].\"b c\"')::VARCHAR")]
+    #[case::long_arrow("j ->> 'a'", "GET_PATH(j, 'a')::VARCHAR")]
+    #[case::arrow_expression("j -> k", "GET(j, k)")]
+    #[case::hash_arrow("j #> '{a,0,b}'", "GET_PATH(j, 'a[0].b')")]
+    #[case::hash_long_arrow("j #>> '{a}'", "GET_PATH(j, 'a')::VARCHAR")]
+    #[case::json_comparison("j ->> 'a' = 'x'", "GET_PATH(j, 'a')::VARCHAR = 'x'")]
+    #[case::any("id = ANY(ARRAY[1, 2])", "id IN (1, 2)")]
+    #[case::any_column("id = ANY(ids)", "ARRAY_CONTAINS(id::VARIANT, ids)")]
+    #[case::any_operator("id > ANY(ARRAY[1, 2])", "(id > 1 OR id > 2)")]
+    #[case::any_empty("id = ANY(ARRAY[])", "false")]
+    #[case::all("id <> ALL(ARRAY[1, 2])", "id NOT IN (1, 2)")]
+    #[case::all_column("id <> ALL(ids)", "NOT ARRAY_CONTAINS(id::VARIANT, ids)")]
+    #[case::all_operator("id < ALL(ARRAY[1, 2])", "(id < 1 AND id < 2)")]
+    #[case::ilike_any("s ILIKE ANY(ARRAY['a%', 'b%'])", "s ILIKE ANY('a%', 'b%')")]
+    #[case::like_all("s LIKE ALL(ARRAY['a%', '%b'])", "s LIKE ALL('a%', '%b')")]
+    #[case::ilike_all("s ILIKE ALL(ARRAY['a%', '%b'])", "(s ILIKE 'a%' AND s ILIKE '%b')")]
+    #[case::not_ilike_any(
+        "s NOT ILIKE ANY(ARRAY['a%', 'b%'])",
+        "(s NOT ILIKE 'a%' OR s NOT ILIKE 'b%')"
+    )]
+    #[case::ilike("s ILIKE 'a%'", "s ILIKE 'a%'")]
+    #[case::filter(
+        "sum(x) FILTER (WHERE included_in_headcount)",
+        "sum(CASE WHEN included_in_headcount THEN x END)"
+    )]
+    #[case::filter_count(
+        "count(*) FILTER (WHERE included_in_headcount)",
+        "COUNT_IF(included_in_headcount)"
+    )]
+    #[case::filter_distinct(
+        "count(DISTINCT id) FILTER (WHERE included_in_headcount)",
+        "count(DISTINCT CASE WHEN included_in_headcount THEN id END)"
+    )]
+    #[case::filter_mapped_function(
+        "string_agg(s, ',') FILTER (WHERE x > 1)",
+        "LISTAGG(CASE WHEN x > 1 THEN s END, ',')"
+    )]
+    #[case::filter_window(
+        "count(*) FILTER (WHERE x > 1) OVER (PARTITION BY id)",
+        "COUNT_IF(x > 1) OVER (PARTITION BY id)"
+    )]
+    fn test_expression_translation(#[case] postgres: &str, #[case] snowflake: &str) {
+        let sql = format!("SELECT {} AS value FROM dm_employees", postgres);
+        let expected = format!("SELECT {} AS value FROM dm_employees", snowflake);
+        assert_eq!(transform(&sql), Ok(expected));
+    }
+
+    #[test]
+    fn test_where_translation() {
+        assert_eq!(
+            transform("SELECT id FROM dm_employees WHERE department_level_1 ILIKE ANY(ARRAY['a%']) AND effective_date > now() - '1 mon'::interval"),
+            Ok("SELECT id FROM dm_employees WHERE department_level_1 ILIKE ANY('a%') AND effective_date > CURRENT_TIMESTAMP() - INTERVAL '1 month'".to_string())
+        );
+    }
+
+    #[rstest]
+    #[case::cast_interval("SELECT s::interval FROM dm_employees")]
+    #[case::interval_unit("SELECT INTERVAL '1 decade' FROM dm_employees")]
+    #[case::interval_fields("SELECT INTERVAL '1-2' YEAR TO MONTH FROM dm_employees")]
+    #[case::hash_arrow_path("SELECT j #> p FROM dm_employees")]
+    #[case::negative_index("SELECT j -> -1 FROM dm_employees")]
+    #[case::any_operator("SELECT id > ANY(ids) FROM dm_employees")]
+    #[case::ilike_any_column("SELECT s ILIKE ANY(patterns) FROM dm_employees")]
+    #[case::filter_wildcard("SELECT sum(*) FILTER (WHERE x) FROM dm_employees")]
+    #[case::distinct_on_position("SELECT DISTINCT ON (id) id FROM dm_employees ORDER BY 3")]
+    fn test_unsupported_syntax(#[case] postgres: &str) {
+        assert!(matches!(
+            transform(postgres),
+            Err(SqlError::UnsupportedSqlConstruct(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::units("1 year 2 mons 3 days", "1 year, 2 months, 3 days")]
+    #[case::abbreviations("2 hrs 30 min 10 secs", "2 hours, 30 minutes, 10 seconds")]
+    #[case::attached_units("1day 12h", "1 day, 12 hours")]
+    #[case::time("1 day 02:30:00", "1 day, 2 hours, 30 minutes")]
+    #[case::time_fraction("00:00:00.5", "0.5 seconds")]
+    #[case::time_zero("00:00", "0 seconds")]
+    #[case::negative_time("-01:30", "-1 hour, -30 minutes")]
+    #[case::ago("@ 1 year 2 days ago", "-1 year, -2 days")]
+    #[case::ago_negative("-1 day ago", "1 day")]
+    #[case::seconds("90", "90 seconds")]
+    #[case::fraction("1.5 hours", "1.5 hours")]
+    fn test_convert_interval(#[case] postgres: &str, #[case] snowflake: &str) {
+        assert_eq!(convert_interval(postgres), Ok(snowflake.to_string()));
+    }
+
+    #[test]
+    fn test_parse_text_array() {
+        assert_eq!(
+            parse_text_array("{a, 0,\"b, \\\"c\\\"\"}"),
+            Some(vec![
+                "a".to_string(),
+                "0".to_string(),
+                "b, \"c\"".to_string()
+            ])
+        );
+        assert_eq!(parse_text_array("{}"), Some(vec![]));
+        assert_eq!(parse_text_array("a,b"), None);
+        assert_eq!(parse_text_array("{\"a\"b}"), None);
+    }
+}
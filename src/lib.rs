@@ -4,6 +4,7 @@ pub mod processor;
 pub mod semantic_model;
 pub mod server;
 pub mod sql_parser;
+pub mod tls;
 pub mod auth;
 
 pub use server::ProxyServer;
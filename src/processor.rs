@@ -1,24 +1,47 @@
-use crate::auth::Authentication;
-use crate::data_store::DataStoreClient;
+use crate::auth::{Authentication, PasswordAuthStartupHandler};
+use crate::data_store::text_format::to_binary;
+use crate::data_store::{
+    encode_raw_value, encode_value, DataStoreClient, DataStoreMapping, Parameter, PoolStats,
+};
 use crate::semantic_model::SemanticModelStore;
-use crate::sql_parser::{SqlError, SqlParser};
+use crate::sql_parser::{
+    bind_parameters, parameter_count, SqlError, SqlParser, VirtualTable, SERVER_VERSION,
+};
+use crate::tls::TlsStartupHandler;
 use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::stream::BoxStream;
+use futures::{Sink, SinkExt, StreamExt, TryStreamExt};
 use log::debug;
-use pgwire::api::auth::cleartext::CleartextPasswordAuthStartupHandler;
 use pgwire::api::auth::DefaultServerParameterProvider;
-use pgwire::api::results::Response;
+use pgwire::api::portal::{Format, Portal};
+use pgwire::api::results::{
+    DescribePortalResponse, DescribeStatementResponse, FieldFormat, FieldInfo, QueryResponse,
+    Response, Tag,
+};
+use pgwire::api::stmt::{QueryParser, StoredStatement};
+use pgwire::api::store::PortalStore;
 use pgwire::api::{
     copy::NoopCopyHandler,
-    query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler},
-    PgWireHandlerFactory,
+    query::{send_execution_response, ExtendedQueryHandler, SimpleQueryHandler},
+    ClientInfo, ClientPortalStore, PgWireHandlerFactory, Type, DEFAULT_NAME, METADATA_USER,
 };
 use pgwire::error::PgWireResult;
 use pgwire::error::{ErrorInfo, PgWireError};
-use std::sync::Arc;
+use pgwire::messages::data::DataRow;
+use pgwire::messages::extendedquery::{Execute, PortalSuspended};
+use pgwire::messages::response::EmptyQueryResponse;
+use pgwire::messages::PgWireBackendMessage;
+use rust_decimal::Decimal;
+use sqlparser::ast::{Expr, Statement, Value};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::OnceCell;
+use tokio_postgres::types::FromSql;
 
-pub struct QueryHandler<D, S> {
+pub struct QueryHandler<D: DataStoreClient, S> {
     data_store: D,
-    semantic_model: S,
+    query_parser: Arc<SqlParser<D::Mapping, S>>,
 }
 
 impl<D, S> QueryHandler<D, S>
@@ -29,38 +52,175 @@ where
     pub fn new(data_store: D, semantic_model: S) -> Self {
         Self {
             data_store,
-            semantic_model,
+            query_parser: Arc::new(SqlParser::new(D::get_mapping(), semantic_model)),
         }
     }
 
-    pub async fn handle(&self, query: &str) -> PgWireResult<Vec<Response>> {
+    /// Runs each statement of the query for the proxy `user`, encoding the
+    /// result columns as `format` requests.
+    ///
+    /// Like Postgres, a failing statement ends the query with its error after
+    /// the results of the statements before it.
+    pub async fn handle(
+        &self,
+        user: &str,
+        query: &str,
+        format: &Format,
+    ) -> PgWireResult<Vec<Response<'_>>> {
         debug!("Initial query: {}", query);
-        let parser = SqlParser::new(D::get_mapping(), self.semantic_model.clone());
-        match parser.transform(query) {
-            Ok(sql) => {
+        let statements = self.query_parser.parse(query).map_err(user_error)?;
+        if statements.is_empty() {
+            return Ok(vec![Response::EmptyQuery]);
+        }
+
+        let count = statements.len();
+        let mut responses = Vec::with_capacity(count);
+        for (idx, statement) in statements.into_iter().enumerate() {
+            let mut response = self.handle_statement(user, statement, format).await;
+            if idx + 1 < count {
+                // Read the rows before running the next statement, releasing
+                // the data store connection and catching errors in the rows
+                response = match response {
+                    Ok(response) => read_rows(response).await,
+                    Err(e) => Err(e),
+                };
+            }
+            match response {
+                Ok(response) => responses.push(response),
+                Err(e) => {
+                    responses.push(error_response(e));
+                    break;
+                }
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Runs a single statement, streaming its result.
+    async fn handle_statement(
+        &self,
+        user: &str,
+        statement: Statement,
+        format: &Format,
+    ) -> PgWireResult<Response<'static>> {
+        match self.query_parser.transform_statement(statement) {
+            Ok(statement) => {
+                let sql = statement.to_string();
                 debug!("Transformed query: {}", &sql);
-                // Execute the sql and return the result
-                self.data_store.execute(&sql).await.map_err(|e| {
-                    PgWireError::UserError(Box::new(ErrorInfo::new(
-                        "SQLSTATE".to_string(),
-                        "ERROR".to_string(),
-                        e.to_string(),
-                    )))
-                })
+                self.data_store
+                    .execute_streaming(user, &sql, &[], format)
+                    .await
+                    .map_err(user_error)
             }
-            Err(SqlError::InformationSchemaResult(info)) => {
-                // TODO: Return the information schema result
-                todo!()
+            Err(SqlError::InformationSchemaResult(table)) => {
+                debug!("Answering query from information_schema");
+                virtual_table_response(table, format)
             }
-            Err(e) => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "SQLSTATE".to_string(),
-                "ERROR".to_string(),
-                e.to_string(),
-            )))),
+            Err(e) => Err(user_error(e)),
         }
     }
 }
 
+fn virtual_table_fields(table: &VirtualTable, format: &Format) -> Vec<FieldInfo> {
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| {
+            FieldInfo::new(
+                column.name.clone(),
+                None,
+                None,
+                column.data_type.clone(),
+                format.format_for(idx),
+            )
+        })
+        .collect()
+}
+
+/// Encodes a table computed by the proxy itself as a query response.
+///
+/// The values are computed as text and converted for binary format columns.
+fn virtual_table_response<'a>(table: VirtualTable, format: &Format) -> PgWireResult<Response<'a>> {
+    let fields = virtual_table_fields(&table, format);
+    let data_rows = table
+        .rows
+        .into_iter()
+        .map(|row| {
+            let mut buffer = BytesMut::new();
+            for (value, field) in row.into_iter().zip(&fields) {
+                match (value, field.format()) {
+                    (Some(text), FieldFormat::Binary) => {
+                        let raw = to_binary(field.datatype(), &text)?;
+                        encode_raw_value(&mut buffer, Some(&raw));
+                    }
+                    (value, _) => encode_value(&mut buffer, value),
+                }
+            }
+            Ok(DataRow::new(buffer, fields.len() as i16))
+        })
+        .collect::<PgWireResult<Vec<_>>>()?;
+
+    Ok(Response::Query(QueryResponse::new(
+        Arc::new(fields),
+        futures::stream::iter(data_rows.into_iter().map(Ok)),
+    )))
+}
+
+/// Sets the format the client requested on each result column.
+fn with_format(fields: Vec<FieldInfo>, format: &Format) -> Vec<FieldInfo> {
+    fields
+        .into_iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            FieldInfo::new(
+                field.name().to_owned(),
+                field.table_id(),
+                field.column_id(),
+                field.datatype().clone(),
+                format.format_for(idx),
+            )
+        })
+        .collect()
+}
+
+/// Collects the rows of a query response, failing with the first error in them.
+async fn read_rows(response: Response<'_>) -> PgWireResult<Response<'_>> {
+    let Response::Query(query) = response else {
+        return Ok(response);
+    };
+    let fields = query.row_schema();
+    let command_tag = query.command_tag().to_owned();
+    let rows = query.data_rows().try_collect::<Vec<_>>().await?;
+    let mut query = QueryResponse::new(fields, futures::stream::iter(rows.into_iter().map(Ok)));
+    query.set_command_tag(&command_tag);
+    Ok(Response::Query(query))
+}
+
+fn error_response<'a>(e: PgWireError) -> Response<'a> {
+    match e {
+        PgWireError::UserError(info) => Response::Error(info),
+        e => Response::Error(Box::new(error_info("XX000", e.to_string()))),
+    }
+}
+
+/// The user the client logged in as.
+fn client_user<C: ClientInfo>(client: &C) -> &str {
+    client
+        .metadata()
+        .get(METADATA_USER)
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+fn user_error(e: impl Into<ErrorInfo>) -> PgWireError {
+    PgWireError::UserError(Box::new(e.into()))
+}
+
+fn error_info(code: &str, message: String) -> ErrorInfo {
+    ErrorInfo::new("ERROR".to_string(), code.to_string(), message)
+}
+
 #[async_trait]
 impl<D, S> SimpleQueryHandler for QueryHandler<D, S>
 where
@@ -69,18 +229,597 @@ where
 {
     async fn do_query<'a, C>(
         &self,
-        _client: &mut C,
+        client: &mut C,
         query: &'a str,
     ) -> PgWireResult<Vec<Response<'a>>>
     where
         'life0: 'a,
+        C: ClientInfo + Unpin + Send + Sync,
     {
-        self.handle(query).await
+        self.handle(client_user(client), query, &Format::UnifiedText)
+            .await
+    }
+}
+
+/// Statement prepared through the extended query protocol.
+///
+/// The query is rewritten once when the client sends `Parse`. On every
+/// `Execute`, the parameters are sent along with the rewritten statement to
+/// data stores that bind them, or inlined into it as literals otherwise. The
+/// result columns are looked up on the first `Describe` and cached for the
+/// lifetime of the prepared statement.
+///
+/// Queries answered by the proxy keep the original statement instead, since
+/// they can only be evaluated once the parameter values are known.
+#[derive(Clone, Debug)]
+pub struct PreparedStatement {
+    statement: Option<Statement>,
+    parameter_types: Vec<Type>,
+    fields: Arc<OnceCell<Vec<FieldInfo>>>,
+    is_virtual: bool,
+    suspended: Arc<Mutex<Vec<SuspendedPortal>>>,
+}
+
+/// The rows a portal of the statement has left after an `Execute` with a row
+/// limit, which the next `Execute` of the portal continues with.
+struct SuspendedPortal {
+    /// Rebinding the portal's name creates another portal, which starts over.
+    portal: Weak<Portal<PreparedStatement>>,
+    command_tag: String,
+    rows: BoxStream<'static, PgWireResult<DataRow>>,
+}
+
+impl Debug for SuspendedPortal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SuspendedPortal")
+            .field(
+                "portal",
+                &self.portal.upgrade().map(|portal| portal.name.clone()),
+            )
+            .field("command_tag", &self.command_tag)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PreparedStatement {
+    /// Returns the statement with its parameters replaced by `values`.
+    fn bind(&self, values: &[Expr]) -> PgWireResult<Option<Statement>> {
+        self.statement
+            .as_ref()
+            .map(|statement| {
+                let mut statement = statement.clone();
+                bind_parameters(&mut statement, values).map_err(user_error)?;
+                Ok(statement)
+            })
+            .transpose()
+    }
+
+    /// Takes the command tag and rows the portal was suspended with, if any.
+    fn resume(
+        &self,
+        portal: &Arc<Portal<PreparedStatement>>,
+    ) -> Option<(String, BoxStream<'static, PgWireResult<DataRow>>)> {
+        let mut suspended = self.suspended.lock().unwrap();
+        let idx = suspended
+            .iter()
+            .position(|s| std::ptr::eq(s.portal.as_ptr(), Arc::as_ptr(portal)))?;
+        let SuspendedPortal {
+            command_tag, rows, ..
+        } = suspended.swap_remove(idx);
+        Some((command_tag, rows))
+    }
+
+    /// Keeps the rows the portal has left for its next `Execute`, dropping the
+    /// ones of portals that were closed or rebound since they were suspended,
+    /// which releases their data store connections.
+    fn suspend(
+        &self,
+        portal: &Arc<Portal<PreparedStatement>>,
+        command_tag: String,
+        rows: BoxStream<'static, PgWireResult<DataRow>>,
+    ) {
+        let mut suspended = self.suspended.lock().unwrap();
+        suspended.retain(|s| s.portal.strong_count() > 0);
+        suspended.push(SuspendedPortal {
+            portal: Arc::downgrade(portal),
+            command_tag,
+            rows,
+        });
+    }
+}
+
+#[async_trait]
+impl<M, S> QueryParser for SqlParser<M, S>
+where
+    M: crate::data_store::DataStoreMapping + Send + Sync,
+    S: SemanticModelStore + Send + Sync,
+{
+    type Statement = PreparedStatement;
+
+    async fn parse_sql(&self, sql: &str, types: &[Type]) -> PgWireResult<Self::Statement> {
+        debug!("Initial prepared query: {}", sql);
+        let (mut statements, virtual_table) = match self.transform_statements(sql) {
+            Ok(statements) => (statements, None),
+            Err(SqlError::InformationSchemaResult(table)) => {
+                (self.parse(sql).map_err(user_error)?, Some(table))
+            }
+            Err(e) => return Err(user_error(e)),
+        };
+        if statements.len() > 1 {
+            return Err(user_error(error_info(
+                "42601",
+                "cannot insert multiple commands into a prepared statement".to_string(),
+            )));
+        }
+
+        let statement = statements.pop();
+        let count = match &statement {
+            Some(statement) => parameter_count(statement).map_err(user_error)?,
+            None => 0,
+        };
+
+        // Parameters the client did not declare a type for are sent as text
+        let parameter_types = (0..count)
+            .map(|idx| types.get(idx).cloned().unwrap_or(Type::UNKNOWN))
+            .collect();
+
+        Ok(PreparedStatement {
+            statement,
+            parameter_types,
+            fields: Arc::new(OnceCell::new_with(
+                virtual_table
+                    .as_ref()
+                    .map(|table| virtual_table_fields(table, &Format::UnifiedText)),
+            )),
+            is_virtual: virtual_table.is_some(),
+            suspended: Arc::default(),
+        })
     }
 }
 
-pub struct ProcessorFactory<D, S> {
+impl<D, S> QueryHandler<D, S>
+where
+    D: DataStoreClient + Send + Sync,
+    S: SemanticModelStore + Send + Sync,
+{
+    /// Runs the portal's statement with its parameters.
+    async fn execute_portal(
+        &self,
+        user: &str,
+        portal: &Portal<PreparedStatement>,
+    ) -> PgWireResult<Response<'static>> {
+        let statement = &portal.statement.statement;
+        if statement.is_virtual {
+            // The proxy evaluates the literals itself, so they need no escaping
+            let values = decode_parameters(portal, Value::SingleQuotedString)?;
+            let Some(bound) = statement.bind(&values)? else {
+                return Ok(Response::EmptyQuery);
+            };
+            return self
+                .handle_statement(user, bound, &portal.result_column_format)
+                .await;
+        }
+
+        let (sql, parameters) = if self.data_store.binds_parameters() {
+            let Some(query) = &statement.statement else {
+                return Ok(Response::EmptyQuery);
+            };
+            (query.to_string(), portal_parameters(portal)?)
+        } else {
+            let mapping = self.query_parser.mapping();
+            let values = decode_parameters(portal, |value| mapping.string_literal(value))?;
+            let Some(bound) = statement.bind(&values)? else {
+                return Ok(Response::EmptyQuery);
+            };
+            (bound.to_string(), vec![])
+        };
+
+        debug!("Transformed prepared query: {}", &sql);
+        self.data_store
+            .execute_streaming(user, &sql, &parameters, &portal.result_column_format)
+            .await
+            .map_err(user_error)
+    }
+
+    /// Returns the result columns of the statement, asking the data store on first use.
+    async fn describe_fields(
+        &self,
+        user: &str,
+        statement: &PreparedStatement,
+    ) -> PgWireResult<Vec<FieldInfo>> {
+        let fields = statement
+            .fields
+            .get_or_try_init(|| async {
+                if self.data_store.binds_parameters() {
+                    return match &statement.statement {
+                        Some(query) => self
+                            .data_store
+                            .describe(user, &query.to_string(), &statement.parameter_types)
+                            .await
+                            .map_err(user_error),
+                        None => Ok(vec![]),
+                    };
+                }
+                // Bind NULLs since the parameter values are not known yet
+                let nulls = vec![Expr::Value(Value::Null); statement.parameter_types.len()];
+                match statement.bind(&nulls)? {
+                    Some(statement) => self
+                        .data_store
+                        .describe(user, &statement.to_string(), &[])
+                        .await
+                        .map_err(user_error),
+                    None => Ok(vec![]),
+                }
+            })
+            .await?;
+
+        Ok(fields.clone())
+    }
+}
+
+#[async_trait]
+impl<D, S> ExtendedQueryHandler for QueryHandler<D, S>
+where
+    D: DataStoreClient + Send + Sync,
+    S: SemanticModelStore + Send + Sync,
+{
+    type Statement = PreparedStatement;
+    type QueryParser = SqlParser<D::Mapping, S>;
+
+    fn query_parser(&self) -> Arc<Self::QueryParser> {
+        self.query_parser.clone()
+    }
+
+    async fn do_describe_statement<C>(
+        &self,
+        client: &mut C,
+        target: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<DescribeStatementResponse>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let fields = self
+            .describe_fields(client_user(client), &target.statement)
+            .await?;
+        Ok(DescribeStatementResponse::new(
+            target.statement.parameter_types.clone(),
+            fields,
+        ))
+    }
+
+    async fn do_describe_portal<C>(
+        &self,
+        client: &mut C,
+        target: &Portal<Self::Statement>,
+    ) -> PgWireResult<DescribePortalResponse>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let fields = self
+            .describe_fields(client_user(client), &target.statement.statement)
+            .await?;
+        Ok(DescribePortalResponse::new(with_format(
+            fields,
+            &target.result_column_format,
+        )))
+    }
+
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        portal: &'a Portal<Self::Statement>,
+        _max_rows: usize,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        // The row limit is applied by `on_execute`, which suspends the portal
+        self.execute_portal(client_user(client), portal).await
+    }
+
+    /// Runs the portal, or resumes it where the previous `Execute` stopped, and
+    /// sends at most `max_rows` of its rows when that is not zero. Like Postgres,
+    /// a portal with rows left is suspended until the client executes it again.
+    async fn on_execute<C>(&self, client: &mut C, message: Execute) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let portal_name = message.name.as_deref().unwrap_or(DEFAULT_NAME);
+        let portal = client
+            .portal_store()
+            .get_portal(portal_name)
+            .ok_or_else(|| PgWireError::PortalNotFound(portal_name.to_owned()))?;
+
+        let (command_tag, rows) = match portal.statement.statement.resume(&portal) {
+            Some(suspended) => suspended,
+            None => match self.execute_portal(client_user(client), &portal).await? {
+                Response::Query(query) => (query.command_tag().to_owned(), query.data_rows()),
+                Response::EmptyQuery => {
+                    client
+                        .feed(PgWireBackendMessage::EmptyQueryResponse(
+                            EmptyQueryResponse::new(),
+                        ))
+                        .await?;
+                    return Ok(());
+                }
+                Response::Execution(tag) => return send_execution_response(client, tag).await,
+                Response::Error(err) => {
+                    client
+                        .send(PgWireBackendMessage::ErrorResponse((*err).into()))
+                        .await?;
+                    return Ok(());
+                }
+                Response::CopyIn(_) | Response::CopyOut(_) | Response::CopyBoth(_) => {
+                    return Err(user_error(error_info(
+                        "0A000",
+                        "COPY is not supported".to_string(),
+                    )));
+                }
+            },
+        };
+
+        let max_rows = message.max_rows.max(0) as usize;
+        if let Some(rows) = send_rows(client, &command_tag, rows, max_rows).await? {
+            portal
+                .statement
+                .statement
+                .suspend(&portal, command_tag, rows);
+        }
+        Ok(())
+    }
+}
+
+/// Sends the rows, at most `max_rows` of them unless it is zero, and completes
+/// the command once they run out. Otherwise the portal is reported suspended
+/// and the rows left are returned.
+async fn send_rows<C>(
+    client: &mut C,
+    command_tag: &str,
+    mut rows: BoxStream<'static, PgWireResult<DataRow>>,
+    max_rows: usize,
+) -> PgWireResult<Option<BoxStream<'static, PgWireResult<DataRow>>>>
+where
+    C: Sink<PgWireBackendMessage> + Unpin,
+    PgWireError: From<C::Error>,
+{
+    let mut sent = 0;
+    while max_rows == 0 || sent < max_rows {
+        match rows.next().await {
+            Some(row) => {
+                client.feed(PgWireBackendMessage::DataRow(row?)).await?;
+                sent += 1;
+            }
+            None => break,
+        }
+    }
+
+    // Only suspend when rows are left, so the data store is released right away otherwise
+    let next = match max_rows > 0 && sent == max_rows {
+        true => rows.next().await,
+        false => None,
+    };
+    match next {
+        Some(row) => {
+            client
+                .send(PgWireBackendMessage::PortalSuspended(PortalSuspended::new()))
+                .await?;
+            Ok(Some(
+                futures::stream::once(async { row }).chain(rows).boxed(),
+            ))
+        }
+        None => {
+            let tag = Tag::new(command_tag).with_rows(sent);
+            client
+                .send(PgWireBackendMessage::CommandComplete(tag.into()))
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// The parameters bound to the portal, as the client sent them.
+fn portal_parameters(portal: &Portal<PreparedStatement>) -> PgWireResult<Vec<Parameter>> {
+    let statement = &portal.statement.statement;
+    statement
+        .parameter_types
+        .iter()
+        .enumerate()
+        .map(|(idx, pg_type)| {
+            let value = portal
+                .parameters
+                .get(idx)
+                .ok_or(PgWireError::ParameterIndexOutOfBound(idx))?;
+            Ok(Parameter {
+                pg_type: pg_type.clone(),
+                value: value.clone(),
+                format: portal.parameter_format.format_for(idx),
+            })
+        })
+        .collect()
+}
+
+/// Decodes the parameters bound to the portal into SQL literals, strings
+/// being quoted by `string_literal`.
+fn decode_parameters(
+    portal: &Portal<PreparedStatement>,
+    string_literal: impl Fn(String) -> Value,
+) -> PgWireResult<Vec<Expr>> {
+    portal
+        .statement
+        .statement
+        .parameter_types
+        .iter()
+        .enumerate()
+        .map(|(idx, pg_type)| decode_parameter(portal, idx, pg_type, &string_literal))
+        .collect()
+}
+
+/// Decodes a bound parameter into a SQL literal.
+///
+/// Text format parameters are validated against their declared type so they can
+/// be inlined safely, binary format parameters are decoded from their Postgres
+/// wire representation.
+fn decode_parameter<S>(
+    portal: &Portal<S>,
+    idx: usize,
+    pg_type: &Type,
+    string_literal: &impl Fn(String) -> Value,
+) -> PgWireResult<Expr> {
+    let Some(raw) = portal
+        .parameters
+        .get(idx)
+        .ok_or(PgWireError::ParameterIndexOutOfBound(idx))?
+    else {
+        return Ok(Expr::Value(Value::Null));
+    };
+
+    let value = if portal.parameter_format.is_binary(idx) {
+        decode_binary_parameter(raw, pg_type, string_literal)?
+    } else {
+        let text = String::from_utf8(raw.to_vec())
+            .map_err(|e| PgWireError::FailedToParseParameter(Box::new(e)))?;
+        decode_text_parameter(text, pg_type, string_literal)?
+    };
+
+    // Keep negative numbers apart from operators before them, `- -1` and not `--1`
+    match value {
+        Value::Number(number, _) if number.starts_with('-') => Ok(Expr::Nested(Box::new(
+            Expr::Value(Value::Number(number, false)),
+        ))),
+        value => Ok(Expr::Value(value)),
+    }
+}
+
+fn decode_text_parameter(
+    text: String,
+    pg_type: &Type,
+    string_literal: &impl Fn(String) -> Value,
+) -> PgWireResult<Value> {
+    match *pg_type {
+        Type::INT2
+        | Type::INT4
+        | Type::INT8
+        | Type::OID
+        | Type::FLOAT4
+        | Type::FLOAT8
+        | Type::NUMERIC => {
+            if !is_number(&text) {
+                return Err(user_error(error_info(
+                    "22P02",
+                    format!(
+                        "invalid input syntax for type {}: \"{}\"",
+                        pg_type.name(),
+                        text
+                    ),
+                )));
+            }
+            Ok(Value::Number(text, false))
+        }
+        Type::BOOL => match text.to_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => Ok(Value::Boolean(true)),
+            "f" | "false" | "n" | "no" | "off" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(user_error(error_info(
+                "22P02",
+                format!("invalid input syntax for type boolean: \"{}\"", text),
+            ))),
+        },
+        _ => Ok(string_literal(text)),
+    }
+}
+
+/// Whether the text is a plain decimal number, optionally signed and with an
+/// exponent, which can be inlined as is. Words such as `NaN` or `infinity`
+/// would be read as identifiers.
+fn is_number(text: &str) -> bool {
+    fn is_digits(text: &str) -> bool {
+        text.bytes().all(|b| b.is_ascii_digit())
+    }
+    fn unsigned(text: &str) -> &str {
+        text.strip_prefix(['+', '-']).unwrap_or(text)
+    }
+
+    let (mantissa, exponent) = match unsigned(text).split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(unsigned(exponent))),
+        None => (unsigned(text), None),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    !(integer.is_empty() && fraction.is_empty())
+        && is_digits(integer)
+        && is_digits(fraction)
+        && exponent.is_none_or(|exponent| !exponent.is_empty() && is_digits(exponent))
+}
+
+fn decode_binary_parameter(
+    raw: &[u8],
+    pg_type: &Type,
+    string_literal: &impl Fn(String) -> Value,
+) -> PgWireResult<Value> {
+    fn decode<'a, T: FromSql<'a>>(pg_type: &Type, raw: &'a [u8]) -> PgWireResult<T> {
+        T::from_sql(pg_type, raw).map_err(PgWireError::FailedToParseParameter)
+    }
+    fn finite(pg_type: &Type, value: f64) -> PgWireResult<Value> {
+        match value.is_finite() {
+            true => Ok(Value::Number(value.to_string(), false)),
+            false => Err(user_error(error_info(
+                "0A000",
+                format!(
+                    "{} parameters can't be {} when inlined into the query",
+                    pg_type.name(),
+                    value
+                ),
+            ))),
+        }
+    }
+
+    let value = match *pg_type {
+        Type::INT2 => Value::Number(decode::<i16>(pg_type, raw)?.to_string(), false),
+        Type::INT4 => Value::Number(decode::<i32>(pg_type, raw)?.to_string(), false),
+        Type::INT8 => Value::Number(decode::<i64>(pg_type, raw)?.to_string(), false),
+        Type::OID => Value::Number(decode::<u32>(pg_type, raw)?.to_string(), false),
+        Type::FLOAT4 => finite(pg_type, decode::<f32>(pg_type, raw)?.into())?,
+        Type::FLOAT8 => finite(pg_type, decode::<f64>(pg_type, raw)?)?,
+        Type::NUMERIC => Value::Number(decode::<Decimal>(pg_type, raw)?.to_string(), false),
+        Type::BOOL => Value::Boolean(decode::<bool>(pg_type, raw)?),
+        Type::DATE => string_literal(decode::<chrono::NaiveDate>(pg_type, raw)?.to_string()),
+        Type::TIMESTAMP => {
+            string_literal(decode::<chrono::NaiveDateTime>(pg_type, raw)?.to_string())
+        }
+        Type::TIMESTAMPTZ => {
+            string_literal(decode::<chrono::DateTime<chrono::Utc>>(pg_type, raw)?.to_rfc3339())
+        }
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN => {
+            string_literal(decode::<String>(pg_type, raw)?)
+        }
+        _ => {
+            return Err(user_error(error_info(
+                "0A000",
+                format!(
+                    "binary format is not supported for parameters of type {}",
+                    pg_type.name()
+                ),
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+pub struct ProcessorFactory<D: DataStoreClient, S> {
     handler: Arc<QueryHandler<D, S>>,
+    authentication: Arc<Authentication>,
+    tls_required: bool,
 }
 
 impl<D, S> ProcessorFactory<D, S>
@@ -91,8 +830,21 @@ where
     pub fn new(data_store: D, semantic_model: S) -> Self {
         Self {
             handler: Arc::new(QueryHandler::new(data_store, semantic_model)),
+            authentication: Arc::new(Authentication::from_env()),
+            tls_required: false,
         }
     }
+
+    /// Refuses clients that connect without TLS.
+    pub fn with_tls_required(mut self, tls_required: bool) -> Self {
+        self.tls_required = tls_required;
+        self
+    }
+
+    /// Usage of the DataStore's connection pool, if it has one.
+    pub fn pool_stats(&self) -> Option<PoolStats> {
+        self.handler.data_store.pool_stats()
+    }
 }
 
 impl<D, S> PgWireHandlerFactory for ProcessorFactory<D, S>
@@ -101,9 +853,9 @@ where
     S: SemanticModelStore + Send + Sync,
 {
     type StartupHandler =
-        CleartextPasswordAuthStartupHandler<Authentication, DefaultServerParameterProvider>;
+        TlsStartupHandler<PasswordAuthStartupHandler<DefaultServerParameterProvider>>;
     type SimpleQueryHandler = QueryHandler<D, S>;
-    type ExtendedQueryHandler = PlaceholderExtendedQueryHandler;
+    type ExtendedQueryHandler = QueryHandler<D, S>;
     type CopyHandler = NoopCopyHandler;
 
     fn simple_query_handler(&self) -> Arc<Self::SimpleQueryHandler> {
@@ -111,13 +863,16 @@ where
     }
 
     fn extended_query_handler(&self) -> Arc<Self::ExtendedQueryHandler> {
-        Arc::new(PlaceholderExtendedQueryHandler)
+        self.handler.clone()
     }
 
     fn startup_handler(&self) -> Arc<Self::StartupHandler> {
-        Arc::new(CleartextPasswordAuthStartupHandler::new(
-            Authentication::from_env(),
-            DefaultServerParameterProvider::default(),
+        // Clients like psql pick their catalog queries based on the server version
+        let mut parameters = DefaultServerParameterProvider::default();
+        parameters.server_version = SERVER_VERSION.to_string();
+        Arc::new(TlsStartupHandler::new(
+            PasswordAuthStartupHandler::new(self.authentication.clone(), parameters),
+            self.tls_required,
         ))
     }
 
@@ -125,3 +880,115 @@ where
         Arc::new(NoopCopyHandler)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_store::snowflake::SnowflakeMapping;
+    use rstest::rstest;
+
+    fn decode(text: &str, pg_type: &Type) -> PgWireResult<Value> {
+        let string_literal = |value| SnowflakeMapping {}.string_literal(value);
+        decode_text_parameter(text.to_string(), pg_type, &string_literal)
+    }
+
+    #[rstest]
+    #[case::integer("42", true)]
+    #[case::negative("-42", true)]
+    #[case::signed("+4.2", true)]
+    #[case::fraction(".5", true)]
+    #[case::trailing_point("5.", true)]
+    #[case::exponent("1.5e-3", true)]
+    #[case::nan("NaN", false)]
+    #[case::infinity("inf", false)]
+    #[case::infinity_word("-Infinity", false)]
+    #[case::empty("", false)]
+    #[case::point(".", false)]
+    #[case::empty_exponent("1e", false)]
+    #[case::expression("1 OR 1=1", false)]
+    #[case::hex("0x10", false)]
+    fn test_is_number(#[case] text: &str, #[case] expected: bool) {
+        assert_eq!(is_number(text), expected);
+    }
+
+    #[rstest]
+    #[case::number("42", Type::INT4, "42")]
+    #[case::string(r"\'; DROP TABLE t --", Type::TEXT, r"'\\\'; DROP TABLE t --'")]
+    #[case::boolean("yes", Type::BOOL, "true")]
+    fn test_decode_text_parameter(#[case] text: &str, #[case] pg_type: Type, #[case] sql: &str) {
+        assert_eq!(decode(text, &pg_type).unwrap().to_string(), sql);
+    }
+
+    #[rstest]
+    #[case::nan("NaN", Type::FLOAT8)]
+    #[case::infinity("infinity", Type::NUMERIC)]
+    #[case::injection("1; DROP TABLE t", Type::INT4)]
+    fn test_invalid_number_parameter(#[case] text: &str, #[case] pg_type: Type) {
+        assert!(decode(text, &pg_type).is_err());
+    }
+
+    fn data_rows(count: usize) -> BoxStream<'static, PgWireResult<DataRow>> {
+        let rows = (0..count).map(|idx| {
+            let mut buffer = BytesMut::new();
+            encode_value(&mut buffer, Some(idx.to_string()));
+            Ok(DataRow::new(buffer, 1))
+        });
+        futures::stream::iter(rows).boxed()
+    }
+
+    /// Runs `send_rows` and returns the messages sent, with data rows by their value.
+    async fn sent_messages(
+        rows: BoxStream<'static, PgWireResult<DataRow>>,
+        max_rows: usize,
+    ) -> (
+        Vec<String>,
+        Option<BoxStream<'static, PgWireResult<DataRow>>>,
+    ) {
+        let mut sink =
+            Vec::new().sink_map_err(|e: std::convert::Infallible| -> PgWireError { match e {} });
+        let rows = send_rows(&mut sink, "SELECT", rows, max_rows)
+            .await
+            .unwrap();
+        let messages = sink
+            .into_inner()
+            .into_iter()
+            .map(|message| match message {
+                PgWireBackendMessage::DataRow(row) => {
+                    String::from_utf8(row.data[4..].to_vec()).unwrap()
+                }
+                PgWireBackendMessage::CommandComplete(complete) => complete.tag,
+                PgWireBackendMessage::PortalSuspended(_) => "suspended".to_string(),
+                message => format!("{:?}", message),
+            })
+            .collect();
+        (messages, rows)
+    }
+
+    #[rstest]
+    #[case::unlimited(3, 0, vec!["0", "1", "2", "SELECT 3"])]
+    #[case::under_limit(2, 5, vec!["0", "1", "SELECT 2"])]
+    #[case::exact_limit(2, 2, vec!["0", "1", "SELECT 2"])]
+    #[case::empty(0, 2, vec!["SELECT 0"])]
+    #[case::over_limit(3, 2, vec!["0", "1", "suspended"])]
+    #[tokio::test]
+    async fn test_send_rows(
+        #[case] count: usize,
+        #[case] max_rows: usize,
+        #[case] expected: Vec<&str>,
+    ) {
+        let (messages, rows) = sent_messages(data_rows(count), max_rows).await;
+        assert_eq!(messages, expected);
+        assert_eq!(rows.is_some(), expected.last() == Some(&"suspended"));
+    }
+
+    #[tokio::test]
+    async fn test_resume_suspended_rows() {
+        let (first, rows) = sent_messages(data_rows(5), 2).await;
+        assert_eq!(first, vec!["0", "1", "suspended"]);
+        let (second, rows) = sent_messages(rows.unwrap(), 2).await;
+        assert_eq!(second, vec!["2", "3", "suspended"]);
+        let (last, rows) = sent_messages(rows.unwrap(), 2).await;
+        assert_eq!(last, vec!["4", "SELECT 1"]);
+        assert!(rows.is_none());
+    }
+}
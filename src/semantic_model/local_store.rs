@@ -5,7 +5,7 @@ use super::{Dimension, Measure, SemanticModel, SemanticModelStore, SemanticModel
 use crate::config::SemanticModelJSONConfig;
 use log::warn;
 use log::{debug, error};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
@@ -127,7 +127,7 @@ impl SemanticModelStore for LocalSemanticModelStore {
     fn get_semantic_model(&self, name: &str) -> Result<SemanticModel, SemanticModelStoreError> {
         match self.semantic_models.get(name) {
             Some(model) => Ok(model.clone()),
-            None => Err(SemanticModelStoreError::MeasureNotFound),
+            None => Err(SemanticModelStoreError::ModelNotFound),
         }
     }
 
@@ -30,6 +30,16 @@ impl Measure {
             Measure::Derived(derived) => &derived.name,
         }
     }
+
+    /// Declared data type of the measure, derived measures don't declare one.
+    pub fn data_type(&self) -> Option<&str> {
+        match self {
+            Measure::Simple(simple) => Some(&simple.data_type),
+            Measure::Ratio(ratio) => Some(&ratio.data_type),
+            Measure::Cumulative(cumulative) => Some(&cumulative.data_type),
+            Measure::Derived(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -3,7 +3,7 @@ pub mod measure;
 pub mod s3_store;
 
 use measure::Measure;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 use serde::{Deserialize, Serialize};
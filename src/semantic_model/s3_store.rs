@@ -2,7 +2,7 @@ use crate::config::S3Config;
 
 use super::{Measure, SemanticModel, SemanticModelStore, SemanticModelStoreError};
 use aws_sdk_s3::{config::BehaviorVersion, Client};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::BTreeMap;
 use std::error::Error;
 use tokio::runtime::Runtime;
 
@@ -76,7 +76,7 @@ impl SemanticModelStore for S3SemanticModelStore {
                     let semantic_model = serde_json::from_str(&json).unwrap();
                     Ok(semantic_model)
                 }
-                None => Err(SemanticModelStoreError::MeasureNotFound),
+                None => Err(SemanticModelStoreError::ModelNotFound),
             }
         })
     }
@@ -2,12 +2,18 @@ use crate::config::Config;
 use crate::data_store::DataStoreClient;
 use crate::processor::ProcessorFactory;
 use crate::semantic_model::SemanticModelStore;
-use log::{error, info};
+use crate::tls::ReloadableTlsAcceptor;
+use log::{error, info, warn};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::signal;
+use tokio::signal::unix::{signal as unix_signal, SignalKind};
 
-pub struct ProxyServer<D, S> {
+/// How often the connection pool usage is logged.
+const POOL_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct ProxyServer<D: DataStoreClient, S> {
     pub config: Config,
     pub factory: Arc<ProcessorFactory<D, S>>,
 }
@@ -18,6 +24,7 @@ where
     S: SemanticModelStore + Send + Sync + 'static,
 {
     pub fn new(config: Config, factory: ProcessorFactory<D, S>) -> Self {
+        let factory = factory.with_tls_required(config.tls_required);
         Self {
             config,
             factory: Arc::new(factory),
@@ -28,6 +35,13 @@ where
         let server_address = format!("{}:{}", self.config.server_host, self.config.server_port);
         info!("Starting server at {}", server_address);
 
+        let tls = self.load_tls()?;
+        if let Some(tls) = &tls {
+            Self::reload_tls_on_hangup(tls.clone())?;
+        }
+
+        self.log_pool_stats();
+
         let listener = TcpListener::bind(&server_address).await.map_err(|err| {
             error!("Failed to bind server address: {}", err);
             err
@@ -38,7 +52,7 @@ where
         // Gracefully handle shutdown signals
         let signal_future = signal::ctrl_c();
         tokio::select! {
-            _ = self.accept_connections(listener) => {},
+            _ = self.accept_connections(listener, tls) => {},
             _ = signal_future => {
                 info!("Shutdown signal received. Stopping server...");
             }
@@ -47,15 +61,69 @@ where
         Ok(())
     }
 
-    async fn accept_connections(&self, listener: TcpListener) {
+    fn load_tls(&self) -> Result<Option<Arc<ReloadableTlsAcceptor>>, Box<dyn std::error::Error>> {
+        match (&self.config.tls_cert_path, &self.config.tls_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                let tls = ReloadableTlsAcceptor::load(cert_path, key_path).map_err(|err| {
+                    error!("Failed to load TLS certificate: {}", err);
+                    err
+                })?;
+                Ok(Some(Arc::new(tls)))
+            }
+            (None, None) if !self.config.tls_required => Ok(None),
+            _ => {
+                let message = "TLS_CERT_PATH and TLS_KEY_PATH must both be set to enable TLS";
+                error!("{}", message);
+                Err(message.into())
+            }
+        }
+    }
+
+    /// Reloads the TLS certificate and key on SIGHUP, like Postgres does.
+    fn reload_tls_on_hangup(tls: Arc<ReloadableTlsAcceptor>) -> std::io::Result<()> {
+        let mut hangup = unix_signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if let Err(e) = tls.reload() {
+                    warn!("Keeping the current TLS certificate: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Logs the DataStore's connection pool usage periodically, for monitoring.
+    fn log_pool_stats(&self) {
+        if self.factory.pool_stats().is_none() {
+            return;
+        }
+        let factory = self.factory.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(POOL_STATS_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Some(stats) = factory.pool_stats() {
+                    info!("Connection pool: {}", stats);
+                }
+            }
+        });
+    }
+
+    async fn accept_connections(
+        &self,
+        listener: TcpListener,
+        tls: Option<Arc<ReloadableTlsAcceptor>>,
+    ) {
         loop {
             match listener.accept().await {
                 Ok((tcp_stream, addr)) => {
                     info!("New connection accepted from: {}", addr);
                     let factory_ref = self.factory.clone();
+                    let tls_acceptor = tls.as_ref().map(|tls| tls.acceptor());
                     tokio::spawn(async move {
                         if let Err(e) =
-                            pgwire::tokio::process_socket(tcp_stream, None, factory_ref).await
+                            pgwire::tokio::process_socket(tcp_stream, tls_acceptor, factory_ref)
+                                .await
                         {
                             error!("Error processing socket: {}", e);
                         }
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{visit_expressions, visit_relations, Expr, ObjectName, Query};
+
+use super::virtual_table::{Scalar, VirtualCatalog, VirtualTable};
+use super::{information_schema, pg_catalog, SqlError};
+use crate::semantic_model::{SemanticModel, SemanticModelStore};
+
+/// The `information_schema` and `pg_catalog` schemas, synthesized from the
+/// semantic models so that clients can introspect them.
+pub struct Catalog {
+    models: BTreeMap<String, SemanticModel>,
+}
+
+impl Catalog {
+    pub fn new<S: SemanticModelStore>(semantic_model: &S) -> Result<Self, SqlError> {
+        let models = semantic_model
+            .get_all_semantic_models()
+            .map_err(|e| SqlError::SemanticModelError(e.to_string()))?;
+        Ok(Self { models })
+    }
+}
+
+impl VirtualCatalog for Catalog {
+    fn table(&self, name: &[String]) -> Result<Option<VirtualTable>, SqlError> {
+        match name {
+            [schema, table] if schema == "information_schema" => {
+                information_schema::table(table, &self.models).map(Some)
+            }
+            [schema, table] if schema == "pg_catalog" => {
+                pg_catalog::table(table, &self.models).map(Some)
+            }
+            // pg_catalog is always first on the search path
+            [table] if pg_catalog::is_table(table) => {
+                pg_catalog::table(table, &self.models).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Scalar]) -> Option<Result<Scalar, SqlError>> {
+        pg_catalog::call(name, args, &self.models)
+    }
+}
+
+/// Returns true when the query should be answered from the [`Catalog`].
+///
+/// That is the case when it reads from a catalog table, or when it doesn't
+/// read from any table but calls a catalog function like `version()`.
+pub fn is_catalog_query(query: &Query) -> bool {
+    let mut has_relations = false;
+    let mut reads_catalog = false;
+    let _ = visit_relations(query, |name| {
+        has_relations = true;
+        reads_catalog |= is_catalog_relation(name);
+        ControlFlow::<()>::Continue(())
+    });
+    if has_relations {
+        return reads_catalog;
+    }
+
+    let mut calls_catalog = false;
+    let _ = visit_expressions(query, |expr| {
+        calls_catalog |= match expr {
+            Expr::Function(func) => func
+                .name
+                .0
+                .last()
+                .is_some_and(|ident| pg_catalog::is_function(&ident.value.to_lowercase())),
+            Expr::Identifier(ident) => ident.value.eq_ignore_ascii_case("current_schema"),
+            _ => false,
+        };
+        ControlFlow::<()>::Continue(())
+    });
+    calls_catalog
+}
+
+fn is_catalog_relation(name: &ObjectName) -> bool {
+    let names: Vec<String> = name.0.iter().map(|i| i.value.to_lowercase()).collect();
+    match &names[..] {
+        [.., schema, _] => schema == "information_schema" || schema == "pg_catalog",
+        [table] => pg_catalog::is_table(table),
+        [] => false,
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use tokio_postgres::types::Type;
+
+use super::virtual_table::{VirtualColumn, VirtualTable};
+use super::SqlError;
+use crate::semantic_model::SemanticModel;
+
+/// Catalog reported for the semantic models.
+pub const CATALOG_NAME: &str = "main";
+
+/// Schema the semantic models are exposed in.
+pub const SCHEMA_NAME: &str = "public";
+
+/// Builds the virtual `information_schema` table with the given name.
+pub fn table(
+    name: &str,
+    models: &BTreeMap<String, SemanticModel>,
+) -> Result<VirtualTable, SqlError> {
+    match name {
+        "tables" => Ok(tables(models)),
+        "columns" => Ok(columns(models)),
+        _ => Err(SqlError::UnsupportedSqlConstruct(format!(
+            "information_schema.{} is not supported",
+            name
+        ))),
+    }
+}
+
+/// `information_schema.tables` listing every semantic model as a table.
+fn tables(models: &BTreeMap<String, SemanticModel>) -> VirtualTable {
+    VirtualTable {
+        columns: vec![
+            VirtualColumn::new("table_catalog", Type::NAME),
+            VirtualColumn::new("table_schema", Type::NAME),
+            VirtualColumn::new("table_name", Type::NAME),
+            VirtualColumn::new("table_type", Type::VARCHAR),
+        ],
+        rows: models
+            .keys()
+            .map(|name| {
+                vec![
+                    Some(CATALOG_NAME.to_string()),
+                    Some(SCHEMA_NAME.to_string()),
+                    Some(name.clone()),
+                    Some("BASE TABLE".to_string()),
+                ]
+            })
+            .collect(),
+    }
+}
+
+/// `information_schema.columns` listing the dimensions of every semantic model
+/// followed by its measures, which are flagged through `is_measure`.
+fn columns(models: &BTreeMap<String, SemanticModel>) -> VirtualTable {
+    let mut rows = Vec::new();
+    for (name, model) in models {
+        let dimensions = model
+            .dimensions
+            .iter()
+            .map(|dimension| (dimension.name.as_str(), dimension.data_type.as_str(), false));
+        // Derived measures combine other measures, which are numeric
+        let measures = model.measures.iter().map(|measure| {
+            (
+                measure.name(),
+                measure.data_type().unwrap_or("number"),
+                true,
+            )
+        });
+
+        for (position, (column, data_type, is_measure)) in dimensions.chain(measures).enumerate() {
+            let data_type = postgres_type(data_type);
+            rows.push(vec![
+                Some(CATALOG_NAME.to_string()),
+                Some(SCHEMA_NAME.to_string()),
+                Some(name.clone()),
+                Some(column.to_string()),
+                Some((position + 1).to_string()),
+                None,
+                Some("YES".to_string()),
+                Some(sql_type_name(&data_type).to_string()),
+                Some(data_type.name().to_string()),
+                Some(if is_measure { "YES" } else { "NO" }.to_string()),
+            ]);
+        }
+    }
+
+    VirtualTable {
+        columns: vec![
+            VirtualColumn::new("table_catalog", Type::NAME),
+            VirtualColumn::new("table_schema", Type::NAME),
+            VirtualColumn::new("table_name", Type::NAME),
+            VirtualColumn::new("column_name", Type::NAME),
+            VirtualColumn::new("ordinal_position", Type::INT4),
+            VirtualColumn::new("column_default", Type::VARCHAR),
+            VirtualColumn::new("is_nullable", Type::VARCHAR),
+            VirtualColumn::new("data_type", Type::VARCHAR),
+            VirtualColumn::new("udt_name", Type::NAME),
+            VirtualColumn::new("is_measure", Type::VARCHAR),
+        ],
+        rows,
+    }
+}
+
+/// Maps a semantic model data type to the Postgres type it is exposed as.
+///
+/// Unknown data types are exposed as `text`.
+pub fn postgres_type(data_type: &str) -> Type {
+    match data_type.to_lowercase().as_str() {
+        "boolean" | "bool" => Type::BOOL,
+        "smallint" => Type::INT2,
+        "integer" | "int" => Type::INT4,
+        "bigint" => Type::INT8,
+        "float" | "double" => Type::FLOAT8,
+        "number" | "numeric" | "decimal" => Type::NUMERIC,
+        "date" => Type::DATE,
+        "time" => Type::TIME,
+        "timestamp" => Type::TIMESTAMP,
+        "timestamptz" => Type::TIMESTAMPTZ,
+        _ => Type::TEXT,
+    }
+}
+
+/// The SQL standard name of a type, as reported by `information_schema` and
+/// `format_type`.
+pub fn sql_type_name(data_type: &Type) -> &str {
+    match *data_type {
+        Type::BOOL => "boolean",
+        Type::INT2 => "smallint",
+        Type::INT4 => "integer",
+        Type::INT8 => "bigint",
+        Type::FLOAT4 => "real",
+        Type::FLOAT8 => "double precision",
+        Type::NUMERIC => "numeric",
+        Type::VARCHAR => "character varying",
+        Type::BPCHAR => "character",
+        Type::DATE => "date",
+        Type::TIME => "time without time zone",
+        Type::TIMESTAMP => "timestamp without time zone",
+        Type::TIMESTAMPTZ => "timestamp with time zone",
+        _ => data_type.name(),
+    }
+}
@@ -1,8 +1,19 @@
+mod catalog;
+mod information_schema;
+mod parameters;
+mod pg_catalog;
 mod transformations;
+mod virtual_table;
+
+pub use parameters::{bind_parameters, parameter_count};
+pub use pg_catalog::SERVER_VERSION;
+pub use virtual_table::{VirtualColumn, VirtualTable};
 
 use crate::data_store::DataStoreMapping;
 use crate::semantic_model::SemanticModelStore;
+use pgwire::error::ErrorInfo;
 use sqlparser::ast::*;
+use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::Parser;
 use thiserror::Error;
 
@@ -24,22 +35,83 @@ pub enum SqlError {
     SqlColumnNotFoundError(String, String),
 
     #[error("Information schema result: {0:?}")]
-    InformationSchemaResult(Vec<String>),
+    InformationSchemaResult(VirtualTable),
 
     #[error("Invalid MEASURE function: {0}")]
     InvalidMeasureFunction(String),
 
+    #[error("MEASURE is not allowed in {0}")]
+    MeasureNotAllowed(String),
+
     #[error("Invalid function argument: {0}")]
     InvalidFunctionArgument(String),
 
     #[error("Semantic model error: {0}")]
     SemanticModelError(String),
 
+    #[error("No semantic model found for table: {0}")]
+    SemanticModelNotFound(String),
+
     #[error("SQL parsing error: {0}")]
     SqlParsingError(String),
 
     #[error("Unsupported SQL construct: {0}")]
     UnsupportedSqlConstruct(String),
+
+    #[error("Too many rows: {0}")]
+    TooManyRows(String),
+}
+
+impl SqlError {
+    /// The SQLSTATE code drivers classify the error by.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SqlError::PermissionDenied(_) => "42501",
+            SqlError::SqlParseError(_) => "42601",
+            SqlError::MeasureFunctionError(_) | SqlError::InvalidMeasureFunction(_) => "42883",
+            SqlError::MeasureNotAllowed(_) => "42803",
+            SqlError::SqlTransformationError(_) => "42000",
+            SqlError::SqlColumnNotFoundError(..) => "42703",
+            SqlError::InvalidFunctionArgument(_) => "22023",
+            SqlError::SemanticModelNotFound(_) => "42P01",
+            SqlError::UnsupportedSqlConstruct(_) => "0A000",
+            SqlError::TooManyRows(_) => "54000",
+            // Failures of the semantic models rather than of the query
+            SqlError::InformationSchemaResult(_)
+            | SqlError::SemanticModelError(_)
+            | SqlError::SqlParsingError(_) => "XX000",
+        }
+    }
+
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            SqlError::PermissionDenied(_) => {
+                Some("The proxy only runs SELECT statements.".to_string())
+            }
+            SqlError::InvalidMeasureFunction(_) => Some(
+                "MEASURE takes the name of a single measure, like MEASURE(head_count).".to_string(),
+            ),
+            SqlError::MeasureNotAllowed(_) => {
+                Some("Measures are aggregates, filter on them in HAVING instead.".to_string())
+            }
+            SqlError::SqlColumnNotFoundError(_, table) => Some(format!(
+                "Query information_schema.columns to list the columns of {}.",
+                table
+            )),
+            SqlError::SemanticModelNotFound(_) => {
+                Some("Query information_schema.tables to list the semantic models.".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<SqlError> for ErrorInfo {
+    fn from(e: SqlError) -> Self {
+        let mut info = ErrorInfo::new("ERROR".to_string(), e.code().to_string(), e.to_string());
+        info.hint = e.hint();
+        info
+    }
 }
 
 pub struct SqlParser<M, S> {
@@ -59,30 +131,61 @@ where
         }
     }
 
-    pub fn transform(&self, query: &str) -> Result<String, SqlError> {
-        let output_queries: Result<Vec<String>, SqlError> = self
-            .parse(query)?
+    /// The mapping queries are rewritten for the data store with.
+    pub fn mapping(&self) -> &M {
+        &self.data_store_mapping
+    }
+
+    /// Rewrites each statement of the query into the SQL run on the data store.
+    pub fn transform(&self, query: &str) -> Result<Vec<String>, SqlError> {
+        self.transform_statements(query).map(|statements| {
+            statements
+                .iter()
+                .map(|statement| statement.to_string())
+                .collect()
+        })
+    }
+
+    /// Rewrites each statement of the query and returns the rewritten AST.
+    ///
+    /// Only read-only queries are accepted. Parameter placeholders such as `$1`
+    /// are kept as is, see [`bind_parameters`] for substituting them.
+    ///
+    /// Queries over `information_schema` and `pg_catalog` are answered by the
+    /// proxy and returned as [`SqlError::InformationSchemaResult`].
+    pub fn transform_statements(&self, query: &str) -> Result<Vec<Statement>, SqlError> {
+        self.parse(query)?
             .into_iter()
-            .map(|statement| match statement {
-                Statement::Query(mut query) => transformations::apply_transformations(
+            .map(|statement| self.transform_statement(statement))
+            .collect()
+    }
+
+    /// Rewrites a single parsed statement, see [`SqlParser::transform_statements`].
+    pub fn transform_statement(&self, statement: Statement) -> Result<Statement, SqlError> {
+        match statement {
+            Statement::Query(mut query) => {
+                if catalog::is_catalog_query(&query) {
+                    let catalog = catalog::Catalog::new(&self.semantic_model)?;
+                    let result = virtual_table::evaluate(&query, &catalog)?;
+                    return Err(SqlError::InformationSchemaResult(result));
+                }
+                transformations::apply_transformations(
                     &mut query,
                     &self.data_store_mapping,
                     &self.semantic_model,
                 )
-                .and_then(|_| Ok(Statement::Query(query)))
-                .map(|stmt| stmt.to_string()),
-                _ => Err(SqlError::PermissionDenied(
-                    "Only read-only SQL statements are allowed".to_string(),
-                )),
-            })
-            .collect();
-
-        output_queries.map(|queries| queries.join(";\n"))
+                .map(|_| Statement::Query(query))
+            }
+            _ => Err(SqlError::PermissionDenied(
+                "Only read-only SQL statements are allowed".to_string(),
+            )),
+        }
     }
 
-    fn parse(&self, query: &str) -> Result<Vec<Statement>, SqlError> {
-        let data_store = self.data_store_mapping.get_dialect();
-        let statements = Parser::parse_sql(data_store, query)
+    /// Parses the query as Postgres, which clients of the proxy speak whatever the
+    /// data store.
+    pub fn parse(&self, query: &str) -> Result<Vec<Statement>, SqlError> {
+        let statements = Parser::parse_sql(&PostgreSqlDialect {}, query)
             .map_err(|e| SqlError::SqlParseError(e.to_string()))?;
         Ok(statements)
     }
@@ -93,8 +196,13 @@ mod test {
     use super::{SqlError, SqlParser};
     use crate::data_store::postgres::PostgresMapping;
     use crate::data_store::snowflake::SnowflakeMapping;
+    use crate::data_store::{DataStoreMapping, FunctionMapping};
     use crate::semantic_model::local_store::LocalSemanticModelStore;
     use rstest::*;
+    use sqlparser::ast::{Expr, Value};
+    use sqlparser::dialect::PostgreSqlDialect;
+    use sqlparser::parser::Parser;
+    use tokio_postgres::types::Type;
 
     #[fixture]
     fn sql_parser_fixture() -> SqlParser<PostgresMapping, LocalSemanticModelStore> {
@@ -123,7 +231,6 @@ mod test {
         "SELECT department_level_1, MEASURE(dm_employees.headcount) AS headcount FROM dm_employees;",
         "SELECT department_level_1, COUNT(dm_employees.id) AS headcount FROM dm_employees"
     )]
-    #[ignore = "Handle locally created table names"]
     #[case::query_with_cte(
         "WITH cte AS (SELECT department_level_1, MEASURE(dm_employees.headcount) FROM dm_employees) SELECT * FROM cte;",
         "WITH cte AS (SELECT department_level_1, COUNT(dm_employees.id) AS headcount FROM dm_employees) SELECT * FROM cte"
@@ -132,7 +239,6 @@ mod test {
         "SELECT department_level_1, MEASURE(dm_employees.headcount) AS 'MEASURE(headcount)' FROM dm_employees;",
         "SELECT department_level_1, COUNT(dm_employees.id) AS 'MEASURE(headcount)' FROM dm_employees"
     )]
-    #[ignore = "Handle locally created table names"]
     #[case::measure_alias_should_be_ignored_second(
         "WITH cte AS (SELECT department_level_1, MEASURE(dm_employees.headcount) AS 'measure_headcount' FROM dm_employees) SELECT * FROM cte;",
         "WITH cte AS (SELECT department_level_1, COUNT(dm_employees.id) AS 'measure_headcount' FROM dm_employees) SELECT * FROM cte"
@@ -149,16 +255,95 @@ mod test {
         "SELECT department_level_1, MEASURE(dm_employees.headcount), false as is_total FROM dm_employees UNION SELECT null as department_level_1, MEASURE(dm_employees.headcount), true as is_total FROM dm_employees;",
         "SELECT department_level_1, COUNT(dm_employees.id) AS headcount, false AS is_total FROM dm_employees UNION SELECT NULL AS department_level_1, COUNT(dm_employees.id) AS headcount, true AS is_total FROM dm_employees"
     )]
-    #[ignore = "Handle locally created table names"]
     #[case::test_subquery(
         "SELECT subquery.department_level_1, MEASURE(dm_employees.headcount) FROM (SELECT * FROM dm_employees) AS subquery;",
-        "SELECT subquery.department_level_1, COUNT(dm_employees.id) AS headcount FROM (SELECT * FROM dm_employees) AS subquery"
+        "SELECT subquery.department_level_1, COUNT(dm_employees.id) AS headcount FROM (SELECT department_level_1, id, included_in_headcount FROM dm_employees) AS subquery"
+    )]
+    #[case::cte_reading_cte(
+        "WITH a AS (SELECT id, MEASURE(dm_employees.headcount) FROM dm_employees GROUP BY id), b AS (SELECT * FROM a) SELECT headcount FROM b;",
+        "WITH a AS (SELECT id, COUNT(dm_employees.id) AS headcount FROM dm_employees GROUP BY id), b AS (SELECT * FROM a) SELECT headcount FROM b"
+    )]
+    #[case::cte_column_aliases(
+        "WITH cte (level, flag) AS (SELECT department_level_1, included_in_headcount FROM dm_employees) SELECT cte.level, flag FROM cte;",
+        "WITH cte (level, flag) AS (SELECT department_level_1, included_in_headcount FROM dm_employees) SELECT cte.level, flag FROM cte"
+    )]
+    #[case::measure_in_subquery(
+        "SELECT s.headcount FROM (SELECT MEASURE(dm_employees.headcount) FROM dm_employees) AS s;",
+        "SELECT s.headcount FROM (SELECT COUNT(dm_employees.id) AS headcount FROM dm_employees) AS s"
+    )]
+    #[case::cte_shadows_model(
+        "WITH dm_employees AS (SELECT id FROM dm_employees) SELECT id FROM dm_employees;",
+        "WITH dm_employees AS (SELECT id FROM dm_employees) SELECT id FROM dm_employees"
+    )]
+    #[case::qualified_wildcard(
+        "SELECT dm_employees.*, MEASURE(dm_employees.headcount) FROM dm_employees;",
+        "SELECT dm_employees.department_level_1, dm_employees.id, dm_employees.included_in_headcount, COUNT(dm_employees.id) AS headcount FROM dm_employees"
+    )]
+    #[case::qualified_wildcard_alias(
+        "SELECT e.*, d.department_level_1_name FROM dm_employees AS e JOIN dm_departments AS d ON e.department_level_1 = d.department_level_1;",
+        "SELECT e.department_level_1, e.id, e.included_in_headcount, d.department_level_1_name FROM dm_employees AS e JOIN dm_departments AS d ON e.department_level_1 = d.department_level_1"
+    )]
+    #[case::wildcard_with_measure(
+        "SELECT *, MEASURE(dm_employees.headcount) FROM dm_employees;",
+        "SELECT dm_employees.department_level_1, dm_employees.id, dm_employees.included_in_headcount, COUNT(dm_employees.id) AS headcount FROM dm_employees"
+    )]
+    #[case::wildcard_across_joins(
+        "SELECT * FROM dm_employees JOIN dm_departments ON dm_employees.department_level_1 = dm_departments.department_level_1_name;",
+        "SELECT dm_employees.department_level_1, dm_employees.id, dm_employees.included_in_headcount, dm_departments.department_level_1_name FROM dm_employees JOIN dm_departments ON dm_employees.department_level_1 = dm_departments.department_level_1_name"
+    )]
+    #[case::qualified_wildcard_over_cte(
+        "WITH cte AS (SELECT id FROM dm_employees) SELECT cte.*, 1 AS one FROM cte;",
+        "WITH cte AS (SELECT id FROM dm_employees) SELECT cte.id, 1 AS one FROM cte"
+    )]
+    #[case::qualified_wildcard_unknown_columns(
+        "SELECT s.*, 1 AS one FROM (SELECT id + 1 FROM dm_employees) AS s;",
+        "SELECT s.*, 1 AS one FROM (SELECT id + 1 FROM dm_employees) AS s"
+    )]
+    #[case::function_arguments(
+        "SELECT COALESCE(department_level_1, 'none'), DATE_TRUNC('month', effective_date) AS month FROM dm_employees;",
+        "SELECT COALESCE(department_level_1, 'none'), DATE_TRUNC('month', effective_date) AS month FROM dm_employees"
+    )]
+    #[case::measure_in_function(
+        "SELECT COALESCE(MEASURE(dm_employees.headcount), 0) AS headcount FROM dm_employees;",
+        "SELECT COALESCE(COUNT(dm_employees.id), 0) AS headcount FROM dm_employees"
+    )]
+    #[case::measure_in_round(
+        "SELECT ROUND(MEASURE(dm_employees.headcount), 2) AS headcount FROM dm_employees;",
+        "SELECT ROUND(COUNT(dm_employees.id), 2) AS headcount FROM dm_employees"
+    )]
+    #[case::measure_in_case(
+        "SELECT CASE WHEN MEASURE(dm_employees.headcount) > 10 THEN 'large' ELSE 'small' END AS size FROM dm_employees;",
+        "SELECT CASE WHEN COUNT(dm_employees.id) > 10 THEN 'large' ELSE 'small' END AS size FROM dm_employees"
+    )]
+    #[case::measure_in_cast(
+        "SELECT CAST(MEASURE(dm_employees.headcount) AS FLOAT) AS headcount FROM dm_employees;",
+        "SELECT CAST(COUNT(dm_employees.id) AS FLOAT) AS headcount FROM dm_employees"
+    )]
+    #[case::measure_in_parentheses(
+        "SELECT -(MEASURE(dm_employees.headcount) + 1) AS headcount FROM dm_employees;",
+        "SELECT -(COUNT(dm_employees.id) + 1) AS headcount FROM dm_employees"
+    )]
+    #[case::measure_in_having_in_list(
+        "SELECT department_level_1 FROM dm_employees GROUP BY department_level_1 HAVING MEASURE(dm_employees.headcount) IN (1, 2);",
+        "SELECT department_level_1 FROM dm_employees GROUP BY department_level_1 HAVING COUNT(dm_employees.id) IN (1, 2)"
+    )]
+    #[case::measure_in_order_by(
+        "SELECT department_level_1, MEASURE(dm_employees.headcount) FROM dm_employees GROUP BY department_level_1 ORDER BY MEASURE(dm_employees.headcount) DESC;",
+        "SELECT department_level_1, COUNT(dm_employees.id) AS headcount FROM dm_employees GROUP BY department_level_1 ORDER BY COUNT(dm_employees.id) DESC"
+    )]
+    #[case::measure_in_where_subquery(
+        "SELECT id FROM dm_employees WHERE department_level_1 IN (SELECT department_level_1 FROM dm_employees GROUP BY department_level_1 HAVING MEASURE(dm_employees.headcount) > 10);",
+        "SELECT id FROM dm_employees WHERE department_level_1 IN (SELECT department_level_1 FROM dm_employees GROUP BY department_level_1 HAVING COUNT(dm_employees.id) > 10)"
     )]
     #[case::test_case_statement(
         "SELECT CASE WHEN department_level_1 = 'a' THEN 'a' WHEN department_level_1 = 'b' THEN 'b' ELSE 'c' END as case_column FROM dm_employees;",
         "SELECT CASE WHEN department_level_1 = 'a' THEN 'a' WHEN department_level_1 = 'b' THEN 'b' ELSE 'c' END AS case_column FROM dm_employees"
     )]
-    #[case::test_distinct_on_snowflake_dialect(
+    #[case::cast(
+        "SELECT id::int4, CAST(id AS text) FROM dm_employees",
+        "SELECT id::INT4, CAST(id AS TEXT) FROM dm_employees"
+    )]
+    #[case::test_distinct_on_postgres_dialect(
         "SELECT DISTINCT ON (department_level_1) department_level_1, MEASURE(dm_employees.headcount) FROM dm_employees;",
         "SELECT DISTINCT ON (department_level_1) department_level_1, COUNT(dm_employees.id) AS headcount FROM dm_employees"
     )]
@@ -166,7 +351,22 @@ mod test {
     fn test_parser_on_postgres(#[case] initial_query: &str, #[case] expected_query: &str) {
         let sql_parser = sql_parser_fixture();
         let transformed_query = sql_parser.transform(initial_query).unwrap();
-        assert_eq!(expected_query, transformed_query);
+        assert_eq!(vec![expected_query], transformed_query);
+    }
+
+    #[test]
+    fn test_multiple_statements() {
+        let sql_parser = sql_parser_fixture();
+        let transformed = sql_parser
+            .transform("SELECT id FROM dm_employees; SELECT MEASURE(dm_employees.headcount) FROM dm_employees;")
+            .unwrap();
+        assert_eq!(
+            transformed,
+            vec![
+                "SELECT id FROM dm_employees",
+                "SELECT COUNT(dm_employees.id) AS headcount FROM dm_employees",
+            ]
+        );
     }
 
     #[rstest]
@@ -174,10 +374,79 @@ mod test {
         "SELECT id, now() AS now FROM dm_employees",
         "SELECT id, CURRENT_TIMESTAMP() AS now FROM dm_employees"
     )]
+    #[case::nested_function(
+        "SELECT id, COALESCE(DATE_TRUNC('day', now()), now()) AS today FROM dm_employees",
+        "SELECT id, COALESCE(DATE_TRUNC('day', CURRENT_TIMESTAMP()), CURRENT_TIMESTAMP()) AS today FROM dm_employees"
+    )]
+    #[case::mapped_by_arity(
+        "SELECT id, now(1) FROM dm_employees",
+        "SELECT id, now(1) FROM dm_employees"
+    )]
+    #[case::function_in_where(
+        "SELECT id FROM dm_employees WHERE date_part('dow', now()) = 1 GROUP BY to_char(now(), 'YYYY')",
+        "SELECT id FROM dm_employees WHERE DATE_PART(dayofweek, CURRENT_TIMESTAMP()) = 1 GROUP BY TO_CHAR(CURRENT_TIMESTAMP(), 'YYYY')"
+    )]
     fn test_func_parser_on_postgres(#[case] initial_query: &str, #[case] expected_query: &str) {
         let sql_parser = snowflake_parser_fixture();
         let transformed_query = sql_parser.transform(initial_query).unwrap();
-        assert_eq!(expected_query, transformed_query.to_string());
+        assert_eq!(vec![expected_query], transformed_query);
+    }
+
+    /// Postgres with `greatest` rewritten to a CASE expression.
+    struct RewriteMapping;
+
+    impl DataStoreMapping for RewriteMapping {
+        fn get_dialect(&self) -> &dyn sqlparser::dialect::Dialect {
+            PostgresMapping {}.get_dialect()
+        }
+
+        fn map_function(&self, name: &str, arity: usize) -> Option<FunctionMapping> {
+            match (name, arity) {
+                ("greatest", 2) => Some(FunctionMapping::Rewrite(|args| {
+                    let [a, b] = <[Expr; 2]>::try_from(args).unwrap();
+                    let sql = format!("CASE WHEN {a} >= {b} THEN {a} ELSE {b} END");
+                    Parser::new(&PostgreSqlDialect {})
+                        .try_with_sql(&sql)
+                        .and_then(|mut parser| parser.parse_expr())
+                        .map_err(|e| e.to_string())
+                })),
+                ("greatest", _) => Some(FunctionMapping::Rewrite(|_| {
+                    Err("greatest takes two arguments".to_string())
+                })),
+                _ => None,
+            }
+        }
+
+        fn map_type(&self, _pg_type: &Type) -> Option<&'static str> {
+            None
+        }
+
+        fn string_literal(&self, value: String) -> Value {
+            Value::SingleQuotedString(value)
+        }
+    }
+
+    #[test]
+    fn test_function_rewrite() {
+        let sql_parser = SqlParser::new(RewriteMapping, LocalSemanticModelStore::mock());
+        assert_eq!(
+            sql_parser.transform("SELECT greatest(id, greatest(id, 1)) AS g FROM dm_employees"),
+            Ok(vec![
+                "SELECT CASE WHEN id >= CASE WHEN id >= 1 THEN id ELSE 1 END THEN id ELSE CASE WHEN id >= 1 THEN id ELSE 1 END END AS g FROM dm_employees".to_string()
+            ])
+        );
+        assert_eq!(
+            sql_parser.transform("SELECT greatest(id) AS g FROM dm_employees"),
+            Err(SqlError::InvalidFunctionArgument(
+                "greatest takes two arguments".to_string()
+            ))
+        );
+        assert_eq!(
+            sql_parser.transform("SELECT greatest(DISTINCT id, 1) AS g FROM dm_employees"),
+            Err(SqlError::InvalidFunctionArgument(
+                "greatest only supports positional arguments".to_string()
+            ))
+        );
     }
 
     #[rstest]
@@ -195,22 +464,127 @@ mod test {
     #[rstest]
     #[case::query_information_schema_tables(
         "SELECT * FROM information_schema.tables;",
-        vec!["dm_departments".to_string(), "dm_employees".to_string()]
+        vec!["table_catalog", "table_schema", "table_name", "table_type"],
+        vec![
+            vec!["main", "public", "dm_departments", "BASE TABLE"],
+            vec!["main", "public", "dm_employees", "BASE TABLE"],
+        ]
+    )]
+    #[case::projection_and_order_by(
+        "SELECT table_name AS name FROM information_schema.tables ORDER BY table_name DESC",
+        vec!["name"],
+        vec![vec!["dm_employees"], vec!["dm_departments"]]
+    )]
+    #[case::filter(
+        "SELECT table_schema, table_name FROM information_schema.tables WHERE table_name = 'dm_employees' AND table_type = 'BASE TABLE'",
+        vec!["table_schema", "table_name"],
+        vec![vec!["public", "dm_employees"]]
+    )]
+    #[case::filter_no_match(
+        "SELECT table_name FROM information_schema.tables WHERE table_schema NOT IN ('public')",
+        vec!["table_name"],
+        vec![]
+    )]
+    #[case::case_insensitive_names(
+        "SELECT TABLE_NAME FROM INFORMATION_SCHEMA.TABLES WHERE table_name LIKE '%dep%'",
+        vec!["TABLE_NAME"],
+        vec![vec!["dm_departments"]]
+    )]
+    #[case::query_information_schema_columns(
+        "SELECT table_name, column_name, ordinal_position, data_type, udt_name, is_measure FROM information_schema.columns",
+        vec!["table_name", "column_name", "ordinal_position", "data_type", "udt_name", "is_measure"],
+        vec![
+            vec!["dm_departments", "department_level_1_name", "1", "text", "text", "NO"],
+            vec!["dm_employees", "department_level_1", "1", "text", "text", "NO"],
+            vec!["dm_employees", "id", "2", "integer", "int4", "NO"],
+            vec!["dm_employees", "included_in_headcount", "3", "boolean", "bool", "NO"],
+            vec!["dm_employees", "headcount", "4", "integer", "int4", "YES"],
+            vec!["dm_employees", "ending_headcount", "5", "integer", "int4", "YES"],
+        ]
+    )]
+    #[case::columns_of_table(
+        "SELECT column_name FROM information_schema.columns WHERE table_schema = 'public' AND table_name = 'dm_employees' AND is_measure = 'NO' ORDER BY ordinal_position DESC",
+        vec!["column_name"],
+        vec![vec!["included_in_headcount"], vec!["id"], vec!["department_level_1"]]
+    )]
+    #[case::psql_list_tables(
+        "SELECT n.nspname as \"Schema\", c.relname as \"Name\", CASE c.relkind WHEN 'r' THEN 'table' WHEN 'v' THEN 'view' END as \"Type\", pg_catalog.pg_get_userbyid(c.relowner) as \"Owner\" FROM pg_catalog.pg_class c LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace LEFT JOIN pg_catalog.pg_am am ON am.oid = c.relam WHERE c.relkind IN ('r','p','') AND n.nspname <> 'pg_catalog' AND n.nspname !~ '^pg_toast' AND n.nspname <> 'information_schema' AND pg_catalog.pg_table_is_visible(c.oid) ORDER BY 1,2",
+        vec!["Schema", "Name", "Type", "Owner"],
+        vec![
+            vec!["public", "dm_departments", "table", "eqtble"],
+            vec!["public", "dm_employees", "table", "eqtble"],
+        ]
+    )]
+    #[case::psql_describe_table(
+        "SELECT a.attname, pg_catalog.format_type(a.atttypid, a.atttypmod), a.attnotnull, pg_catalog.col_description(a.attrelid, a.attnum) FROM pg_catalog.pg_attribute a WHERE a.attrelid = (SELECT c.oid FROM pg_catalog.pg_class c WHERE c.relname OPERATOR(pg_catalog.~) '^(dm_employees)$' COLLATE pg_catalog.default) AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum",
+        vec!["attname", "format_type", "attnotnull", "col_description"],
+        vec![
+            vec!["department_level_1", "text", "f", "Top level department of the employee"],
+            vec!["id", "integer", "f", "Unique identifier for the employee"],
+            vec!["included_in_headcount", "boolean", "f", "Flag indicating if the employee is included in headcount calculations"],
+        ]
+    )]
+    #[case::regclass(
+        "SELECT relname FROM pg_class WHERE oid = 'dm_departments'::regclass",
+        vec!["relname"],
+        vec![vec!["dm_departments"]]
+    )]
+    #[case::catalog_functions(
+        "SELECT current_schema(), current_database()",
+        vec!["current_schema", "current_database"],
+        vec![vec!["public", "main"]]
     )]
     fn test_information_schema_result_body(
         #[case] query: &str,
-        #[case] expected_body: Vec<String>,
+        #[case] expected_columns: Vec<&str>,
+        #[case] expected_rows: Vec<Vec<&str>>,
     ) {
         let sql_parser = sql_parser_fixture();
-        assert_eq!(
+        let Err(SqlError::InformationSchemaResult(table)) = sql_parser.transform(query) else {
+            panic!("Expected an information schema result");
+        };
+        let columns: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+        let rows: Vec<Vec<&str>> = table
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|v| v.as_deref().unwrap()).collect())
+            .collect();
+        assert_eq!(expected_columns, columns);
+        assert_eq!(expected_rows, rows);
+    }
+
+    #[rstest]
+    #[case::unknown_table("SELECT * FROM information_schema.views")]
+    #[case::unknown_column("SELECT table_owner FROM information_schema.tables")]
+    #[case::unknown_columns_column("SELECT column_type FROM information_schema.columns")]
+    #[case::set_operation(
+        "SELECT table_name FROM information_schema.tables UNION SELECT department_level_1 FROM dm_employees"
+    )]
+    #[case::unknown_catalog_table("SELECT * FROM pg_catalog.pg_stat_activity")]
+    #[case::join_with_semantic_model(
+        "SELECT relname FROM pg_class c JOIN dm_employees e ON e.id = c.oid"
+    )]
+    fn test_information_schema_errors(#[case] query: &str) {
+        let sql_parser = sql_parser_fixture();
+        assert!(!matches!(
             sql_parser.transform(query),
-            Err(SqlError::InformationSchemaResult(expected_body))
-        );
+            Ok(_) | Err(SqlError::InformationSchemaResult(_))
+        ));
     }
 
     #[rstest]
     #[case::column_not_found("SELECT headcount FROM dm_employees;", "headcount", "dm_employees")]
     #[case::column_not_found("SELECT age, health FROM dm_employees;", "age", "dm_employees")]
+    #[case::cte_column_not_found(
+        "WITH cte AS (SELECT id FROM dm_employees) SELECT department_level_1 FROM cte;",
+        "department_level_1",
+        "cte"
+    )]
+    #[case::subquery_column_not_found(
+        "SELECT s.department_level_1 FROM (SELECT id FROM dm_employees) AS s;",
+        "department_level_1",
+        "s"
+    )]
     fn test_column_not_found_error(
         #[case] query: &str,
         #[case] missing_column: String,
@@ -222,4 +596,37 @@ mod test {
             Err(SqlError::SqlColumnNotFoundError(missing_column, table))
         );
     }
+
+    #[rstest]
+    #[case::where_clause("SELECT id FROM dm_employees WHERE MEASURE(dm_employees.headcount) > 10;")]
+    #[case::nested_in_where(
+        "SELECT id FROM dm_employees WHERE id = 1 OR (CASE WHEN MEASURE(dm_employees.headcount) > 10 THEN true END);"
+    )]
+    #[case::where_in_subquery(
+        "SELECT MEASURE(dm_employees.headcount) FROM (SELECT id FROM dm_employees WHERE ROUND(MEASURE(dm_employees.headcount)) > 1) AS s;"
+    )]
+    fn test_measure_not_allowed(#[case] query: &str) {
+        let sql_parser = sql_parser_fixture();
+        assert_eq!(
+            sql_parser.transform(query),
+            Err(SqlError::MeasureNotAllowed("WHERE".to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case::syntax_error("SELEC id FROM dm_employees", "42601")]
+    #[case::column_not_found("SELECT age FROM dm_employees", "42703")]
+    #[case::measure_not_found("SELECT MEASURE(dm_employees.age) FROM dm_employees", "42703")]
+    #[case::measure_in_where(
+        "SELECT id FROM dm_employees WHERE MEASURE(dm_employees.headcount) > 10",
+        "42803"
+    )]
+    #[case::unknown_table("SELECT id FROM employees", "42P01")]
+    #[case::write("DELETE FROM dm_employees", "42501")]
+    #[case::unsupported("SELECT * FROM information_schema.views", "0A000")]
+    fn test_error_code(#[case] query: &str, #[case] code: &str) {
+        let sql_parser = sql_parser_fixture();
+        let error = sql_parser.transform(query).unwrap_err();
+        assert_eq!(error.code(), code, "{}", error);
+    }
 }
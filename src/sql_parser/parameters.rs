@@ -0,0 +1,132 @@
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{visit_expressions, visit_expressions_mut, Expr, Statement, Value};
+
+use super::SqlError;
+
+/// Returns the number of parameters referenced by the statement.
+///
+/// Parameters use Postgres style `$n` placeholders, so the count is the highest
+/// referenced index rather than the number of placeholders.
+pub fn parameter_count(statement: &Statement) -> Result<usize, SqlError> {
+    let mut count = 0;
+    let result = visit_expressions(statement, |expr| {
+        if let Expr::Value(Value::Placeholder(placeholder)) = expr {
+            match placeholder_index(placeholder) {
+                Ok(idx) => count = count.max(idx + 1),
+                Err(e) => return ControlFlow::Break(e),
+            }
+        }
+        ControlFlow::Continue(())
+    });
+
+    match result {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(count),
+    }
+}
+
+/// Replaces every `$n` placeholder in the statement with `values[n - 1]`.
+pub fn bind_parameters(statement: &mut Statement, values: &[Expr]) -> Result<(), SqlError> {
+    let result = visit_expressions_mut(statement, |expr| {
+        if let Expr::Value(Value::Placeholder(placeholder)) = expr {
+            let value = placeholder_index(placeholder).and_then(|idx| {
+                values.get(idx).cloned().ok_or_else(|| {
+                    SqlError::InvalidFunctionArgument(format!(
+                        "No value bound for parameter {}",
+                        placeholder
+                    ))
+                })
+            });
+            match value {
+                Ok(value) => *expr = value,
+                Err(e) => return ControlFlow::Break(e),
+            }
+        }
+        ControlFlow::Continue(())
+    });
+
+    match result {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+/// Parses the zero based index out of a `$n` placeholder.
+fn placeholder_index(placeholder: &str) -> Result<usize, SqlError> {
+    placeholder
+        .strip_prefix('$')
+        .and_then(|idx| idx.parse::<usize>().ok())
+        .filter(|idx| *idx > 0)
+        .map(|idx| idx - 1)
+        .ok_or_else(|| {
+            SqlError::UnsupportedSqlConstruct(format!(
+                "Unsupported parameter placeholder: {}",
+                placeholder
+            ))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bind_parameters, parameter_count};
+    use crate::sql_parser::SqlError;
+    use rstest::*;
+    use sqlparser::ast::{Expr, Statement, Value};
+    use sqlparser::dialect::PostgreSqlDialect;
+    use sqlparser::parser::Parser;
+
+    fn parse(sql: &str) -> Statement {
+        Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0)
+    }
+
+    #[rstest]
+    #[case::no_parameters("SELECT id FROM dm_employees", 0)]
+    #[case::single_parameter("SELECT id FROM dm_employees WHERE id = $1", 1)]
+    #[case::highest_index("SELECT $2, id FROM dm_employees WHERE id = $1 OR id = $2", 2)]
+    #[case::subquery(
+        "SELECT id FROM dm_employees WHERE id IN (SELECT id FROM dm_employees WHERE id > $3)",
+        3
+    )]
+    fn test_parameter_count(#[case] sql: &str, #[case] expected: usize) {
+        assert_eq!(parameter_count(&parse(sql)), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::number(
+        "SELECT id FROM dm_employees WHERE id = $1",
+        vec![Expr::Value(Value::Number("42".to_string(), false))],
+        "SELECT id FROM dm_employees WHERE id = 42"
+    )]
+    #[case::escaped_string(
+        "SELECT id FROM dm_employees WHERE department_level_1 = $1 LIMIT $2",
+        vec![
+            Expr::Value(Value::SingleQuotedString("it's".to_string())),
+            Expr::Value(Value::Number("10".to_string(), false)),
+        ],
+        "SELECT id FROM dm_employees WHERE department_level_1 = 'it''s' LIMIT 10"
+    )]
+    #[case::null(
+        "SELECT $1 AS value",
+        vec![Expr::Value(Value::Null)],
+        "SELECT NULL AS value"
+    )]
+    fn test_bind_parameters(#[case] sql: &str, #[case] values: Vec<Expr>, #[case] expected: &str) {
+        let mut statement = parse(sql);
+        bind_parameters(&mut statement, &values).unwrap();
+        assert_eq!(expected, statement.to_string());
+    }
+
+    #[test]
+    fn test_bind_missing_parameter() {
+        let mut statement = parse("SELECT id FROM dm_employees WHERE id = $2");
+        assert_eq!(
+            bind_parameters(&mut statement, &[Expr::Value(Value::Null)]),
+            Err(SqlError::InvalidFunctionArgument(
+                "No value bound for parameter $2".to_string()
+            ))
+        );
+    }
+}
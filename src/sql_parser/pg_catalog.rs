@@ -0,0 +1,670 @@
+use std::collections::BTreeMap;
+
+use tokio_postgres::types::Type;
+
+use super::information_schema::{postgres_type, sql_type_name, CATALOG_NAME, SCHEMA_NAME};
+use super::virtual_table::{Scalar, VirtualColumn, VirtualTable};
+use super::SqlError;
+use crate::semantic_model::SemanticModel;
+
+/// Postgres version reported to clients, `pg_catalog` mirrors its layout.
+pub const SERVER_VERSION: &str = "15.0";
+
+/// Role owning every object in the catalog.
+const OWNER_NAME: &str = "eqtble";
+const OWNER_OID: u32 = 10;
+
+const PG_CATALOG_OID: u32 = 11;
+const PUBLIC_OID: u32 = 2200;
+const INFORMATION_SCHEMA_OID: u32 = 13000;
+
+/// `pg_class` oid, used as the `classoid` of table descriptions.
+const PG_CLASS_OID: u32 = 1259;
+/// Oid of the `heap` access method.
+const HEAP_OID: u32 = 2;
+const DEFAULT_COLLATION_OID: u32 = 100;
+const C_COLLATION_OID: u32 = 950;
+
+const DATABASE_OID: u32 = 16384;
+/// Semantic models are numbered from this oid in name order.
+const FIRST_TABLE_OID: u32 = 16385;
+
+/// Tables of `pg_catalog` the proxy emulates. Tables describing objects the
+/// semantic layer doesn't have, like indexes or triggers, are empty.
+const TABLES: &[&str] = &[
+    "pg_am",
+    "pg_attrdef",
+    "pg_attribute",
+    "pg_class",
+    "pg_collation",
+    "pg_constraint",
+    "pg_database",
+    "pg_description",
+    "pg_index",
+    "pg_inherits",
+    "pg_namespace",
+    "pg_policy",
+    "pg_proc",
+    "pg_publication",
+    "pg_publication_namespace",
+    "pg_publication_rel",
+    "pg_roles",
+    "pg_statistic_ext",
+    "pg_trigger",
+    "pg_type",
+];
+
+pub fn is_table(name: &str) -> bool {
+    TABLES.contains(&name)
+}
+
+pub fn is_function(name: &str) -> bool {
+    call(name, &[], &BTreeMap::new()).is_some()
+}
+
+/// Builds the virtual `pg_catalog` table with the given name.
+pub fn table(
+    name: &str,
+    models: &BTreeMap<String, SemanticModel>,
+) -> Result<VirtualTable, SqlError> {
+    let table = match name {
+        "pg_am" => virtual_table(
+            &[
+                ("oid", Type::OID),
+                ("amname", Type::NAME),
+                ("amtype", Type::CHAR),
+            ],
+            vec![vec![value(HEAP_OID), value("heap"), value("t")]],
+        ),
+        "pg_attribute" => attributes(models),
+        "pg_class" => classes(models),
+        "pg_database" => virtual_table(
+            &[
+                ("oid", Type::OID),
+                ("datname", Type::NAME),
+                ("datdba", Type::OID),
+                ("encoding", Type::INT4),
+                ("datcollate", Type::TEXT),
+                ("datctype", Type::TEXT),
+                ("datistemplate", Type::BOOL),
+                ("datallowconn", Type::BOOL),
+                ("datconnlimit", Type::INT4),
+                ("datacl", Type::TEXT),
+            ],
+            vec![vec![
+                value(DATABASE_OID),
+                value(CATALOG_NAME),
+                value(OWNER_OID),
+                value(6),
+                value("C"),
+                value("C"),
+                value(false),
+                value(true),
+                value(-1),
+                None,
+            ]],
+        ),
+        "pg_description" => descriptions(models),
+        "pg_namespace" => virtual_table(
+            &[
+                ("oid", Type::OID),
+                ("nspname", Type::NAME),
+                ("nspowner", Type::OID),
+                ("nspacl", Type::TEXT),
+            ],
+            [
+                (PG_CATALOG_OID, "pg_catalog"),
+                (PUBLIC_OID, SCHEMA_NAME),
+                (INFORMATION_SCHEMA_OID, "information_schema"),
+            ]
+            .into_iter()
+            .map(|(oid, name)| vec![value(oid), value(name), value(OWNER_OID), None])
+            .collect(),
+        ),
+        "pg_roles" => virtual_table(
+            &[
+                ("oid", Type::OID),
+                ("rolname", Type::NAME),
+                ("rolsuper", Type::BOOL),
+                ("rolinherit", Type::BOOL),
+                ("rolcreaterole", Type::BOOL),
+                ("rolcreatedb", Type::BOOL),
+                ("rolcanlogin", Type::BOOL),
+                ("rolreplication", Type::BOOL),
+                ("rolconnlimit", Type::INT4),
+                ("rolvaliduntil", Type::TIMESTAMPTZ),
+                ("rolbypassrls", Type::BOOL),
+            ],
+            vec![vec![
+                value(OWNER_OID),
+                value(OWNER_NAME),
+                value(false),
+                value(true),
+                value(false),
+                value(false),
+                value(true),
+                value(false),
+                value(-1),
+                None,
+                value(false),
+            ]],
+        ),
+        "pg_type" => types(),
+        "pg_attrdef" => empty_table(&[
+            ("oid", Type::OID),
+            ("adrelid", Type::OID),
+            ("adnum", Type::INT2),
+            ("adbin", Type::TEXT),
+        ]),
+        "pg_collation" => empty_table(&[
+            ("oid", Type::OID),
+            ("collname", Type::NAME),
+            ("collnamespace", Type::OID),
+        ]),
+        "pg_constraint" => empty_table(&[
+            ("oid", Type::OID),
+            ("conname", Type::NAME),
+            ("connamespace", Type::OID),
+            ("contype", Type::CHAR),
+            ("conrelid", Type::OID),
+            ("confrelid", Type::OID),
+            ("conkey", Type::INT2_ARRAY),
+            ("confkey", Type::INT2_ARRAY),
+        ]),
+        "pg_index" => empty_table(&[
+            ("indexrelid", Type::OID),
+            ("indrelid", Type::OID),
+            ("indnatts", Type::INT2),
+            ("indisunique", Type::BOOL),
+            ("indisprimary", Type::BOOL),
+            ("indkey", Type::INT2_ARRAY),
+        ]),
+        "pg_inherits" => empty_table(&[
+            ("inhrelid", Type::OID),
+            ("inhparent", Type::OID),
+            ("inhseqno", Type::INT4),
+            ("inhdetachpending", Type::BOOL),
+        ]),
+        "pg_policy" => empty_table(&[
+            ("oid", Type::OID),
+            ("polname", Type::NAME),
+            ("polrelid", Type::OID),
+            ("polcmd", Type::CHAR),
+            ("polpermissive", Type::BOOL),
+            ("polroles", Type::OID_ARRAY),
+            ("polqual", Type::TEXT),
+            ("polwithcheck", Type::TEXT),
+        ]),
+        "pg_proc" => empty_table(&[
+            ("oid", Type::OID),
+            ("proname", Type::NAME),
+            ("pronamespace", Type::OID),
+            ("prorettype", Type::OID),
+        ]),
+        "pg_publication" => empty_table(&[
+            ("oid", Type::OID),
+            ("pubname", Type::NAME),
+            ("pubowner", Type::OID),
+            ("puballtables", Type::BOOL),
+        ]),
+        "pg_publication_namespace" => empty_table(&[
+            ("oid", Type::OID),
+            ("pnpubid", Type::OID),
+            ("pnnspid", Type::OID),
+        ]),
+        "pg_publication_rel" => empty_table(&[
+            ("oid", Type::OID),
+            ("prpubid", Type::OID),
+            ("prrelid", Type::OID),
+            ("prqual", Type::TEXT),
+            ("prattrs", Type::INT2_ARRAY),
+        ]),
+        "pg_statistic_ext" => empty_table(&[
+            ("oid", Type::OID),
+            ("stxrelid", Type::OID),
+            ("stxname", Type::NAME),
+            ("stxnamespace", Type::OID),
+            ("stxowner", Type::OID),
+            ("stxstattarget", Type::INT4),
+            ("stxkeys", Type::INT2_ARRAY),
+            ("stxkind", Type::CHAR_ARRAY),
+        ]),
+        "pg_trigger" => empty_table(&[
+            ("oid", Type::OID),
+            ("tgrelid", Type::OID),
+            ("tgname", Type::NAME),
+            ("tgisinternal", Type::BOOL),
+        ]),
+        _ => {
+            return Err(SqlError::UnsupportedSqlConstruct(format!(
+                "pg_catalog.{} is not supported",
+                name
+            )))
+        }
+    };
+    Ok(table)
+}
+
+/// `pg_class` listing every semantic model as a table in the public schema.
+fn classes(models: &BTreeMap<String, SemanticModel>) -> VirtualTable {
+    virtual_table(
+        &[
+            ("oid", Type::OID),
+            ("relname", Type::NAME),
+            ("relnamespace", Type::OID),
+            ("reltype", Type::OID),
+            ("reloftype", Type::OID),
+            ("relowner", Type::OID),
+            ("relam", Type::OID),
+            ("relfilenode", Type::OID),
+            ("reltablespace", Type::OID),
+            ("relpages", Type::INT4),
+            ("reltuples", Type::FLOAT4),
+            ("reltoastrelid", Type::OID),
+            ("relhasindex", Type::BOOL),
+            ("relisshared", Type::BOOL),
+            ("relpersistence", Type::CHAR),
+            ("relkind", Type::CHAR),
+            ("relnatts", Type::INT2),
+            ("relchecks", Type::INT2),
+            ("relhasrules", Type::BOOL),
+            ("relhastriggers", Type::BOOL),
+            ("relhassubclass", Type::BOOL),
+            ("relrowsecurity", Type::BOOL),
+            ("relforcerowsecurity", Type::BOOL),
+            ("relispopulated", Type::BOOL),
+            ("relreplident", Type::CHAR),
+            ("relispartition", Type::BOOL),
+            ("relacl", Type::TEXT),
+            ("reloptions", Type::TEXT_ARRAY),
+            ("relpartbound", Type::TEXT),
+        ],
+        models
+            .values()
+            .zip(FIRST_TABLE_OID..)
+            .map(|(model, oid)| {
+                vec![
+                    value(oid),
+                    value(&model.name),
+                    value(PUBLIC_OID),
+                    value(0),
+                    value(0),
+                    value(OWNER_OID),
+                    value(HEAP_OID),
+                    value(0),
+                    value(0),
+                    value(0),
+                    value(-1),
+                    value(0),
+                    value(false),
+                    value(false),
+                    value("p"),
+                    value("r"),
+                    value(model.dimensions.len()),
+                    value(0),
+                    value(false),
+                    value(false),
+                    value(false),
+                    value(false),
+                    value(false),
+                    value(true),
+                    value("d"),
+                    value(false),
+                    None,
+                    None,
+                    None,
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// `pg_attribute` listing the dimensions of every semantic model as columns.
+fn attributes(models: &BTreeMap<String, SemanticModel>) -> VirtualTable {
+    let mut rows = Vec::new();
+    for (model, oid) in models.values().zip(FIRST_TABLE_OID..) {
+        for (dimension, attnum) in model.dimensions.iter().zip(1..) {
+            let data_type = postgres_type(&dimension.data_type);
+            let (length, ..) = type_info(&data_type);
+            rows.push(vec![
+                value(oid),
+                value(&dimension.name),
+                value(data_type.oid()),
+                value(-1),
+                value(length),
+                value(attnum),
+                value(0),
+                value(-1),
+                value(if length == -1 { "x" } else { "p" }),
+                value(""),
+                value(false),
+                value(false),
+                value(""),
+                value(""),
+                value(false),
+                value(true),
+                value(collation(&data_type)),
+            ]);
+        }
+    }
+
+    virtual_table(
+        &[
+            ("attrelid", Type::OID),
+            ("attname", Type::NAME),
+            ("atttypid", Type::OID),
+            ("attstattarget", Type::INT4),
+            ("attlen", Type::INT2),
+            ("attnum", Type::INT2),
+            ("attndims", Type::INT4),
+            ("atttypmod", Type::INT4),
+            ("attstorage", Type::CHAR),
+            ("attcompression", Type::CHAR),
+            ("attnotnull", Type::BOOL),
+            ("atthasdef", Type::BOOL),
+            ("attidentity", Type::CHAR),
+            ("attgenerated", Type::CHAR),
+            ("attisdropped", Type::BOOL),
+            ("attislocal", Type::BOOL),
+            ("attcollation", Type::OID),
+        ],
+        rows,
+    )
+}
+
+/// `pg_description` with the descriptions of the semantic models and their
+/// dimensions.
+fn descriptions(models: &BTreeMap<String, SemanticModel>) -> VirtualTable {
+    let mut rows = Vec::new();
+    for (model, oid) in models.values().zip(FIRST_TABLE_OID..) {
+        let dimensions = model
+            .dimensions
+            .iter()
+            .zip(1..)
+            .map(|(dimension, attnum)| (attnum, &dimension.description));
+        for (objsubid, description) in std::iter::once((0, &model.description)).chain(dimensions) {
+            if description.is_empty() {
+                continue;
+            }
+            rows.push(vec![
+                value(oid),
+                value(PG_CLASS_OID),
+                value(objsubid),
+                value(description),
+            ]);
+        }
+    }
+
+    virtual_table(
+        &[
+            ("objoid", Type::OID),
+            ("classoid", Type::OID),
+            ("objsubid", Type::INT4),
+            ("description", Type::TEXT),
+        ],
+        rows,
+    )
+}
+
+/// `pg_type` with the types the proxy can return.
+fn types() -> VirtualTable {
+    virtual_table(
+        &[
+            ("oid", Type::OID),
+            ("typname", Type::NAME),
+            ("typnamespace", Type::OID),
+            ("typowner", Type::OID),
+            ("typlen", Type::INT2),
+            ("typbyval", Type::BOOL),
+            ("typtype", Type::CHAR),
+            ("typcategory", Type::CHAR),
+            ("typisdefined", Type::BOOL),
+            ("typdelim", Type::CHAR),
+            ("typrelid", Type::OID),
+            ("typelem", Type::OID),
+            ("typnotnull", Type::BOOL),
+            ("typbasetype", Type::OID),
+            ("typtypmod", Type::INT4),
+            ("typndims", Type::INT4),
+            ("typcollation", Type::OID),
+        ],
+        known_types()
+            .iter()
+            .map(|data_type| {
+                let (length, category) = type_info(data_type);
+                vec![
+                    value(data_type.oid()),
+                    value(data_type.name()),
+                    value(PG_CATALOG_OID),
+                    value(OWNER_OID),
+                    value(length),
+                    value(length > 0 && length <= 8),
+                    value("b"),
+                    value(category),
+                    value(true),
+                    value(","),
+                    value(0),
+                    value(0),
+                    value(false),
+                    value(0),
+                    value(-1),
+                    value(0),
+                    value(collation(data_type)),
+                ]
+            })
+            .collect(),
+    )
+}
+
+fn known_types() -> [Type; 21] {
+    [
+        Type::BOOL,
+        Type::BYTEA,
+        Type::CHAR,
+        Type::NAME,
+        Type::INT8,
+        Type::INT2,
+        Type::INT4,
+        Type::TEXT,
+        Type::OID,
+        Type::JSON,
+        Type::FLOAT4,
+        Type::FLOAT8,
+        Type::BPCHAR,
+        Type::VARCHAR,
+        Type::DATE,
+        Type::TIME,
+        Type::TIMESTAMP,
+        Type::TIMESTAMPTZ,
+        Type::INTERVAL,
+        Type::NUMERIC,
+        Type::UUID,
+    ]
+}
+
+/// Length and category of a type, as reported by `pg_type`.
+fn type_info(data_type: &Type) -> (i16, &'static str) {
+    match *data_type {
+        Type::BOOL => (1, "B"),
+        Type::CHAR => (1, "Z"),
+        Type::INT2 => (2, "N"),
+        Type::INT4 | Type::OID | Type::FLOAT4 => (4, "N"),
+        Type::INT8 | Type::FLOAT8 => (8, "N"),
+        Type::NUMERIC => (-1, "N"),
+        Type::NAME => (64, "S"),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR => (-1, "S"),
+        Type::DATE => (4, "D"),
+        Type::TIME | Type::TIMESTAMP | Type::TIMESTAMPTZ => (8, "D"),
+        Type::INTERVAL => (16, "T"),
+        Type::UUID => (16, "U"),
+        _ => (-1, "U"),
+    }
+}
+
+fn collation(data_type: &Type) -> u32 {
+    match *data_type {
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR => DEFAULT_COLLATION_OID,
+        Type::NAME => C_COLLATION_OID,
+        _ => 0,
+    }
+}
+
+/// Calls a `pg_catalog` function, returning `None` for unknown functions.
+pub fn call(
+    name: &str,
+    args: &[Scalar],
+    models: &BTreeMap<String, SemanticModel>,
+) -> Option<Result<Scalar, SqlError>> {
+    let result = match (name, args) {
+        ("version", _) => Ok(text(format!(
+            "PostgreSQL {} ({} {})",
+            SERVER_VERSION,
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ))),
+        ("current_database", _) => Ok(text(CATALOG_NAME)),
+        ("current_schema", _) => Ok(text(SCHEMA_NAME)),
+        ("current_schemas", [include_implicit]) => {
+            let mut schemas = vec![text(SCHEMA_NAME)];
+            if *include_implicit == Scalar::Bool(true) {
+                schemas.insert(0, text("pg_catalog"));
+            }
+            Ok(Scalar::Array(schemas))
+        }
+        ("current_setting", [Scalar::Text(setting)]) => match setting.as_str() {
+            "server_version" => Ok(text(SERVER_VERSION)),
+            "server_version_num" => Ok(text("150000")),
+            "server_encoding" | "client_encoding" => Ok(text("UTF8")),
+            "search_path" => Ok(text(SCHEMA_NAME)),
+            "standard_conforming_strings" => Ok(text("on")),
+            _ => Err(SqlError::InvalidFunctionArgument(format!(
+                "unrecognized configuration parameter \"{}\"",
+                setting
+            ))),
+        },
+        ("pg_get_userbyid", _) => Ok(text(OWNER_NAME)),
+        ("pg_encoding_to_char", _) => Ok(text("UTF8")),
+        // Every object in the catalog is visible and accessible
+        (
+            "pg_table_is_visible"
+            | "pg_type_is_visible"
+            | "has_database_privilege"
+            | "has_schema_privilege"
+            | "has_table_privilege",
+            _,
+        ) => Ok(Scalar::Bool(true)),
+        ("pg_relation_is_publishable", _) => Ok(Scalar::Bool(false)),
+        // The semantic layer has no expressions, sizes or statistics to report
+        (
+            "pg_get_expr"
+            | "pg_get_statisticsobjdef_columns"
+            | "pg_table_size"
+            | "pg_total_relation_size"
+            | "pg_relation_size"
+            | "pg_size_pretty"
+            | "shobj_description",
+            _,
+        ) => Ok(Scalar::Null),
+        ("format_type", [oid, ..]) => Ok(type_by_oid(oid)
+            .map(|data_type| text(sql_type_name(&data_type)))
+            .unwrap_or(Scalar::Null)),
+        ("obj_description", [oid, ..]) => Ok(model_by_oid(oid, models)
+            .map(|model| &model.description)
+            .filter(|description| !description.is_empty())
+            .map(text)
+            .unwrap_or(Scalar::Null)),
+        ("col_description", [oid, Scalar::Number(attnum)]) => Ok(model_by_oid(oid, models)
+            .and_then(|model| model.dimensions.get((*attnum as usize).checked_sub(1)?))
+            .map(|dimension| &dimension.description)
+            .filter(|description| !description.is_empty())
+            .map(text)
+            .unwrap_or(Scalar::Null)),
+        ("to_regclass", [Scalar::Text(name)]) => Ok(match unqualified(name) {
+            Some(name) => models
+                .keys()
+                .zip(FIRST_TABLE_OID..)
+                .find(|(model, _)| **model == name)
+                .map(|(_, oid)| Scalar::Number(oid.into()))
+                .unwrap_or(Scalar::Null),
+            None => Scalar::Null,
+        }),
+        ("to_regtype", [Scalar::Text(name)]) => Ok(known_types()
+            .iter()
+            .find(|data_type| {
+                data_type.name().eq_ignore_ascii_case(name)
+                    || sql_type_name(data_type).eq_ignore_ascii_case(name)
+            })
+            .map(|data_type| Scalar::Number(data_type.oid().into()))
+            .unwrap_or(Scalar::Null)),
+        ("to_regnamespace", [Scalar::Text(name)]) => Ok(match name.as_str() {
+            "pg_catalog" => Scalar::Number(PG_CATALOG_OID.into()),
+            SCHEMA_NAME => Scalar::Number(PUBLIC_OID.into()),
+            "information_schema" => Scalar::Number(INFORMATION_SCHEMA_OID.into()),
+            _ => Scalar::Null,
+        }),
+        (
+            "current_schemas" | "current_setting" | "col_description" | "format_type"
+            | "obj_description" | "to_regclass" | "to_regtype" | "to_regnamespace",
+            _,
+        ) => Err(SqlError::InvalidFunctionArgument(format!(
+            "Invalid arguments for function {}",
+            name
+        ))),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn model_by_oid<'a>(
+    oid: &Scalar,
+    models: &'a BTreeMap<String, SemanticModel>,
+) -> Option<&'a SemanticModel> {
+    let Scalar::Number(oid) = oid else {
+        return None;
+    };
+    let idx = (*oid as u32).checked_sub(FIRST_TABLE_OID)?;
+    models.values().nth(idx as usize)
+}
+
+fn type_by_oid(oid: &Scalar) -> Option<Type> {
+    match oid {
+        Scalar::Number(oid) => Type::from_oid(*oid as u32),
+        _ => None,
+    }
+}
+
+/// Table name of a possibly schema qualified and quoted relation name, `None`
+/// when it is qualified with a schema other than public.
+fn unqualified(name: &str) -> Option<String> {
+    let unquote = |part: &str| match part.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+        Some(quoted) => quoted.to_string(),
+        None => part.to_lowercase(),
+    };
+    match name.split_once('.') {
+        Some((schema, table)) if unquote(schema) == SCHEMA_NAME => Some(unquote(table)),
+        Some(_) => None,
+        None => Some(unquote(name)),
+    }
+}
+
+fn virtual_table(columns: &[(&str, Type)], rows: Vec<Vec<Option<String>>>) -> VirtualTable {
+    VirtualTable {
+        columns: columns
+            .iter()
+            .map(|(name, data_type)| VirtualColumn::new(name, data_type.clone()))
+            .collect(),
+        rows,
+    }
+}
+
+fn empty_table(columns: &[(&str, Type)]) -> VirtualTable {
+    virtual_table(columns, vec![])
+}
+
+fn value(value: impl ToString) -> Option<String> {
+    Some(value.to_string())
+}
+
+fn text(value: impl ToString) -> Scalar {
+    Scalar::Text(value.to_string())
+}
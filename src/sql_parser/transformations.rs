@@ -1,6 +1,9 @@
-use crate::data_store::DataStoreMapping;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+use crate::data_store::{pg_type, DataStoreMapping, FunctionMapping};
 use crate::semantic_model::measure::Renderable;
-use crate::semantic_model::SemanticModelStore;
+use crate::semantic_model::{SemanticModel, SemanticModelStore, SemanticModelStoreError};
 use sqlparser::ast::*;
 use sqlparser::parser::Parser;
 
@@ -15,23 +18,184 @@ pub fn apply_transformations<M: DataStoreMapping, S: SemanticModelStore>(
     data_store_mapping: &M,
     semantic_model: &S,
 ) -> Result<(), SqlError> {
-    log::trace!("apply_transformations: input query = {}", query.to_string());
+    map_expressions(query, data_store_mapping)?;
+    transform_query(query, data_store_mapping, semantic_model, &Scope::default()).map(|_| ())
+}
 
-    // Transform the main body of the query
-    apply_set_expression(&mut query.body, data_store_mapping, semantic_model)?;
+/// Maps the Postgres functions, expressions and query clauses anywhere in the
+/// query to the data store's, innermost first.
+///
+/// Runs before MEASURE functions are expanded, as measures are already written
+/// for the data store.
+fn map_expressions<M: DataStoreMapping>(query: &mut Query, data_store: &M) -> Result<(), SqlError> {
+    match query.visit(&mut DataStoreMapper { data_store }) {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+struct DataStoreMapper<'a, M> {
+    data_store: &'a M,
+}
+
+impl<M: DataStoreMapping> VisitorMut for DataStoreMapper<'_, M> {
+    type Break = SqlError;
+
+    fn post_visit_query(&mut self, query: &mut Query) -> ControlFlow<SqlError> {
+        match self.data_store.map_query(query) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(SqlError::UnsupportedSqlConstruct(e)),
+        }
+    }
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<SqlError> {
+        let is_function = matches!(expr, Expr::Function(_));
+        match self.data_store.map_expression(expr) {
+            Ok(Some(mapped)) => *expr = mapped,
+            Ok(None) => {}
+            Err(e) => return ControlFlow::Break(SqlError::UnsupportedSqlConstruct(e)),
+        }
+        if let Expr::Cast { data_type, .. } = expr {
+            if let Some(mapped) = map_data_type(data_type, self.data_store) {
+                *data_type = mapped;
+            }
+        }
+        // Calls the query makes are mapped by name, not those built by map_expression
+        if let (true, Expr::Function(func)) = (is_function, &mut *expr) {
+            match map_function(func, self.data_store) {
+                Ok(Some(mapped)) => *expr = mapped,
+                Ok(None) => {}
+                Err(e) => return ControlFlow::Break(e),
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Maps a type the query casts to, keeping its length, precision and scale, or
+/// returns `None` when the data store has the same type.
+fn map_data_type<M: DataStoreMapping>(data_type: &DataType, data_store: &M) -> Option<DataType> {
+    let name = data_store.map_type(&pg_type(data_type)?)?;
+    let modifiers = match data_type {
+        DataType::Char(Some(CharacterLength::IntegerLength { length, .. }))
+        | DataType::Character(Some(CharacterLength::IntegerLength { length, .. }))
+        | DataType::Varchar(Some(CharacterLength::IntegerLength { length, .. }))
+        | DataType::CharacterVarying(Some(CharacterLength::IntegerLength { length, .. }))
+        | DataType::CharVarying(Some(CharacterLength::IntegerLength { length, .. })) => {
+            vec![length.to_string()]
+        }
+        DataType::Numeric(info) | DataType::Decimal(info) | DataType::Dec(info) => match info {
+            ExactNumberInfo::None => vec![],
+            ExactNumberInfo::Precision(precision) => vec![precision.to_string()],
+            ExactNumberInfo::PrecisionAndScale(precision, scale) => {
+                vec![precision.to_string(), scale.to_string()]
+            }
+        },
+        DataType::Time(Some(precision), _) | DataType::Timestamp(Some(precision), _) => {
+            vec![precision.to_string()]
+        }
+        _ => vec![],
+    };
+    Some(DataType::Custom(
+        ObjectName(vec![Ident::new(name)]),
+        modifiers,
+    ))
+}
+
+/// Relations defined by the query itself, CTEs and aliased subqueries, which
+/// are not looked up in the semantic model store.
+///
+/// Each relation maps to the columns of its projection, or `None` when they
+/// cannot be inferred and references to it are not validated.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    relations: HashMap<String, Option<Vec<String>>>,
+}
+
+impl Scope {
+    fn get(&self, name: &str) -> Option<&Option<Vec<String>>> {
+        self.relations.get(name)
+    }
+
+    fn insert(&mut self, alias: &TableAlias, columns: Option<Vec<String>>) {
+        // Column aliases such as `cte (a, b)` rename the projection
+        let columns = if alias.columns.is_empty() {
+            columns
+        } else {
+            Some(alias.columns.iter().map(|c| c.value.clone()).collect())
+        };
+        self.relations.insert(alias.name.value.clone(), columns);
+    }
+}
+
+/// The relation a SELECT reads from, which its columns are validated against.
+enum Relation {
+    Model(SemanticModel),
+    Local {
+        name: String,
+        columns: Option<Vec<String>>,
+    },
+}
+
+impl Relation {
+    fn name(&self) -> &str {
+        match self {
+            Relation::Model(model) => &model.name,
+            Relation::Local { name, .. } => name,
+        }
+    }
+
+    fn has_column(&self, column: &str) -> bool {
+        match self {
+            Relation::Model(model) => model.dimensions.iter().any(|dim| dim.name == column),
+            Relation::Local { columns, .. } => columns
+                .as_ref()
+                .is_none_or(|columns| columns.iter().any(|c| c == column)),
+        }
+    }
+}
 
-    // Transform each CTE if present
+/// Transforms a query with the relations of the enclosing queries in scope and
+/// returns the columns of its projection.
+fn transform_query<M: DataStoreMapping, S: SemanticModelStore>(
+    query: &mut Query,
+    data_store_mapping: &M,
+    semantic_model: &S,
+    scope: &Scope,
+) -> Result<Option<Vec<String>>, SqlError> {
+    log::trace!("apply_transformations: input query = {}", query);
+
+    // Transform each CTE if present, each one can read the ones before it
+    let mut scope = scope.clone();
     if let Some(with) = &mut query.with {
         for cte in &mut with.cte_tables {
-            apply_transformations(&mut cte.query, data_store_mapping, semantic_model)?;
+            if with.recursive {
+                scope.insert(&cte.alias, None);
+            }
+            let columns =
+                transform_query(&mut cte.query, data_store_mapping, semantic_model, &scope)?;
+            scope.insert(&cte.alias, columns);
         }
     }
 
-    log::trace!(
-        "apply_transformations: transformed query = {}",
-        query.to_string()
-    );
-    Ok(())
+    // Transform the main body of the query
+    let columns =
+        apply_set_expression(&mut query.body, data_store_mapping, semantic_model, &scope)?;
+
+    // Transform ORDER BY clause if present
+    if let Some(order_by) = &mut query.order_by {
+        for order_by_expr in &mut order_by.exprs {
+            rewrite_expression(
+                &mut order_by_expr.expr,
+                data_store_mapping,
+                semantic_model,
+                &scope,
+            )?;
+        }
+    }
+
+    log::trace!("apply_transformations: transformed query = {}", query);
+    Ok(columns)
 }
 
 /// Applies transformations to a SQL set expression.
@@ -42,25 +206,25 @@ fn apply_set_expression<D: DataStoreMapping, S: SemanticModelStore>(
     set_expr: &mut SetExpr,
     data_store: &D,
     semantic_model: &S,
-) -> Result<(), SqlError> {
-    log::trace!(
-        "apply_set_expression: input set_expr = {}",
-        set_expr.to_string()
-    );
+    scope: &Scope,
+) -> Result<Option<Vec<String>>, SqlError> {
+    log::trace!("apply_set_expression: input set_expr = {}", set_expr);
 
-    match set_expr {
+    let columns = match set_expr {
         SetExpr::Select(select) => {
             log::trace!("apply_set_expression: processing SELECT");
-            apply_select_transformations(select, data_store, semantic_model)?
+            apply_select_transformations(select, data_store, semantic_model, scope)?
         }
         SetExpr::Query(query) => {
             log::trace!("apply_set_expression: processing subquery");
-            apply_transformations(query, data_store, semantic_model)?
+            transform_query(query, data_store, semantic_model, scope)?
         }
         SetExpr::SetOperation { left, right, .. } => {
             log::trace!("apply_set_expression: processing set operation");
-            apply_set_expression(left, data_store, semantic_model)?;
-            apply_set_expression(right, data_store, semantic_model)?;
+            // The columns of a set operation are named by its first query
+            let columns = apply_set_expression(left, data_store, semantic_model, scope)?;
+            apply_set_expression(right, data_store, semantic_model, scope)?;
+            columns
         }
         _ => {
             log::trace!("apply_set_expression: unsupported set expression type");
@@ -68,123 +232,158 @@ fn apply_set_expression<D: DataStoreMapping, S: SemanticModelStore>(
                 "Unsupported set expression type".to_string(),
             ));
         }
-    }
+    };
 
-    log::trace!(
-        "apply_set_expression: transformed set_expr = {}",
-        set_expr.to_string()
-    );
-    Ok(())
+    log::trace!("apply_set_expression: transformed set_expr = {}", set_expr);
+    Ok(columns)
 }
 
 /// Applies transformations to a SELECT statement.
 ///
-/// This function processes each item in the SELECT list, as well as HAVING and QUALIFY clauses
-/// if present. It handles both named and unnamed expressions.
+/// This function processes each item in the SELECT list, as well as WHERE, HAVING and QUALIFY
+/// clauses if present. It handles both named and unnamed expressions, and returns the columns of the
+/// transformed projection.
 fn apply_select_transformations<D: DataStoreMapping, S: SemanticModelStore>(
     select: &mut Select,
     data_store: &D,
     model_store: &S,
-) -> Result<(), SqlError> {
-    log::trace!(
-        "apply_select_transformations: input select = {}",
-        select.to_string()
-    );
-
-    // First, try to get the semantic model for the table being queried
-    let model = get_model_from_select(select, model_store);
-
-    // Handle different cases based on the select projection and model availability
-    match (model, &mut select.projection[..]) {
-        // Information_schema tables query with wildcard
-        (Err(e @ SqlError::InformationSchemaResult(_)), [SelectItem::Wildcard(_)]) => {
-            log::trace!("apply_select_transformations: handling information_schema.tables");
-            return Err(e);
+    scope: &Scope,
+) -> Result<Option<Vec<String>>, SqlError> {
+    log::trace!("apply_select_transformations: input select = {}", select);
+
+    // Transform the subqueries in FROM, which the rest of the SELECT can refer to by alias
+    let mut scope = scope.clone();
+    for table in &mut select.from {
+        let joins = table.joins.iter_mut().map(|join| &mut join.relation);
+        for relation in std::iter::once(&mut table.relation).chain(joins) {
+            if let TableFactor::Derived {
+                subquery, alias, ..
+            } = relation
+            {
+                log::trace!("apply_select_transformations: processing derived table");
+                let columns = transform_query(subquery, data_store, model_store, &scope)?;
+                if let Some(alias) = alias {
+                    scope.insert(alias, columns);
+                }
+            }
         }
+    }
 
-        // Unsupported information schema query
-        (Err(SqlError::InformationSchemaResult(_)), _) => {
-            log::trace!("apply_select_transformations: Unsupported information schema query");
-            return Err(SqlError::SqlTransformationError(
-                "Unsupported information.schema query".to_owned(),
-            ));
-        }
+    // First, try to get the relation being queried
+    let relation = get_relation_from_select(select, model_store, &scope);
+    let single_relation = select.from.len() == 1 && select.from[0].joins.is_empty();
 
-        // Unsupported information schema query
+    // Handle different cases based on the select projection and relation
+    match (relation, &mut select.projection[..]) {
+        // No semantic model for the table being queried
         (Err(e), _) => {
-            log::trace!("apply_select_transformations: Error {}", e.to_string());
+            log::trace!("apply_select_transformations: Error {}", e);
             return Err(e);
         }
 
         // We have a semantic model and a single wildcard
-        (Ok(model), [SelectItem::Wildcard(_)]) => {
+        (Ok(Relation::Model(model)), [SelectItem::Wildcard(_)]) if single_relation => {
             log::trace!("apply_select_transformations: processing wildcard with model");
             let mut temp_select = select.clone();
             process_wildcard_expr(&mut temp_select, &model)?;
             *select = temp_select;
         }
 
-        // Case 3: We have a semantic model, process each projection
-        (Ok(model), _) => {
+        // A wildcard over a CTE or subquery keeps the columns it already selects
+        (Ok(Relation::Local { .. }), [SelectItem::Wildcard(_)]) if single_relation => {
+            log::trace!("apply_select_transformations: keeping wildcard over local relation");
+        }
+
+        // Case 3: We have a relation, process each projection
+        (Ok(relation), _) => {
             for projection in &mut select.projection {
                 match projection {
                     SelectItem::ExprWithAlias { expr, .. } => {
-                        rewrite_expression(expr, data_store, model_store)?;
+                        rewrite_expression(expr, data_store, model_store, &scope)?;
                     }
                     SelectItem::UnnamedExpr(expr) => {
                         log::trace!("apply_select_transformations: processing unnamed expression");
                         let old_expr = expr.clone();
-                        let new_expr = rewrite_expression(expr, data_store, model_store)?;
-                        process_unnamed_expr(projection, &old_expr, new_expr, &model, model_store)?;
-                    }
-                    SelectItem::QualifiedWildcard(object_name, _) => {
-                        if let Some(_table) = object_name.0.last() {
-                            // Handle qualified wildcard (table.* or schema.table.*)
-                            // Add your logic here
-                            todo!("Handle table.* wildcard. Replace with table.column1, table.column2 etc")
-                        } else {
-                            return Err(SqlError::SqlTransformationError(
-                                "Invalid qualified wildcard".to_string(),
-                            ));
-                        }
+                        let new_expr = rewrite_expression(expr, data_store, model_store, &scope)?;
+                        process_unnamed_expr(
+                            projection,
+                            &old_expr,
+                            new_expr,
+                            &relation,
+                            model_store,
+                            &scope,
+                            &select.from,
+                        )?;
                     }
-                    SelectItem::Wildcard(_) => {
-                        log::trace!("apply_select_transformations: Unsupported query wildcard with multiple projects");
-                        return Err(SqlError::SqlTransformationError(
-                            "Wildcard with multiple projects is not supported".to_owned(),
-                        ));
+                    SelectItem::QualifiedWildcard(..) | SelectItem::Wildcard(_) => {
+                        log::trace!("apply_select_transformations: deferring wildcard expansion");
                     }
                 }
             }
+
+            let is_wildcard = |item: &SelectItem| {
+                matches!(
+                    item,
+                    SelectItem::QualifiedWildcard(..) | SelectItem::Wildcard(_)
+                )
+            };
+            if select.projection.iter().any(is_wildcard) {
+                expand_wildcards(select, model_store, &scope)?;
+            }
         }
     }
 
+    // Transform WHERE clause if present, where measures can't be aggregated
+    if let Some(selection) = &mut select.selection {
+        log::trace!("apply_select_transformations: processing WHERE clause");
+        rewrite_condition(selection, data_store, model_store, &scope, "WHERE")?;
+    }
+
     // Transform HAVING clause if present
     if let Some(having) = &mut select.having {
         log::trace!("apply_select_transformations: processing HAVING clause");
-        rewrite_expression(having, data_store, model_store)?;
+        rewrite_expression(having, data_store, model_store, &scope)?;
     }
 
     // Transform QUALIFY clause if present
     if let Some(qualify) = &mut select.qualify {
         log::trace!("apply_select_transformations: processing QUALIFY clause");
-        rewrite_expression(qualify, data_store, model_store)?;
+        rewrite_expression(qualify, data_store, model_store, &scope)?;
     }
 
     log::trace!(
         "apply_select_transformations: transformed select = {}",
-        select.to_string()
+        select
     );
-    Ok(())
+    Ok(projection_columns(select, &scope))
+}
+
+/// Infers the names of the columns a transformed SELECT returns, or `None` when
+/// an item has no name that can be referred to.
+fn projection_columns(select: &Select, scope: &Scope) -> Option<Vec<String>> {
+    let mut columns = Vec::new();
+    for item in &select.projection {
+        match item {
+            SelectItem::UnnamedExpr(Expr::Identifier(ident)) => columns.push(ident.value.clone()),
+            SelectItem::UnnamedExpr(Expr::CompoundIdentifier(idents)) => {
+                columns.push(idents.last()?.value.clone())
+            }
+            SelectItem::ExprWithAlias { alias, .. } => columns.push(alias.value.clone()),
+            // Wildcards over semantic models are expanded, so only local relations remain
+            SelectItem::Wildcard(_) => {
+                let name = first_table_name(select)?;
+                columns.extend(scope.get(&name)?.clone()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(columns)
 }
 
 // Proccesses a wildcard expression in the SELECT list.
 //
 // This function handles the conversion of wildcards into the dimensions of the semantic model.
-fn process_wildcard_expr(
-    select: &mut Select,
-    model: &crate::semantic_model::SemanticModel,
-) -> Result<(), SqlError> {
+fn process_wildcard_expr(select: &mut Select, model: &SemanticModel) -> Result<(), SqlError> {
     {
         // If a semantic model is provided, proceed to get the dimensions, otherwise skip
         // Get the dimensions from the semantic model
@@ -207,60 +406,189 @@ fn process_wildcard_expr(
     Ok(())
 }
 
+/// Expands `*` and `table.*` into the qualified columns of the relations in FROM, so
+/// they can be mixed with other items and span joins.
+///
+/// Wildcards over CTEs or subqueries whose columns are unknown are kept as written.
+fn expand_wildcards<S: SemanticModelStore>(
+    select: &mut Select,
+    model_store: &S,
+    scope: &Scope,
+) -> Result<(), SqlError> {
+    let relations = from_relations(select, model_store, scope)?;
+
+    let mut projection = Vec::with_capacity(select.projection.len());
+    for item in std::mem::take(&mut select.projection) {
+        match &item {
+            SelectItem::Wildcard(_) => {
+                for (qualifier, relation) in &relations {
+                    match qualified_columns(qualifier, relation) {
+                        Some(columns) => projection.extend(columns),
+                        None => projection.push(SelectItem::QualifiedWildcard(
+                            ObjectName(vec![qualifier.clone()]),
+                            WildcardAdditionalOptions::default(),
+                        )),
+                    }
+                }
+            }
+            SelectItem::QualifiedWildcard(ObjectName(idents), _) => {
+                let name = idents.last().map(|ident| ident.value.as_str());
+                let (qualifier, relation) = relations
+                    .iter()
+                    .find(|(qualifier, _)| Some(qualifier.value.as_str()) == name)
+                    .ok_or_else(|| {
+                        SqlError::SemanticModelNotFound(name.unwrap_or_default().to_string())
+                    })?;
+                match qualified_columns(qualifier, relation) {
+                    Some(columns) => projection.extend(columns),
+                    None => projection.push(item),
+                }
+            }
+            _ => projection.push(item),
+        }
+    }
+
+    select.projection = projection;
+    Ok(())
+}
+
+/// Resolves every relation in FROM, joins included, along with the name its
+/// columns are qualified by.
+fn from_relations<S: SemanticModelStore>(
+    select: &Select,
+    model_store: &S,
+    scope: &Scope,
+) -> Result<Vec<(Ident, Relation)>, SqlError> {
+    let mut relations = Vec::new();
+    for table in &select.from {
+        let joins = table.joins.iter().map(|join| &join.relation);
+        for factor in std::iter::once(&table.relation).chain(joins) {
+            match factor {
+                TableFactor::Table {
+                    name: ObjectName(idents),
+                    alias,
+                    ..
+                } => {
+                    let Some(table_name) = idents.last() else {
+                        continue;
+                    };
+                    let qualifier = alias.as_ref().map_or(table_name, |alias| &alias.name);
+                    let relation = match scope.get(&table_name.value) {
+                        Some(columns) if idents.len() == 1 => Relation::Local {
+                            name: qualifier.value.clone(),
+                            columns: columns.clone(),
+                        },
+                        _ => Relation::Model(
+                            model_store
+                                .get_semantic_model(&table_name.value)
+                                .map_err(|e| semantic_model_error(e, &table_name.value))?,
+                        ),
+                    };
+                    relations.push((qualifier.clone(), relation));
+                }
+                TableFactor::Derived {
+                    alias: Some(alias), ..
+                } => {
+                    let relation = Relation::Local {
+                        name: alias.name.value.clone(),
+                        columns: scope.get(&alias.name.value).cloned().flatten(),
+                    };
+                    relations.push((alias.name.clone(), relation));
+                }
+                _ => {
+                    return Err(SqlError::UnsupportedSqlConstruct(
+                        "Wildcards are only supported over tables and aliased subqueries"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(relations)
+}
+
+/// The columns of a relation qualified by its name or alias, or `None` when they are
+/// unknown.
+fn qualified_columns(qualifier: &Ident, relation: &Relation) -> Option<Vec<SelectItem>> {
+    let columns = match relation {
+        Relation::Model(model) => model
+            .dimensions
+            .iter()
+            .map(|dim| dim.name.clone())
+            .collect(),
+        Relation::Local { columns, .. } => columns.clone()?,
+    };
+    let columns = columns
+        .into_iter()
+        .map(|column| {
+            SelectItem::UnnamedExpr(Expr::CompoundIdentifier(vec![
+                qualifier.clone(),
+                Ident::new(column),
+            ]))
+        })
+        .collect();
+    Some(columns)
+}
+
 /// Processes an unnamed expression in the SELECT list.
 ///
 /// This function handles the special case of MEASURE functions and determines
 /// whether to add an alias to the expression based on its type.
-/// Additionally, it checks if any selected columns are not present in the semantic model dimensions.
+/// Additionally, it checks if any selected columns are not present in the relation they
+/// are selected from.
 fn process_unnamed_expr<S: SemanticModelStore>(
     projection: &mut SelectItem,
     old_expr: &Expr,
     rewritten_expr: Expr,
-    semantic_model: &crate::semantic_model::SemanticModel,
+    relation: &Relation,
     model_store: &S,
+    scope: &Scope,
+    from: &[TableWithJoins],
 ) -> Result<(), SqlError> {
-    log::trace!(
-        "process_unnamed_expr: input old_expr = {}",
-        old_expr.to_string()
-    );
+    log::trace!("process_unnamed_expr: input old_expr = {}", old_expr);
 
-    // Extract model and verify column exists in one step
+    // Extract relation and verify column exists in one step
     match old_expr {
-        Expr::Identifier(ident) => {
-            if !semantic_model
-                .dimensions
-                .iter()
-                .any(|dim| dim.name == ident.value)
-            {
-                log::trace!(
-                    "process_unnamed_expr: column {} not found in semantic model {}",
-                    ident.value,
-                    semantic_model.name,
-                );
-                return Err(SqlError::SqlColumnNotFoundError(
-                    ident.value.clone(),
-                    semantic_model.name.clone(),
-                ));
-            }
+        Expr::Identifier(ident) if !relation.has_column(&ident.value) => {
+            log::trace!(
+                "process_unnamed_expr: column {} not found in relation {}",
+                ident.value,
+                relation.name(),
+            );
+            return Err(SqlError::SqlColumnNotFoundError(
+                ident.value.clone(),
+                relation.name().to_string(),
+            ));
         }
         Expr::CompoundIdentifier(idents) => {
             if let Some([table, column]) = idents.last_chunk::<2>() {
-                let model = if table.value != semantic_model.name {
-                    &model_store
-                        .get_semantic_model(&table.value)
-                        .map_err(|e| SqlError::SemanticModelError(e.to_string()))?
+                let other;
+                let table_relation = if let Some(columns) = scope.get(&table.value) {
+                    other = Relation::Local {
+                        name: table.value.clone(),
+                        columns: columns.clone(),
+                    };
+                    &other
+                } else if table.value != relation.name() {
+                    let table_name = aliased_table(from, &table.value).unwrap_or(&table.value);
+                    other = Relation::Model(
+                        model_store
+                            .get_semantic_model(table_name)
+                            .map_err(|e| semantic_model_error(e, table_name))?,
+                    );
+                    &other
                 } else {
-                    semantic_model
+                    relation
                 };
-                if !model.dimensions.iter().any(|dim| dim.name == column.value) {
+                if !table_relation.has_column(&column.value) {
                     log::trace!(
-                        "process_unnamed_expr: column {} not found in semantic model {}",
+                        "process_unnamed_expr: column {} not found in relation {}",
                         column.value,
-                        semantic_model.name,
+                        table_relation.name(),
                     );
                     return Err(SqlError::SqlColumnNotFoundError(
                         column.value.clone(),
-                        semantic_model.name.clone(),
+                        table_relation.name().to_string(),
                     ));
                 }
             } else {
@@ -272,98 +600,214 @@ fn process_unnamed_expr<S: SemanticModelStore>(
         _ => (),
     }
 
-    // Handle function expressions (especially MEASURE)
-    if let Expr::Function(func) = old_expr {
-        let args = get_function_args(func)?;
-        if !args.args.is_empty() {
+    // Name rewritten MEASURE functions after the measure
+    match old_expr {
+        Expr::Function(func) if is_measure(func) => {
+            let args = get_function_args(func)?;
             let ident = get_identifier_from_args(args)?;
             let (_, measure_name) = get_measure_info(&ident)?;
-
-            if func.name.to_string().to_uppercase() == "MEASURE" {
-                *projection = SelectItem::ExprWithAlias {
-                    expr: rewritten_expr.clone(),
-                    alias: Ident::new(measure_name.to_string()),
-                };
-            } else {
-                *projection = SelectItem::UnnamedExpr(rewritten_expr.clone());
-            }
-        } else {
-            *projection = SelectItem::UnnamedExpr(rewritten_expr);
+            *projection = SelectItem::ExprWithAlias {
+                expr: rewritten_expr,
+                alias: Ident::new(measure_name.to_string()),
+            };
         }
-    } else {
-        *projection = SelectItem::UnnamedExpr(rewritten_expr);
+        _ => *projection = SelectItem::UnnamedExpr(rewritten_expr),
     }
 
-    log::trace!(
-        "process_unnamed_expr: output projection = {}",
-        projection.to_string()
-    );
+    log::trace!("process_unnamed_expr: output projection = {}", projection);
     Ok(())
 }
 
-/// Rewrites an expression based on the data store mapping and semantic model.
-///
-/// This function handles different types of expressions, including functions,
-/// binary operations, and EXISTS clauses.
+/// Returns the name of the table in FROM that has the given alias.
+fn aliased_table<'a>(from: &'a [TableWithJoins], alias: &str) -> Option<&'a str> {
+    from.iter()
+        .flat_map(|table| {
+            let joins = table.joins.iter().map(|join| &join.relation);
+            std::iter::once(&table.relation).chain(joins)
+        })
+        .find_map(|factor| match factor {
+            TableFactor::Table {
+                name: ObjectName(idents),
+                alias: Some(table_alias),
+                ..
+            } if table_alias.name.value == alias => idents.last().map(|i| i.value.as_str()),
+            _ => None,
+        })
+}
+
+/// Expands the MEASURE functions anywhere in an expression, and transforms the
+/// subqueries it contains with the relations of the enclosing query in scope.
 fn rewrite_expression<D: DataStoreMapping, S: SemanticModelStore>(
     expr: &mut Expr,
     data_store: &D,
     semantic_model: &S,
+    scope: &Scope,
 ) -> Result<Expr, SqlError> {
-    log::trace!("rewrite_expression: input expr = {}", expr.to_string());
-    let new_expr = match expr {
-        Expr::Function(func) => rewrite_function(func, data_store, semantic_model)?,
-        Expr::BinaryOp { left, right, op } => {
-            let new_left = rewrite_expression(left, data_store, semantic_model)?;
-            let new_right = rewrite_expression(right, data_store, semantic_model)?;
-            Expr::BinaryOp {
-                left: Box::new(new_left),
-                right: Box::new(new_right),
-                op: op.clone(),
-            }
+    log::trace!("rewrite_expression: input expr = {}", expr);
+    visit_expression(expr, data_store, semantic_model, scope, None)?;
+    log::trace!("rewrite_expression: output expr = {}", expr);
+    Ok(expr.clone())
+}
+
+/// Transforms the subqueries of an expression in a clause where aggregates are
+/// not allowed, such as WHERE, rejecting MEASURE functions in it.
+fn rewrite_condition<D: DataStoreMapping, S: SemanticModelStore>(
+    expr: &mut Expr,
+    data_store: &D,
+    semantic_model: &S,
+    scope: &Scope,
+    clause: &'static str,
+) -> Result<(), SqlError> {
+    log::trace!("rewrite_condition: input {} = {}", clause, expr);
+    visit_expression(expr, data_store, semantic_model, scope, Some(clause))
+}
+
+fn visit_expression<D: DataStoreMapping, S: SemanticModelStore>(
+    expr: &mut Expr,
+    data_store: &D,
+    semantic_model: &S,
+    scope: &Scope,
+    rejected_in: Option<&'static str>,
+) -> Result<(), SqlError> {
+    let mut rewriter = MeasureRewriter {
+        data_store,
+        semantic_model,
+        scope,
+        rejected_in,
+        depth: 0,
+    };
+    match expr.visit(&mut rewriter) {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+/// Rewrites MEASURE functions in every position of an expression.
+///
+/// Subqueries are transformed as a whole before the visitor walks into them,
+/// so expressions within them are left alone.
+struct MeasureRewriter<'a, D, S> {
+    data_store: &'a D,
+    semantic_model: &'a S,
+    scope: &'a Scope,
+    /// The clause MEASURE functions are rejected in, if any.
+    rejected_in: Option<&'static str>,
+    /// How many subqueries deep the visitor is.
+    depth: usize,
+}
+
+impl<D: DataStoreMapping, S: SemanticModelStore> VisitorMut for MeasureRewriter<'_, D, S> {
+    type Break = SqlError;
+
+    fn pre_visit_query(&mut self, _query: &mut Query) -> ControlFlow<SqlError> {
+        self.depth += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &mut Query) -> ControlFlow<SqlError> {
+        self.depth -= 1;
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<SqlError> {
+        let (Expr::Exists { subquery, .. }
+        | Expr::Subquery(subquery)
+        | Expr::InSubquery { subquery, .. }) = expr
+        else {
+            return ControlFlow::Continue(());
+        };
+        if self.depth > 0 {
+            return ControlFlow::Continue(());
         }
-        Expr::Exists { subquery, negated } => {
-            apply_transformations(subquery, data_store, semantic_model)?;
-            Expr::Exists {
-                subquery: subquery.clone(),
-                negated: *negated,
-            }
+        log::trace!("rewrite_expression: processing subquery");
+        match transform_query(subquery, self.data_store, self.semantic_model, self.scope) {
+            Ok(_) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(e),
         }
-        _ => expr.clone(),
-    };
+    }
 
-    *expr = new_expr.clone();
-    log::trace!("rewrite_expression: output expr = {}", new_expr.to_string());
-    Ok(new_expr)
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<SqlError> {
+        let Expr::Function(func) = expr else {
+            return ControlFlow::Continue(());
+        };
+        if self.depth > 0 || !is_measure(func) {
+            return ControlFlow::Continue(());
+        }
+        if let Some(clause) = self.rejected_in {
+            return ControlFlow::Break(SqlError::MeasureNotAllowed(clause.to_string()));
+        }
+        match rewrite_measure(func, self.data_store, self.semantic_model) {
+            Ok(rewritten) => *expr = rewritten,
+            Err(e) => return ControlFlow::Break(e),
+        }
+        ControlFlow::Continue(())
+    }
 }
 
-/// Rewrites a function expression, handling MEASURE functions specially.
-///
-/// For MEASURE functions, it applies semantic model transformations.
-/// For other functions, it applies data store specific mappings if available.
-fn rewrite_function<D: DataStoreMapping, S: SemanticModelStore>(
+fn is_measure(func: &Function) -> bool {
+    func.name.to_string().to_uppercase() == "MEASURE"
+}
+
+/// Maps a function by its name and number of arguments, or returns `None` when the
+/// data store runs it as is.
+fn map_function<D: DataStoreMapping>(
     func: &mut Function,
     data_store: &D,
-    semantic_model: &S,
-) -> Result<Expr, SqlError> {
-    log::trace!("rewrite_function: input func = {}", func.to_string());
-    let result = if func.name.to_string().to_uppercase() == "MEASURE" {
-        rewrite_measure(func, data_store, semantic_model)
-    } else if let Some(mapped_func) = data_store.map_function(func.to_string().as_str()) {
-        Ok(Expr::Function(Function {
-            name: ObjectName(vec![Ident::new(mapped_func)]),
-            args: FunctionArguments::None,
-            over: None,
-            parameters: FunctionArguments::None,
-            filter: None,
-            null_treatment: None,
-            within_group: vec![],
-        }))
-    } else {
-        Ok(Expr::Function(func.clone()))
+) -> Result<Option<Expr>, SqlError> {
+    // Schema qualified functions are user defined rather than built in
+    let [name] = &func.name.0[..] else {
+        return Ok(None);
     };
-    log::trace!("rewrite_function: output = {:?}", result);
-    result
+    let name = name.value.to_lowercase();
+    let arity = match &func.args {
+        FunctionArguments::List(list) => list.args.len(),
+        _ => 0,
+    };
+
+    match data_store.map_function(&name, arity) {
+        Some(FunctionMapping::Rename(mapped_name)) => {
+            func.name = ObjectName(vec![Ident::new(mapped_name)]);
+            Ok(None)
+        }
+        Some(FunctionMapping::Rewrite(rewrite)) => {
+            let args = positional_args(func).ok_or_else(|| {
+                SqlError::InvalidFunctionArgument(format!(
+                    "{} only supports positional arguments",
+                    name
+                ))
+            })?;
+            rewrite(args)
+                .map(Some)
+                .map_err(SqlError::InvalidFunctionArgument)
+        }
+        Some(FunctionMapping::RewriteCall(rewrite)) => rewrite(func.clone())
+            .map(Some)
+            .map_err(SqlError::InvalidFunctionArgument),
+        Some(FunctionMapping::Unsupported(hint)) => Err(SqlError::UnsupportedSqlConstruct(
+            format!("{} is not supported by the data store, {}", name, hint),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The arguments of a plain function call, or `None` for named or wildcard arguments
+/// and clauses such as `ORDER BY` within the call.
+fn positional_args(func: &Function) -> Option<Vec<Expr>> {
+    match &func.args {
+        FunctionArguments::None => Some(vec![]),
+        FunctionArguments::List(list)
+            if list.duplicate_treatment.is_none() && list.clauses.is_empty() =>
+        {
+            list.args
+                .iter()
+                .map(|arg| match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Some(expr.clone()),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => None,
+    }
 }
 
 /// Rewrites a MEASURE function based on the semantic model.
@@ -371,88 +815,104 @@ fn rewrite_function<D: DataStoreMapping, S: SemanticModelStore>(
 /// This function extracts the measure name, looks it up in the semantic model,
 /// and replaces the MEASURE function with the actual SQL expression for the measure.
 fn rewrite_measure<D: DataStoreMapping, S: SemanticModelStore>(
-    func: &mut Function,
+    func: &Function,
     data_store: &D,
     semantic_model: &S,
 ) -> Result<Expr, SqlError> {
-    log::trace!("rewrite_measure: input func = {}", func.to_string());
+    log::trace!("rewrite_measure: input func = {}", func);
     let args = get_function_args(func)?;
     let ident = get_identifier_from_args(args)?;
     let (table_name, measure_name) = get_measure_info(&ident)?;
 
     let model = semantic_model
         .get_semantic_model(table_name)
-        .map_err(|e| SqlError::SemanticModelError(e.to_string()))?;
+        .map_err(|e| semantic_model_error(e, table_name))?;
 
-    let measure = model
-        .get_measure(measure_name)
-        .map_err(|e| SqlError::SemanticModelError(e.to_string()))?;
+    let measure = model.get_measure(measure_name).map_err(|_| {
+        SqlError::SqlColumnNotFoundError(measure_name.to_string(), table_name.to_string())
+    })?;
 
     let sql = measure
         .render(&model, true)
         .map_err(|e| SqlError::SemanticModelError(e.to_string()))?;
 
     let expr = parse_measure_sql(&sql, data_store.get_dialect())?;
-
-    *func = Function {
-        name: ObjectName(vec![Ident::new(expr.to_string())]),
-        args: FunctionArguments::None,
-        over: None,
-        parameters: FunctionArguments::None,
-        filter: None,
-        null_treatment: None,
-        within_group: vec![],
-    };
-
-    log::trace!("rewrite_measure: output expr = {}", expr.to_string());
+    log::trace!("rewrite_measure: output expr = {}", expr);
     Ok(expr)
 }
 
-fn get_model_from_select<S: SemanticModelStore>(
+/// Resolves the first relation in FROM to a CTE or subquery in scope, or to a
+/// semantic model for base tables.
+fn get_relation_from_select<S: SemanticModelStore>(
     select: &Select,
     semantic_model: &S,
-) -> Result<crate::semantic_model::SemanticModel, SqlError> {
+    scope: &Scope,
+) -> Result<Relation, SqlError> {
+    if let Some(TableFactor::Derived { alias, .. }) = select.from.first().map(|twj| &twj.relation) {
+        let name = alias.as_ref().map(|a| a.name.value.clone());
+        let columns = name
+            .as_ref()
+            .and_then(|name| scope.get(name).cloned().flatten());
+        return Ok(Relation::Local {
+            name: name.unwrap_or_default(),
+            columns,
+        });
+    }
+
     // Extract table name from the first FROM clause
-    let table_name = select
-        .from
-        .first()
-        .and_then(|twj| {
-            if let TableFactor::Table {
-                name: ObjectName(idents),
-                ..
-            } = &twj.relation
-            {
-                // Handle both single identifier and compound (schema.table) cases
-                if idents.len() == 2
-                    && idents[0].value.to_lowercase() == "information_schema"
-                    && idents[1].value.to_lowercase() == "tables"
-                {
-                    // Return all semantic model names for information_schema.tables
-                    return Some("information_schema.tables".to_string());
-                }
-                Some(idents.last().unwrap().value.clone())
-            } else {
-                None
-            }
-        })
-        .unwrap_or_default();
-
-    // Special handling for information_schema.tables
-    if table_name == "information_schema.tables" {
-        let all_models = semantic_model
-            .get_all_semantic_models()
-            .map_err(|e| SqlError::SemanticModelError(e.to_string()))?;
-        return Err(SqlError::InformationSchemaResult(
-            all_models.keys().cloned().collect(),
+    let table_name = first_table_name(select).unwrap_or_default();
+
+    // information_schema is answered by the proxy, which only supports top level queries
+    if table_name == "information_schema" {
+        return Err(SqlError::UnsupportedSqlConstruct(
+            "information_schema can only be queried in a top level SELECT".to_string(),
         ));
     }
 
+    // CTEs shadow semantic models of the same name
+    if let Some(columns) = scope.get(&table_name) {
+        return Ok(Relation::Local {
+            name: table_name,
+            columns: columns.clone(),
+        });
+    }
+
     // Normal case: try to get the semantic model for the table
-    semantic_model.get_semantic_model(&table_name).map_err(|_| {
-        SqlError::SemanticModelError(format!("No semantic model found for table: {}", table_name))
+    semantic_model
+        .get_semantic_model(&table_name)
+        .map(Relation::Model)
+        .map_err(|_| SqlError::SemanticModelNotFound(table_name))
+}
+
+/// Returns the name of the first table in FROM, or `information_schema` for its
+/// tables.
+fn first_table_name(select: &Select) -> Option<String> {
+    select.from.first().and_then(|twj| {
+        if let TableFactor::Table {
+            name: ObjectName(idents),
+            ..
+        } = &twj.relation
+        {
+            // Handle both single identifier and compound (schema.table) cases
+            if idents.len() == 2 && idents[0].value.to_lowercase() == "information_schema" {
+                return Some("information_schema".to_string());
+            }
+            Some(idents.last().unwrap().value.clone())
+        } else {
+            None
+        }
     })
 }
 
+fn semantic_model_error(e: SemanticModelStoreError, table_name: &str) -> SqlError {
+    match e {
+        SemanticModelStoreError::ModelNotFound => {
+            SqlError::SemanticModelNotFound(table_name.to_string())
+        }
+        e => SqlError::SemanticModelError(e.to_string()),
+    }
+}
+
 /// Extracts function arguments from a Function AST node.
 fn get_function_args(func: &Function) -> Result<&FunctionArgumentList, SqlError> {
     match &func.args {
@@ -0,0 +1,1586 @@
+use std::cmp::Ordering;
+use std::iter;
+
+use regex::RegexBuilder;
+use sqlparser::ast::*;
+use tokio_postgres::types::Type;
+
+use super::SqlError;
+
+/// Table synthesized by the proxy instead of being read from the data store.
+///
+/// Values are kept in their Postgres text representation so they can be sent to
+/// the client as is.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VirtualTable {
+    pub columns: Vec<VirtualColumn>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualColumn {
+    pub name: String,
+    pub data_type: Type,
+}
+
+impl VirtualColumn {
+    pub fn new(name: &str, data_type: Type) -> Self {
+        Self {
+            name: name.to_string(),
+            data_type,
+        }
+    }
+}
+
+/// Source of the tables and functions a virtual query can use.
+pub trait VirtualCatalog {
+    /// Returns the table a lower cased relation name refers to, or `None` when
+    /// the relation isn't virtual.
+    fn table(&self, name: &[String]) -> Result<Option<VirtualTable>, SqlError>;
+
+    /// Calls a function provided by the catalog, or returns `None` when the
+    /// catalog doesn't know the function.
+    fn call(&self, name: &str, args: &[Scalar]) -> Option<Result<Scalar, SqlError>>;
+}
+
+/// Evaluates a query over the tables of a [`VirtualCatalog`].
+///
+/// Supports joins, subqueries, set operations, `WHERE`, `DISTINCT`, `ORDER BY`,
+/// `LIMIT` and `OFFSET` with the operators and functions catalog queries
+/// commonly use. Parameter placeholders evaluate to `NULL`.
+pub fn evaluate(query: &Query, catalog: &dyn VirtualCatalog) -> Result<VirtualTable, SqlError> {
+    let output = Evaluator { catalog }.query(query, None)?;
+    Ok(VirtualTable {
+        columns: output.columns,
+        rows: output
+            .rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Scalar::into_text).collect())
+            .collect(),
+    })
+}
+
+fn unsupported(construct: &str) -> SqlError {
+    SqlError::UnsupportedSqlConstruct(format!("{} on virtual tables", construct))
+}
+
+/// Rows a table function may return or a join may combine, since the proxy
+/// computes them in memory.
+const MAX_ROWS: usize = 1_000_000;
+
+fn too_many_rows(construct: &str, count: i128) -> SqlError {
+    SqlError::TooManyRows(format!(
+        "{} would produce {} rows, more than the {} allowed",
+        construct, count, MAX_ROWS
+    ))
+}
+
+/// The integers from `start` to `stop` by `step`, or no rows when any of them
+/// is NULL.
+fn generate_series(
+    name: &str,
+    start: &Scalar,
+    stop: &Scalar,
+    step: &Scalar,
+) -> Result<Output, SqlError> {
+    let integer = |value: &Scalar| match value {
+        Scalar::Number(n) if n.is_finite() && n.fract() == 0.0 && n.abs() < 2f64.powi(63) => {
+            Ok(Some(*n as i64))
+        }
+        Scalar::Null => Ok(None),
+        other => Err(SqlError::InvalidFunctionArgument(format!(
+            "{} takes integer arguments, got {}",
+            name,
+            other.clone().into_text().unwrap_or_default()
+        ))),
+    };
+
+    let (start, stop, step) = match (integer(start)?, integer(stop)?, integer(step)?) {
+        (Some(start), Some(stop), Some(step)) => (start, stop, step),
+        _ => (0, -1, 1),
+    };
+    if step == 0 {
+        return Err(SqlError::InvalidFunctionArgument(
+            "step size cannot equal zero".to_string(),
+        ));
+    }
+
+    // Counting in i128 since the distance between two i64 may not fit in one
+    let distance = stop as i128 - start as i128;
+    let count = match distance.signum() * step.signum() as i128 {
+        -1 => 0,
+        _ => distance / step as i128 + 1,
+    };
+    if count > MAX_ROWS as i128 {
+        return Err(too_many_rows(name, count));
+    }
+
+    let fits_int4 = |n: i64| i32::try_from(n).is_ok();
+    let data_type = match fits_int4(start) && fits_int4(stop) {
+        true => Type::INT4,
+        false => Type::INT8,
+    };
+    Ok(Output {
+        columns: vec![VirtualColumn::new(name, data_type)],
+        rows: (0..count as i64)
+            .map(|idx| vec![Scalar::Number((start + idx * step) as f64)])
+            .collect(),
+    })
+}
+
+fn row_count(expr: &Expr) -> Result<usize, SqlError> {
+    match expr {
+        Expr::Value(Value::Number(n, _)) => n
+            .parse()
+            .map_err(|_| SqlError::InvalidFunctionArgument(format!("Invalid row count: {}", n))),
+        Expr::Value(Value::Null) => Ok(usize::MAX),
+        _ => Err(unsupported("non literal LIMIT and OFFSET")),
+    }
+}
+
+/// Value of an expression evaluated on a virtual table row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Array(Vec<Scalar>),
+}
+
+impl Scalar {
+    fn from_text(value: &Option<String>, data_type: &Type) -> Self {
+        let Some(value) = value else {
+            return Scalar::Null;
+        };
+        match *data_type {
+            Type::BOOL => Scalar::Bool(value == "t" || value == "true"),
+            Type::INT2 | Type::INT4 | Type::INT8 | Type::OID | Type::FLOAT4 | Type::FLOAT8 => value
+                .parse()
+                .map(Scalar::Number)
+                .unwrap_or_else(|_| Scalar::Text(value.clone())),
+            _ => Scalar::Text(value.clone()),
+        }
+    }
+
+    fn into_text(self) -> Option<String> {
+        match self {
+            Scalar::Null => None,
+            Scalar::Bool(true) => Some("t".to_string()),
+            Scalar::Bool(false) => Some("f".to_string()),
+            Scalar::Number(n) => Some(n.to_string()),
+            Scalar::Text(text) => Some(text),
+            Scalar::Array(elements) => {
+                let elements: Vec<String> = elements
+                    .into_iter()
+                    .map(|element| match element.into_text() {
+                        None => "NULL".to_string(),
+                        Some(text)
+                            if text.is_empty()
+                                || text.contains(|c: char| ",{}\"\\ ".contains(c)) =>
+                        {
+                            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+                        }
+                        Some(text) => text,
+                    })
+                    .collect();
+                Some(format!("{{{}}}", elements.join(",")))
+            }
+        }
+    }
+
+    fn as_text(&self) -> Option<String> {
+        self.clone().into_text()
+    }
+
+    /// SQL comparison, `None` when either side is NULL.
+    fn compare(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::Null, _) | (_, Scalar::Null) => None,
+            (Scalar::Number(left), Scalar::Number(right)) => left.partial_cmp(right),
+            (Scalar::Number(left), Scalar::Text(right)) => match right.parse::<f64>() {
+                Ok(right) => left.partial_cmp(&right),
+                Err(_) => Some(left.to_string().cmp(right)),
+            },
+            (Scalar::Text(_), Scalar::Number(_)) => other.compare(self).map(Ordering::reverse),
+            (Scalar::Bool(left), Scalar::Bool(right)) => Some(left.cmp(right)),
+            (Scalar::Bool(left), Scalar::Text(right)) => match right.as_str() {
+                "t" | "true" => Some(left.cmp(&true)),
+                "f" | "false" => Some(left.cmp(&false)),
+                _ => Some(self.as_text().cmp(&other.as_text())),
+            },
+            (Scalar::Text(_), Scalar::Bool(_)) => other.compare(self).map(Ordering::reverse),
+            (left, right) => Some(left.as_text().cmp(&right.as_text())),
+        }
+    }
+
+    /// Ordering used by `ORDER BY`, NULLs sort last unless requested otherwise.
+    fn sort_cmp(&self, other: &Scalar, nulls_first: Option<bool>) -> Ordering {
+        let nulls_first = nulls_first.unwrap_or(false);
+        match (self, other) {
+            (Scalar::Null, Scalar::Null) => Ordering::Equal,
+            (Scalar::Null, _) if nulls_first => Ordering::Less,
+            (Scalar::Null, _) => Ordering::Greater,
+            (_, Scalar::Null) if nulls_first => Ordering::Greater,
+            (_, Scalar::Null) => Ordering::Less,
+            _ => self.compare(other).unwrap_or(Ordering::Equal),
+        }
+    }
+
+    fn truth(&self) -> Result<Option<bool>, SqlError> {
+        match self {
+            Scalar::Null => Ok(None),
+            Scalar::Bool(b) => Ok(Some(*b)),
+            other => Err(SqlError::InvalidFunctionArgument(format!(
+                "argument of boolean operator must be boolean, not {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl From<Option<bool>> for Scalar {
+    fn from(value: Option<bool>) -> Self {
+        value.map(Scalar::Bool).unwrap_or(Scalar::Null)
+    }
+}
+
+/// Rows produced by a query before they are converted to text.
+struct Output {
+    columns: Vec<VirtualColumn>,
+    rows: Vec<Vec<Scalar>>,
+}
+
+/// A relation in the `FROM` clause, named after its alias or table name.
+struct Relation {
+    name: String,
+    columns: Vec<VirtualColumn>,
+}
+
+/// Row being evaluated, along with the rows of the enclosing queries so that
+/// correlated subqueries can reference them.
+struct Scope<'a> {
+    relations: &'a [Relation],
+    row: &'a [Scalar],
+    parent: Option<&'a Scope<'a>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Scope without a row of its own, e.g. for evaluating `FROM` items.
+    fn outer(parent: Option<&'a Scope<'a>>) -> Self {
+        Self {
+            relations: &[],
+            row: &[],
+            parent,
+        }
+    }
+
+    /// Finds the position of a column in this scope's row.
+    fn resolve(&self, idents: &[Ident]) -> Result<Option<(usize, &VirtualColumn)>, SqlError> {
+        let (column, qualifier) = match idents {
+            [.., qualifier, column] => (column, Some(qualifier)),
+            [column] => (column, None),
+            [] => return Err(unsupported("empty identifiers")),
+        };
+
+        let mut found = None;
+        let mut offset = 0;
+        for relation in self.relations {
+            if qualifier.is_none_or(|q| q.value.eq_ignore_ascii_case(&relation.name)) {
+                let position = relation
+                    .columns
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(&column.value));
+                if let Some(idx) = position {
+                    if found.is_some() {
+                        return Err(SqlError::SqlTransformationError(format!(
+                            "column reference \"{}\" is ambiguous",
+                            column.value
+                        )));
+                    }
+                    found = Some((offset + idx, &relation.columns[idx]));
+                }
+            }
+            offset += relation.columns.len();
+        }
+        Ok(found)
+    }
+
+    fn column(&self, idents: &[Ident]) -> Result<Scalar, SqlError> {
+        match (self.resolve(idents)?, self.parent) {
+            (Some((idx, _)), _) => Ok(self.row[idx].clone()),
+            (None, Some(parent)) => parent.column(idents),
+            (None, None) => {
+                let (column, qualifier) = match idents {
+                    [.., qualifier, column] => (column, qualifier.value.clone()),
+                    [column, ..] => (column, String::new()),
+                    [] => return Err(unsupported("empty identifiers")),
+                };
+                Err(SqlError::SqlColumnNotFoundError(
+                    column.value.clone(),
+                    qualifier,
+                ))
+            }
+        }
+    }
+
+    /// Type of the output column for an expression, known for column references.
+    fn column_type(&self, expr: &Expr) -> Option<Type> {
+        let idents = match expr {
+            Expr::Identifier(ident) => std::slice::from_ref(ident),
+            Expr::CompoundIdentifier(idents) => idents,
+            _ => return None,
+        };
+        match self.resolve(idents) {
+            Ok(Some((_, column))) => Some(column.data_type.clone()),
+            _ => self.parent.and_then(|parent| parent.column_type(expr)),
+        }
+    }
+}
+
+struct Evaluator<'c> {
+    catalog: &'c dyn VirtualCatalog,
+}
+
+impl Evaluator<'_> {
+    fn query(&self, query: &Query, outer: Option<&Scope>) -> Result<Output, SqlError> {
+        if query.with.is_some() {
+            return Err(unsupported("WITH"));
+        }
+
+        let order_by = query
+            .order_by
+            .as_ref()
+            .map(|order_by| order_by.exprs.as_slice())
+            .unwrap_or_default();
+        let output = match query.body.as_ref() {
+            SetExpr::Select(select) => self.select(select, order_by, outer)?,
+            body => {
+                let mut output = self.set_expr(body, outer)?;
+                let positions = order_by
+                    .iter()
+                    .map(|order| output_position(&order.expr, &output.columns))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut keyed = output
+                    .rows
+                    .into_iter()
+                    .map(|row| (positions.iter().map(|idx| row[*idx].clone()).collect(), row))
+                    .collect::<Vec<_>>();
+                sort(&mut keyed, order_by);
+                output.rows = keyed.into_iter().map(|(_, row)| row).collect();
+                output
+            }
+        };
+
+        let offset = match &query.offset {
+            Some(offset) => row_count(&offset.value)?,
+            None => 0,
+        };
+        let limit = match &query.limit {
+            Some(limit) => row_count(limit)?,
+            None => usize::MAX,
+        };
+
+        Ok(Output {
+            columns: output.columns,
+            rows: output.rows.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+
+    fn set_expr(&self, body: &SetExpr, outer: Option<&Scope>) -> Result<Output, SqlError> {
+        match body {
+            SetExpr::Select(select) => self.select(select, &[], outer),
+            SetExpr::Query(query) => self.query(query, outer),
+            SetExpr::SetOperation {
+                op,
+                set_quantifier,
+                left,
+                right,
+            } => {
+                let mut output = self.set_expr(left, outer)?;
+                let right = self.set_expr(right, outer)?;
+                if output.columns.len() != right.columns.len() {
+                    return Err(SqlError::SqlTransformationError(format!(
+                        "each {} query must have the same number of columns",
+                        op
+                    )));
+                }
+
+                match op {
+                    SetOperator::Union => output.rows.extend(right.rows),
+                    SetOperator::Except => output.rows.retain(|row| !right.rows.contains(row)),
+                    SetOperator::Intersect => output.rows.retain(|row| right.rows.contains(row)),
+                }
+                if !matches!(set_quantifier, SetQuantifier::All) {
+                    let mut rows: Vec<Vec<Scalar>> = Vec::new();
+                    for row in output.rows {
+                        if !rows.contains(&row) {
+                            rows.push(row);
+                        }
+                    }
+                    output.rows = rows;
+                }
+                Ok(output)
+            }
+            _ => Err(unsupported(&format!("query {}", body))),
+        }
+    }
+
+    fn select(
+        &self,
+        select: &Select,
+        order_by: &[OrderByExpr],
+        outer: Option<&Scope>,
+    ) -> Result<Output, SqlError> {
+        if select.having.is_some()
+            || !matches!(&select.group_by, GroupByExpr::Expressions(e, _) if e.is_empty())
+        {
+            return Err(unsupported("GROUP BY"));
+        }
+
+        let (relations, rows) = self.from(&select.from, outer)?;
+        let scope = |row| Scope {
+            relations: &relations,
+            row,
+            parent: outer,
+        };
+
+        // Filter the source rows
+        let mut kept = Vec::new();
+        for row in &rows {
+            let keep = match &select.selection {
+                Some(selection) => self.eval(selection, &scope(row))? == Scalar::Bool(true),
+                None => true,
+            };
+            if keep {
+                kept.push(row);
+            }
+        }
+
+        // Project the remaining rows
+        let projection = projection(&select.projection, &relations)?;
+        let mut output = kept
+            .into_iter()
+            .map(|row| {
+                let values = projection
+                    .iter()
+                    .map(|(_, expr)| self.eval(expr, &scope(row)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((row, values))
+            })
+            .collect::<Result<Vec<_>, SqlError>>()?;
+
+        if let Some(Distinct::Distinct) = select.distinct {
+            let mut seen: Vec<Vec<Scalar>> = Vec::new();
+            output.retain(|(_, values)| {
+                if seen.contains(values) {
+                    false
+                } else {
+                    seen.push(values.clone());
+                    true
+                }
+            });
+        }
+
+        if !order_by.is_empty() {
+            let mut keyed = output
+                .into_iter()
+                .map(|(row, values)| {
+                    let keys = order_by
+                        .iter()
+                        .map(|order| self.order_key(&order.expr, &scope(row), &projection, &values))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok((keys, (row, values)))
+                })
+                .collect::<Result<Vec<_>, SqlError>>()?;
+            sort(&mut keyed, order_by);
+            output = keyed.into_iter().map(|(_, row)| row).collect();
+        }
+
+        let rows: Vec<Vec<Scalar>> = output.into_iter().map(|(_, values)| values).collect();
+        let columns = projection
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, expr))| VirtualColumn {
+                name: name.clone(),
+                data_type: scope(&[])
+                    .column_type(expr)
+                    .unwrap_or_else(|| infer_type(rows.iter().map(|row| &row[idx]))),
+            })
+            .collect();
+
+        Ok(Output { columns, rows })
+    }
+
+    /// Builds the rows of the `FROM` clause, a single empty row when there is none.
+    fn from(
+        &self,
+        from: &[TableWithJoins],
+        outer: Option<&Scope>,
+    ) -> Result<(Vec<Relation>, Vec<Vec<Scalar>>), SqlError> {
+        let mut relations = Vec::new();
+        let mut rows = vec![vec![]];
+        for table in from {
+            let (relation, right) = self.table_factor(&table.relation, outer)?;
+            rows = self.join(
+                &mut relations,
+                rows,
+                relation,
+                right,
+                None,
+                false,
+                false,
+                outer,
+            )?;
+
+            for join in &table.joins {
+                let (relation, right) = self.table_factor(&join.relation, outer)?;
+                let (constraint, keep_left, keep_right) = match &join.join_operator {
+                    JoinOperator::Inner(constraint) => (Some(constraint), false, false),
+                    JoinOperator::LeftOuter(constraint) => (Some(constraint), true, false),
+                    JoinOperator::RightOuter(constraint) => (Some(constraint), false, true),
+                    JoinOperator::FullOuter(constraint) => (Some(constraint), true, true),
+                    JoinOperator::CrossJoin => (None, false, false),
+                    _ => return Err(unsupported("join type")),
+                };
+                let condition = match constraint {
+                    Some(JoinConstraint::On(expr)) => Some(expr),
+                    Some(JoinConstraint::None) | None => None,
+                    Some(_) => return Err(unsupported("USING and NATURAL joins")),
+                };
+                rows = self.join(
+                    &mut relations,
+                    rows,
+                    relation,
+                    right,
+                    condition,
+                    keep_left,
+                    keep_right,
+                    outer,
+                )?;
+            }
+        }
+        Ok((relations, rows))
+    }
+
+    /// Joins `right` to the rows built so far, padding unmatched rows with
+    /// NULLs for outer joins.
+    #[allow(clippy::too_many_arguments)]
+    fn join(
+        &self,
+        relations: &mut Vec<Relation>,
+        left: Vec<Vec<Scalar>>,
+        relation: Relation,
+        right: Vec<Vec<Scalar>>,
+        condition: Option<&Expr>,
+        keep_left: bool,
+        keep_right: bool,
+        outer: Option<&Scope>,
+    ) -> Result<Vec<Vec<Scalar>>, SqlError> {
+        // Every pair of rows is built before the condition filters it
+        let pairs = left.len() as i128 * right.len() as i128;
+        if pairs > MAX_ROWS as i128 {
+            return Err(too_many_rows("join", pairs));
+        }
+        let left_width: usize = relations.iter().map(|r| r.columns.len()).sum();
+        let right_width = relation.columns.len();
+        relations.push(relation);
+
+        let mut rows = Vec::new();
+        let mut right_matched = vec![false; right.len()];
+        for left_row in &left {
+            let mut matched = false;
+            for (idx, right_row) in right.iter().enumerate() {
+                let row: Vec<Scalar> = left_row.iter().chain(right_row).cloned().collect();
+                let keep = match condition {
+                    Some(condition) => {
+                        let scope = Scope {
+                            relations,
+                            row: &row,
+                            parent: outer,
+                        };
+                        self.eval(condition, &scope)? == Scalar::Bool(true)
+                    }
+                    None => true,
+                };
+                if keep {
+                    matched = true;
+                    right_matched[idx] = true;
+                    rows.push(row);
+                }
+            }
+            if keep_left && !matched {
+                let nulls = iter::repeat_n(Scalar::Null, right_width);
+                rows.push(left_row.iter().cloned().chain(nulls).collect());
+            }
+        }
+        if keep_right {
+            for (right_row, _) in right.iter().zip(right_matched).filter(|(_, m)| !m) {
+                let nulls = iter::repeat_n(Scalar::Null, left_width);
+                rows.push(nulls.chain(right_row.iter().cloned()).collect());
+            }
+        }
+        Ok(rows)
+    }
+
+    fn table_factor(
+        &self,
+        factor: &TableFactor,
+        outer: Option<&Scope>,
+    ) -> Result<(Relation, Vec<Vec<Scalar>>), SqlError> {
+        let (default_name, mut output, alias) = match factor {
+            TableFactor::Table {
+                name, alias, args, ..
+            } => {
+                let names: Vec<String> = name
+                    .0
+                    .iter()
+                    .map(|ident| ident.value.to_lowercase())
+                    .collect();
+                let default_name = names.last().cloned().unwrap_or_default();
+                let output = match args {
+                    Some(args) => {
+                        let args = self.function_args(&args.args, &Scope::outer(outer))?;
+                        self.table_function(&default_name, args, alias.as_ref())?
+                    }
+                    None => {
+                        let table = self.catalog.table(&names)?.ok_or_else(|| {
+                            SqlError::UnsupportedSqlConstruct(format!(
+                                "{} can not be queried together with catalog tables",
+                                name
+                            ))
+                        })?;
+                        let rows = table
+                            .rows
+                            .iter()
+                            .map(|row| {
+                                row.iter()
+                                    .zip(&table.columns)
+                                    .map(|(value, column)| {
+                                        Scalar::from_text(value, &column.data_type)
+                                    })
+                                    .collect()
+                            })
+                            .collect();
+                        Output {
+                            columns: table.columns,
+                            rows,
+                        }
+                    }
+                };
+                (default_name, output, alias)
+            }
+            TableFactor::Derived {
+                subquery, alias, ..
+            } => (String::new(), self.query(subquery, outer)?, alias),
+            TableFactor::UNNEST {
+                alias, array_exprs, ..
+            } => {
+                let scope = Scope::outer(outer);
+                let args = array_exprs
+                    .iter()
+                    .map(|expr| self.eval(expr, &scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let output = self.table_function("unnest", args, alias.as_ref())?;
+                ("unnest".to_string(), output, alias)
+            }
+            _ => return Err(unsupported(&format!("relation {}", factor))),
+        };
+
+        if let Some(alias) = alias {
+            for (column, name) in output.columns.iter_mut().zip(&alias.columns) {
+                column.name = name.value.clone();
+            }
+        }
+        let relation = Relation {
+            name: alias
+                .as_ref()
+                .map(|alias| alias.name.value.clone())
+                .unwrap_or(default_name),
+            columns: output.columns,
+        };
+        Ok((relation, output.rows))
+    }
+
+    /// Set returning functions usable in `FROM`.
+    fn table_function(
+        &self,
+        name: &str,
+        args: Vec<Scalar>,
+        alias: Option<&TableAlias>,
+    ) -> Result<Output, SqlError> {
+        let mut output = match (name, &args[..]) {
+            ("unnest", [Scalar::Array(elements)]) => Output {
+                columns: vec![VirtualColumn::new(name, infer_type(elements.iter()))],
+                rows: elements.iter().map(|e| vec![e.clone()]).collect(),
+            },
+            ("unnest", [Scalar::Null]) => Output {
+                columns: vec![VirtualColumn::new(name, Type::TEXT)],
+                rows: vec![],
+            },
+            ("generate_series", [start, stop]) => {
+                generate_series(name, start, stop, &Scalar::Number(1.0))?
+            }
+            ("generate_series", [start, stop, step]) => generate_series(name, start, stop, step)?,
+            _ => return Err(unsupported(&format!("table function {}", name))),
+        };
+
+        // A function returning a single column is named after its alias
+        if let (Some(alias), [column]) = (alias, &mut output.columns[..]) {
+            column.name = alias.name.value.clone();
+        }
+        Ok(output)
+    }
+
+    /// Resolves an `ORDER BY` item, which may reference an output column by
+    /// position or name, or be an expression over the source row.
+    fn order_key(
+        &self,
+        expr: &Expr,
+        scope: &Scope,
+        projection: &[(String, Expr)],
+        values: &[Scalar],
+    ) -> Result<Scalar, SqlError> {
+        match expr {
+            Expr::Value(Value::Number(n, _)) => n
+                .parse::<usize>()
+                .ok()
+                .filter(|idx| (1..=values.len()).contains(idx))
+                .map(|idx| values[idx - 1].clone())
+                .ok_or_else(|| {
+                    SqlError::InvalidFunctionArgument(format!(
+                        "ORDER BY position {} is not in select list",
+                        n
+                    ))
+                }),
+            Expr::Identifier(ident) => {
+                match projection.iter().position(|(name, _)| *name == ident.value) {
+                    Some(idx) => Ok(values[idx].clone()),
+                    None => self.eval(expr, scope),
+                }
+            }
+            _ => self.eval(expr, scope),
+        }
+    }
+
+    fn eval(&self, expr: &Expr, scope: &Scope) -> Result<Scalar, SqlError> {
+        match expr {
+            Expr::Identifier(ident) => match scope.column(std::slice::from_ref(ident)) {
+                // Postgres allows calling current_schema without parentheses
+                Err(SqlError::SqlColumnNotFoundError(..))
+                    if ident.quote_style.is_none()
+                        && ident.value.eq_ignore_ascii_case("current_schema") =>
+                {
+                    self.call(&ident.value.to_lowercase(), &[])
+                }
+                result => result,
+            },
+            Expr::CompoundIdentifier(idents) => scope.column(idents),
+            Expr::Value(value) => literal(value),
+            Expr::TypedString { data_type, value } => {
+                self.cast(Scalar::Text(value.clone()), data_type)
+            }
+            Expr::Nested(expr) | Expr::Collate { expr, .. } => self.eval(expr, scope),
+            Expr::Cast {
+                expr, data_type, ..
+            } => self.cast(self.eval(expr, scope)?, data_type),
+            Expr::IsNull(expr) => Ok(Scalar::Bool(self.eval(expr, scope)? == Scalar::Null)),
+            Expr::IsNotNull(expr) => Ok(Scalar::Bool(self.eval(expr, scope)? != Scalar::Null)),
+            Expr::IsTrue(expr) => Ok(Scalar::Bool(self.eval(expr, scope)?.truth()? == Some(true))),
+            Expr::IsNotTrue(expr) => {
+                Ok(Scalar::Bool(self.eval(expr, scope)?.truth()? != Some(true)))
+            }
+            Expr::IsFalse(expr) => Ok(Scalar::Bool(
+                self.eval(expr, scope)?.truth()? == Some(false),
+            )),
+            Expr::IsNotFalse(expr) => Ok(Scalar::Bool(
+                self.eval(expr, scope)?.truth()? != Some(false),
+            )),
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr,
+            } => Ok(self.eval(expr, scope)?.truth()?.map(|b| !b).into()),
+            Expr::UnaryOp {
+                op: UnaryOperator::Plus,
+                expr,
+            } => self.eval(expr, scope),
+            Expr::UnaryOp {
+                op: UnaryOperator::Minus,
+                expr,
+            } => match self.eval(expr, scope)? {
+                Scalar::Number(n) => Ok(Scalar::Number(-n)),
+                Scalar::Null => Ok(Scalar::Null),
+                other => Err(SqlError::InvalidFunctionArgument(format!(
+                    "Cannot negate {:?}",
+                    other
+                ))),
+            },
+            Expr::BinaryOp { left, op, right } => {
+                let left = self.eval(left, scope)?;
+                let right = self.eval(right, scope)?;
+                binary_op(left, op, right)
+            }
+            Expr::AnyOp {
+                left,
+                compare_op,
+                right,
+            } => {
+                let left = self.eval(left, scope)?;
+                self.quantified(left, compare_op, self.eval(right, scope)?, false)
+            }
+            Expr::AllOp {
+                left,
+                compare_op,
+                right,
+            } => {
+                let left = self.eval(left, scope)?;
+                self.quantified(left, compare_op, self.eval(right, scope)?, true)
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = self.eval(expr, scope)?;
+                let list = list
+                    .iter()
+                    .map(|item| self.eval(item, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(in_list(&value, &list, *negated))
+            }
+            Expr::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => {
+                let value = self.eval(expr, scope)?;
+                Ok(in_list(
+                    &value,
+                    &self.subquery_values(subquery, scope)?,
+                    *negated,
+                ))
+            }
+            Expr::Exists { subquery, negated } => {
+                let rows = self.query(subquery, Some(scope))?.rows;
+                Ok(Scalar::Bool(rows.is_empty() == *negated))
+            }
+            Expr::Subquery(subquery) => {
+                let mut values = self.subquery_values(subquery, scope)?;
+                match values.len() {
+                    0 => Ok(Scalar::Null),
+                    1 => Ok(values.remove(0)),
+                    _ => Err(SqlError::InvalidFunctionArgument(
+                        "more than one row returned by a subquery used as an expression"
+                            .to_string(),
+                    )),
+                }
+            }
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let value = self.eval(expr, scope)?;
+                let low = value.compare(&self.eval(low, scope)?);
+                let high = value.compare(&self.eval(high, scope)?);
+                Ok(low
+                    .zip(high)
+                    .map(|(low, high)| (low.is_ge() && high.is_le()) != *negated)
+                    .into())
+            }
+            Expr::Like {
+                negated,
+                expr,
+                pattern,
+                ..
+            } => self.eval_like(expr, pattern, *negated, false, scope),
+            Expr::ILike {
+                negated,
+                expr,
+                pattern,
+                ..
+            } => self.eval_like(expr, pattern, *negated, true, scope),
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                let operand = match operand {
+                    Some(operand) => Some(self.eval(operand, scope)?),
+                    None => None,
+                };
+                for (condition, result) in conditions.iter().zip(results) {
+                    let condition = self.eval(condition, scope)?;
+                    let matched = match &operand {
+                        Some(operand) => operand.compare(&condition) == Some(Ordering::Equal),
+                        None => condition == Scalar::Bool(true),
+                    };
+                    if matched {
+                        return self.eval(result, scope);
+                    }
+                }
+                match else_result {
+                    Some(else_result) => self.eval(else_result, scope),
+                    None => Ok(Scalar::Null),
+                }
+            }
+            Expr::Array(array) => Ok(Scalar::Array(
+                array
+                    .elem
+                    .iter()
+                    .map(|elem| self.eval(elem, scope))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Function(func) => self.eval_function(func, scope),
+            _ => Err(unsupported(&format!("expression {}", expr))),
+        }
+    }
+
+    /// Values of the first column a subquery returns.
+    fn subquery_values(&self, subquery: &Query, scope: &Scope) -> Result<Vec<Scalar>, SqlError> {
+        let output = self.query(subquery, Some(scope))?;
+        if output.columns.len() != 1 {
+            return Err(SqlError::InvalidFunctionArgument(
+                "subquery must return only one column".to_string(),
+            ));
+        }
+        Ok(output
+            .rows
+            .into_iter()
+            .map(|mut row| row.remove(0))
+            .collect())
+    }
+
+    /// `ANY` and `ALL` comparisons against an array.
+    fn quantified(
+        &self,
+        left: Scalar,
+        op: &BinaryOperator,
+        right: Scalar,
+        all: bool,
+    ) -> Result<Scalar, SqlError> {
+        let elements = match right {
+            Scalar::Array(elements) => elements,
+            Scalar::Null => return Ok(Scalar::Null),
+            other => {
+                return Err(SqlError::InvalidFunctionArgument(format!(
+                    "op ANY/ALL (array) requires array on right side, not {:?}",
+                    other
+                )))
+            }
+        };
+
+        let mut result = Some(all);
+        for element in elements {
+            match binary_op(left.clone(), op, element)?.truth()? {
+                Some(matched) if matched != all => return Ok(Scalar::Bool(matched)),
+                None => result = None,
+                _ => {}
+            }
+        }
+        Ok(result.into())
+    }
+
+    fn eval_like(
+        &self,
+        expr: &Expr,
+        pattern: &Expr,
+        negated: bool,
+        case_insensitive: bool,
+        scope: &Scope,
+    ) -> Result<Scalar, SqlError> {
+        let (Some(value), Some(pattern)) = (
+            self.eval(expr, scope)?.as_text(),
+            self.eval(pattern, scope)?.as_text(),
+        ) else {
+            return Ok(Scalar::Null);
+        };
+
+        let matched = if case_insensitive {
+            like(&value.to_lowercase(), &pattern.to_lowercase())
+        } else {
+            like(&value, &pattern)
+        };
+        Ok(Scalar::Bool(matched != negated))
+    }
+
+    fn function_args(&self, args: &[FunctionArg], scope: &Scope) -> Result<Vec<Scalar>, SqlError> {
+        args.iter()
+            .map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => self.eval(expr, scope),
+                _ => Err(unsupported(&format!("function argument {}", arg))),
+            })
+            .collect()
+    }
+
+    fn eval_function(&self, func: &Function, scope: &Scope) -> Result<Scalar, SqlError> {
+        let name = func
+            .name
+            .0
+            .last()
+            .map(|ident| ident.value.to_lowercase())
+            .unwrap_or_default();
+
+        let args = match &func.args {
+            FunctionArguments::List(list) => self.function_args(&list.args, scope)?,
+            FunctionArguments::None => vec![],
+            FunctionArguments::Subquery(subquery) if name == "array" => {
+                return Ok(Scalar::Array(self.subquery_values(subquery, scope)?));
+            }
+            FunctionArguments::Subquery(_) => return Err(unsupported("subqueries")),
+        };
+
+        match (name.as_str(), &args[..]) {
+            ("lower", [value]) => Ok(value
+                .as_text()
+                .map(|v| Scalar::Text(v.to_lowercase()))
+                .unwrap_or(Scalar::Null)),
+            ("upper", [value]) => Ok(value
+                .as_text()
+                .map(|v| Scalar::Text(v.to_uppercase()))
+                .unwrap_or(Scalar::Null)),
+            ("length", [value]) => Ok(value
+                .as_text()
+                .map(|v| Scalar::Number(v.chars().count() as f64))
+                .unwrap_or(Scalar::Null)),
+            ("coalesce", values) => Ok(values
+                .iter()
+                .find(|value| **value != Scalar::Null)
+                .cloned()
+                .unwrap_or(Scalar::Null)),
+            ("nullif", [value, other]) => Ok(match value.compare(other) {
+                Some(Ordering::Equal) => Scalar::Null,
+                _ => value.clone(),
+            }),
+            ("array_to_string", [Scalar::Array(elements), separator]) => Ok(separator
+                .as_text()
+                .map(|separator| {
+                    let elements: Vec<String> =
+                        elements.iter().filter_map(Scalar::as_text).collect();
+                    Scalar::Text(elements.join(&separator))
+                })
+                .unwrap_or(Scalar::Null)),
+            ("array_to_string", [Scalar::Null, _]) => Ok(Scalar::Null),
+            _ => self.call(&name, &args),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Scalar]) -> Result<Scalar, SqlError> {
+        self.catalog
+            .call(name, args)
+            .unwrap_or_else(|| Err(unsupported(&format!("function {}", name))))
+    }
+
+    fn cast(&self, value: Scalar, data_type: &DataType) -> Result<Scalar, SqlError> {
+        if value == Scalar::Null {
+            return Ok(Scalar::Null);
+        }
+
+        let type_name = match data_type {
+            DataType::Custom(name, _) => name
+                .0
+                .last()
+                .map(|ident| ident.value.to_lowercase())
+                .unwrap_or_default(),
+            DataType::Array(_) => return Ok(value),
+            _ => {
+                let name = data_type.to_string().to_lowercase();
+                name.split('(').next().unwrap_or_default().to_string()
+            }
+        };
+
+        match (type_name.as_str(), value) {
+            // Object identifier types compare as oids, look names up in the catalog
+            ("regclass" | "regtype" | "regnamespace", Scalar::Text(name)) => {
+                match self.call(&format!("to_{}", type_name), &[Scalar::Text(name.clone())])? {
+                    Scalar::Null => Err(SqlError::InvalidFunctionArgument(format!(
+                        "{} \"{}\" does not exist",
+                        type_name, name
+                    ))),
+                    oid => Ok(oid),
+                }
+            }
+            (
+                "smallint" | "int" | "integer" | "bigint" | "int2" | "int4" | "int8" | "oid"
+                | "real" | "float4" | "float8" | "double precision" | "numeric" | "decimal",
+                value,
+            ) => match value {
+                Scalar::Number(n) => Ok(Scalar::Number(n)),
+                Scalar::Bool(b) => Ok(Scalar::Number(if b { 1.0 } else { 0.0 })),
+                other => {
+                    let text = other.as_text().unwrap_or_default();
+                    text.trim().parse().map(Scalar::Number).map_err(|_| {
+                        SqlError::InvalidFunctionArgument(format!(
+                            "invalid input syntax for type {}: \"{}\"",
+                            type_name, text
+                        ))
+                    })
+                }
+            },
+            ("boolean" | "bool", Scalar::Text(text)) => match text.trim().to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Ok(Scalar::Bool(true)),
+                "f" | "false" | "n" | "no" | "off" | "0" => Ok(Scalar::Bool(false)),
+                _ => Err(SqlError::InvalidFunctionArgument(format!(
+                    "invalid input syntax for type boolean: \"{}\"",
+                    text
+                ))),
+            },
+            (
+                "text" | "varchar" | "character varying" | "char" | "character" | "bpchar" | "name",
+                value,
+            ) => Ok(value.into_text().map(Scalar::Text).unwrap_or(Scalar::Null)),
+            (_, value) => Ok(value),
+        }
+    }
+}
+
+fn binary_op(left: Scalar, op: &BinaryOperator, right: Scalar) -> Result<Scalar, SqlError> {
+    let comparison =
+        |check: fn(Ordering) -> bool| -> Scalar { left.compare(&right).map(check).into() };
+    let regex = |case_insensitive: bool, negated: bool| -> Result<Scalar, SqlError> {
+        let (Some(value), Some(pattern)) = (left.as_text(), right.as_text()) else {
+            return Ok(Scalar::Null);
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| SqlError::InvalidFunctionArgument(e.to_string()))?;
+        Ok(Scalar::Bool(regex.is_match(&value) != negated))
+    };
+
+    match op {
+        BinaryOperator::Eq => Ok(comparison(Ordering::is_eq)),
+        BinaryOperator::NotEq => Ok(comparison(Ordering::is_ne)),
+        BinaryOperator::Lt => Ok(comparison(Ordering::is_lt)),
+        BinaryOperator::LtEq => Ok(comparison(Ordering::is_le)),
+        BinaryOperator::Gt => Ok(comparison(Ordering::is_gt)),
+        BinaryOperator::GtEq => Ok(comparison(Ordering::is_ge)),
+        BinaryOperator::And => Ok(match (left.truth()?, right.truth()?) {
+            (Some(false), _) | (_, Some(false)) => Scalar::Bool(false),
+            (Some(true), Some(true)) => Scalar::Bool(true),
+            _ => Scalar::Null,
+        }),
+        BinaryOperator::Or => Ok(match (left.truth()?, right.truth()?) {
+            (Some(true), _) | (_, Some(true)) => Scalar::Bool(true),
+            (Some(false), Some(false)) => Scalar::Bool(false),
+            _ => Scalar::Null,
+        }),
+        BinaryOperator::StringConcat => Ok(match (left, right) {
+            // NULL arrays concatenate as empty arrays
+            (Scalar::Array(mut left), Scalar::Array(right)) => {
+                left.extend(right);
+                Scalar::Array(left)
+            }
+            (Scalar::Array(array), Scalar::Null) | (Scalar::Null, Scalar::Array(array)) => {
+                Scalar::Array(array)
+            }
+            (left, right) => match (left.into_text(), right.into_text()) {
+                (Some(left), Some(right)) => Scalar::Text(left + &right),
+                _ => Scalar::Null,
+            },
+        }),
+        BinaryOperator::Plus
+        | BinaryOperator::Minus
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Modulo => match (left, right) {
+            (Scalar::Number(left), Scalar::Number(right)) => Ok(Scalar::Number(match op {
+                BinaryOperator::Plus => left + right,
+                BinaryOperator::Minus => left - right,
+                BinaryOperator::Multiply => left * right,
+                BinaryOperator::Divide if right == 0.0 => {
+                    return Err(SqlError::InvalidFunctionArgument(
+                        "division by zero".to_string(),
+                    ))
+                }
+                BinaryOperator::Divide => left / right,
+                _ => left % right,
+            })),
+            (Scalar::Null, _) | (_, Scalar::Null) => Ok(Scalar::Null),
+            (left, right) => Err(SqlError::InvalidFunctionArgument(format!(
+                "operator {} does not support {:?} and {:?}",
+                op, left, right
+            ))),
+        },
+        BinaryOperator::PGRegexMatch => regex(false, false),
+        BinaryOperator::PGRegexIMatch => regex(true, false),
+        BinaryOperator::PGRegexNotMatch => regex(false, true),
+        BinaryOperator::PGRegexNotIMatch => regex(true, true),
+        // Schema qualified operators, e.g. `OPERATOR(pg_catalog.~)`
+        BinaryOperator::PGCustomBinaryOperator(parts) => {
+            let op = match parts.last().map(String::as_str) {
+                Some("=") => BinaryOperator::Eq,
+                Some("<>") | Some("!=") => BinaryOperator::NotEq,
+                Some("<") => BinaryOperator::Lt,
+                Some("<=") => BinaryOperator::LtEq,
+                Some(">") => BinaryOperator::Gt,
+                Some(">=") => BinaryOperator::GtEq,
+                Some("~") => BinaryOperator::PGRegexMatch,
+                Some("~*") => BinaryOperator::PGRegexIMatch,
+                Some("!~") => BinaryOperator::PGRegexNotMatch,
+                Some("!~*") => BinaryOperator::PGRegexNotIMatch,
+                _ => return Err(unsupported(&format!("operator {}", op))),
+            };
+            binary_op(left, &op, right)
+        }
+        _ => Err(unsupported(&format!("operator {}", op))),
+    }
+}
+
+fn in_list(value: &Scalar, list: &[Scalar], negated: bool) -> Scalar {
+    let mut result = Some(false);
+    for item in list {
+        match value.compare(item) {
+            Some(Ordering::Equal) => {
+                result = Some(true);
+                break;
+            }
+            None => result = None,
+            _ => {}
+        }
+    }
+    result.map(|found| found != negated).into()
+}
+
+fn literal(value: &Value) -> Result<Scalar, SqlError> {
+    match value {
+        Value::Number(n, _) => n
+            .parse()
+            .map(Scalar::Number)
+            .map_err(|_| SqlError::InvalidFunctionArgument(format!("Invalid number: {}", n))),
+        Value::SingleQuotedString(s)
+        | Value::DoubleQuotedString(s)
+        | Value::EscapedStringLiteral(s)
+        | Value::NationalStringLiteral(s) => Ok(Scalar::Text(s.clone())),
+        Value::Boolean(b) => Ok(Scalar::Bool(*b)),
+        Value::Null | Value::Placeholder(_) => Ok(Scalar::Null),
+        _ => Err(unsupported(&format!("literal {}", value))),
+    }
+}
+
+/// Type reported for computed output columns, based on their first non NULL value.
+fn infer_type<'s>(mut values: impl Iterator<Item = &'s Scalar>) -> Type {
+    match values.find(|value| **value != Scalar::Null) {
+        Some(Scalar::Bool(_)) => Type::BOOL,
+        Some(Scalar::Number(n)) if n.fract() == 0.0 => Type::INT8,
+        Some(Scalar::Number(_)) => Type::FLOAT8,
+        Some(Scalar::Array(_)) => Type::TEXT_ARRAY,
+        _ => Type::TEXT,
+    }
+}
+
+/// Sorts rows by their `ORDER BY` keys.
+fn sort<T>(keyed: &mut [(Vec<Scalar>, T)], order_by: &[OrderByExpr]) {
+    keyed.sort_by(|(left, _), (right, _)| {
+        order_by
+            .iter()
+            .zip(left.iter().zip(right.iter()))
+            .map(|(order, (left, right))| {
+                let ordering = left.sort_cmp(right, order.nulls_first);
+                if order.asc == Some(false) {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Output column an `ORDER BY` item of a set operation refers to.
+fn output_position(expr: &Expr, columns: &[VirtualColumn]) -> Result<usize, SqlError> {
+    let position = match expr {
+        Expr::Value(Value::Number(n, _)) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|idx| (1..=columns.len()).contains(idx))
+            .map(|idx| idx - 1),
+        Expr::Identifier(ident) => columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(&ident.value)),
+        _ => None,
+    };
+    position.ok_or_else(|| unsupported(&format!("ORDER BY {} on set operations", expr)))
+}
+
+/// Expands the select list into output names and expressions.
+fn projection(
+    items: &[SelectItem],
+    relations: &[Relation],
+) -> Result<Vec<(String, Expr)>, SqlError> {
+    let columns = |relation: &Relation| -> Vec<(String, Expr)> {
+        relation
+            .columns
+            .iter()
+            .map(|column| {
+                (
+                    column.name.clone(),
+                    Expr::CompoundIdentifier(vec![
+                        Ident::new(relation.name.clone()),
+                        Ident::new(column.name.clone()),
+                    ]),
+                )
+            })
+            .collect()
+    };
+
+    let mut projection = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::Wildcard(_) => projection.extend(relations.iter().flat_map(columns)),
+            SelectItem::QualifiedWildcard(name, _) => {
+                let qualifier = name.0.last().map(|ident| ident.value.as_str());
+                let relation = relations
+                    .iter()
+                    .find(|relation| {
+                        qualifier.is_some_and(|q| q.eq_ignore_ascii_case(&relation.name))
+                    })
+                    .ok_or_else(|| {
+                        SqlError::SqlTransformationError(format!(
+                            "missing FROM-clause entry for table \"{}\"",
+                            name
+                        ))
+                    })?;
+                projection.extend(columns(relation));
+            }
+            SelectItem::UnnamedExpr(expr) => {
+                projection.push((output_name(expr), expr.clone()));
+            }
+            SelectItem::ExprWithAlias { expr, alias } => {
+                projection.push((alias.value.clone(), expr.clone()));
+            }
+        }
+    }
+    Ok(projection)
+}
+
+/// Name Postgres gives to an unaliased select item.
+fn output_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.clone(),
+        Expr::CompoundIdentifier(idents) => idents
+            .last()
+            .map(|ident| ident.value.clone())
+            .unwrap_or_default(),
+        Expr::Function(func) => func
+            .name
+            .0
+            .last()
+            .map(|ident| ident.value.to_lowercase())
+            .unwrap_or_default(),
+        Expr::Cast { expr, .. } | Expr::Nested(expr) | Expr::Collate { expr, .. } => {
+            output_name(expr)
+        }
+        Expr::Case { .. } => "case".to_string(),
+        Expr::Array(_) => "array".to_string(),
+        _ => "?column?".to_string(),
+    }
+}
+
+/// Matches `value` against a SQL `LIKE` pattern with `%` and `_` wildcards.
+fn like(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // matches[j] is true when value[..i] matches pattern[..j]
+    let mut matches = vec![false; pattern.len() + 1];
+    matches[0] = true;
+    for j in 1..=pattern.len() {
+        matches[j] = matches[j - 1] && pattern[j - 1] == '%';
+    }
+
+    for c in &value {
+        let mut next = vec![false; pattern.len() + 1];
+        for j in 1..=pattern.len() {
+            next[j] = match pattern[j - 1] {
+                '%' => next[j - 1] || matches[j],
+                '_' => matches[j - 1],
+                p => matches[j - 1] && p == *c,
+            };
+        }
+        matches = next;
+    }
+
+    matches[pattern.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{evaluate, like, Scalar, VirtualCatalog, VirtualColumn, VirtualTable};
+    use crate::sql_parser::SqlError;
+    use rstest::*;
+    use sqlparser::ast::Statement;
+    use sqlparser::dialect::PostgreSqlDialect;
+    use sqlparser::parser::Parser;
+    use tokio_postgres::types::Type;
+
+    struct TestCatalog;
+
+    impl VirtualCatalog for TestCatalog {
+        fn table(&self, name: &[String]) -> Result<Option<VirtualTable>, SqlError> {
+            let table = match name.last().map(String::as_str) {
+                Some("t") => VirtualTable {
+                    columns: vec![
+                        VirtualColumn::new("name", Type::TEXT),
+                        VirtualColumn::new("ordinal", Type::INT4),
+                    ],
+                    rows: vec![
+                        vec![Some("b".to_string()), Some("2".to_string())],
+                        vec![Some("a".to_string()), Some("1".to_string())],
+                        vec![Some("c".to_string()), None],
+                    ],
+                },
+                Some("u") => VirtualTable {
+                    columns: vec![
+                        VirtualColumn::new("ordinal", Type::INT4),
+                        VirtualColumn::new("label", Type::TEXT),
+                    ],
+                    rows: vec![
+                        vec![Some("1".to_string()), Some("one".to_string())],
+                        vec![Some("2".to_string()), Some("two".to_string())],
+                        vec![Some("3".to_string()), Some("three".to_string())],
+                    ],
+                },
+                _ => return Ok(None),
+            };
+            Ok(Some(table))
+        }
+
+        fn call(&self, name: &str, _args: &[Scalar]) -> Option<Result<Scalar, SqlError>> {
+            match name {
+                "version" => Some(Ok(Scalar::Text("test".to_string()))),
+                _ => None,
+            }
+        }
+    }
+
+    fn run(sql: &str) -> Vec<Vec<Option<String>>> {
+        let statement = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        let Statement::Query(query) = statement else {
+            panic!("Expected a query");
+        };
+        evaluate(&query, &TestCatalog).unwrap().rows
+    }
+
+    fn values(rows: &[&[Option<&str>]]) -> Vec<Vec<Option<String>>> {
+        rows.iter()
+            .map(|row| row.iter().map(|v| v.map(str::to_string)).collect())
+            .collect()
+    }
+
+    #[rstest]
+    #[case::filter("SELECT name FROM t WHERE ordinal = 2", values(&[&[Some("b")]]))]
+    #[case::filter_null("SELECT name FROM t WHERE ordinal IS NULL", values(&[&[Some("c")]]))]
+    #[case::order_by("SELECT name FROM t ORDER BY name DESC", values(&[&[Some("c")], &[Some("b")], &[Some("a")]]))]
+    #[case::order_by_nulls_last("SELECT ordinal FROM t ORDER BY 1", values(&[&[Some("1")], &[Some("2")], &[None]]))]
+    #[case::limit_offset("SELECT name FROM t ORDER BY name LIMIT 1 OFFSET 1", values(&[&[Some("b")]]))]
+    #[case::in_list("SELECT name FROM t WHERE name IN ('a', 'c') ORDER BY name", values(&[&[Some("a")], &[Some("c")]]))]
+    #[case::like("SELECT name FROM t WHERE name ILIKE 'B%'", values(&[&[Some("b")]]))]
+    #[case::alias("SELECT x.name AS n FROM t AS x WHERE x.ordinal > 1", values(&[&[Some("b")]]))]
+    #[case::no_from("SELECT version(), 1 + 2", values(&[&[Some("test"), Some("3")]]))]
+    #[case::inner_join(
+        "SELECT t.name, u.label FROM t JOIN u ON u.ordinal = t.ordinal ORDER BY 1",
+        values(&[&[Some("a"), Some("one")], &[Some("b"), Some("two")]])
+    )]
+    #[case::left_join(
+        "SELECT t.name, u.label FROM t LEFT JOIN u ON u.ordinal = t.ordinal ORDER BY t.name DESC",
+        values(&[&[Some("c"), None], &[Some("b"), Some("two")], &[Some("a"), Some("one")]])
+    )]
+    #[case::right_join(
+        "SELECT u.label FROM t RIGHT JOIN u ON u.ordinal = t.ordinal WHERE t.name IS NULL",
+        values(&[&[Some("three")]])
+    )]
+    #[case::cross_join("SELECT count FROM (SELECT 1 AS count) c, t WHERE t.ordinal = 1", values(&[&[Some("1")]]))]
+    #[case::correlated_subquery(
+        "SELECT name, (SELECT label FROM u WHERE u.ordinal = t.ordinal) FROM t ORDER BY name",
+        values(&[&[Some("a"), Some("one")], &[Some("b"), Some("two")], &[Some("c"), None]])
+    )]
+    #[case::exists(
+        "SELECT label FROM u WHERE NOT EXISTS (SELECT 1 FROM t WHERE t.ordinal = u.ordinal)",
+        values(&[&[Some("three")]])
+    )]
+    #[case::in_subquery(
+        "SELECT label FROM u WHERE ordinal IN (SELECT ordinal FROM t) ORDER BY ordinal DESC",
+        values(&[&[Some("two")], &[Some("one")]])
+    )]
+    #[case::union(
+        "SELECT name FROM t WHERE ordinal = 1 UNION SELECT label FROM u WHERE ordinal = 3 UNION SELECT 'a' ORDER BY 1",
+        values(&[&[Some("a")], &[Some("three")]])
+    )]
+    #[case::case_operand(
+        "SELECT CASE ordinal WHEN 1 THEN 'first' WHEN 2 THEN 'second' END FROM t ORDER BY name",
+        values(&[&[Some("first")], &[Some("second")], &[None]])
+    )]
+    #[case::regex("SELECT label FROM u WHERE label !~ '^t' AND label OPERATOR(pg_catalog.~) 'e$'", values(&[&[Some("one")]]))]
+    #[case::cast_and_collate(
+        "SELECT label FROM u WHERE ordinal = '2'::int AND label = 'two' COLLATE pg_catalog.default",
+        values(&[&[Some("two")]])
+    )]
+    #[case::any_array("SELECT label FROM u WHERE ordinal = ANY(ARRAY[1, 3]) ORDER BY 1", values(&[&[Some("one")], &[Some("three")]]))]
+    #[case::array_subquery(
+        "SELECT array_to_string(array(SELECT label FROM u ORDER BY 1), ', ')",
+        values(&[&[Some("one, three, two")]])
+    )]
+    #[case::unnest("SELECT x FROM unnest(ARRAY['a', 'b']) x", values(&[&[Some("a")], &[Some("b")]]))]
+    #[case::generate_series("SELECT n FROM generate_series(1, 3) n", values(&[&[Some("1")], &[Some("2")], &[Some("3")]]))]
+    #[case::generate_series_step("SELECT n FROM generate_series(10, 1, -4) n", values(&[&[Some("10")], &[Some("6")], &[Some("2")]]))]
+    #[case::generate_series_empty("SELECT n FROM generate_series(3, 1) n", values(&[]))]
+    #[case::generate_series_null("SELECT n FROM generate_series(1, NULL) n", values(&[]))]
+    fn test_evaluate(#[case] sql: &str, #[case] expected: Vec<Vec<Option<String>>>) {
+        assert_eq!(expected, run(sql));
+    }
+
+    #[rstest]
+    #[case::ambiguous_column("SELECT ordinal FROM t, u")]
+    #[case::unknown_relation("SELECT * FROM dm_employees")]
+    #[case::unknown_function("SELECT pg_backend_pid()")]
+    #[case::group_by("SELECT name FROM t GROUP BY name")]
+    #[case::generate_series_too_long("SELECT * FROM generate_series(1, 9e18)")]
+    #[case::generate_series_fraction("SELECT * FROM generate_series(1, 2.5)")]
+    #[case::generate_series_nan("SELECT * FROM generate_series(1, 'NaN'::float)")]
+    #[case::generate_series_zero_step("SELECT * FROM generate_series(1, 5, 0)")]
+    #[case::cross_join_too_large(
+        "SELECT * FROM generate_series(1, 1000000) a, generate_series(1, 1000000) b"
+    )]
+    fn test_evaluate_errors(#[case] sql: &str) {
+        let statement = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        let Statement::Query(query) = statement else {
+            panic!("Expected a query");
+        };
+        assert!(evaluate(&query, &TestCatalog).is_err());
+    }
+
+    #[rstest]
+    #[case::series("SELECT * FROM generate_series(1, 1000001)")]
+    #[case::cross_join("SELECT * FROM generate_series(1, 1000) a, generate_series(1, 1001) b")]
+    #[case::join(
+        "SELECT * FROM generate_series(1, 1000) a JOIN generate_series(1, 1001) b ON a = b"
+    )]
+    fn test_row_limit(#[case] sql: &str) {
+        let statement = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        let Statement::Query(query) = statement else {
+            panic!("Expected a query");
+        };
+        let error = evaluate(&query, &TestCatalog).unwrap_err();
+        assert!(matches!(error, SqlError::TooManyRows(_)), "{}", error);
+        assert_eq!(error.code(), "54000");
+    }
+
+    #[rstest]
+    #[case("dm_employees", "dm_%", true)]
+    #[case("dm_employees", "dm_", false)]
+    #[case("dm_employees", "%employees", true)]
+    #[case("dm_employees", "dm_departments", false)]
+    fn test_like(#[case] value: &str, #[case] pattern: &str, #[case] expected: bool) {
+        assert_eq!(expected, like(value, pattern));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures::{Sink, SinkExt};
+use log::info;
+use pgwire::api::auth::StartupHandler;
+use pgwire::api::ClientInfo;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::response::ErrorResponse;
+use pgwire::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+use thiserror::Error;
+use tokio_rustls::rustls::pki_types::pem::{self, PemObject};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::auth::AuthError;
+
+/// ALPN protocol clients negotiate for direct SSL connections (Postgres 17+).
+const POSTGRES_ALPN: &[u8] = b"postgresql";
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Failed to read certificate from {0}: {1}")]
+    Certificate(String, pem::Error),
+    #[error("Failed to read private key from {0}: {1}")]
+    PrivateKey(String, pem::Error),
+    #[error("Invalid TLS configuration: {0}")]
+    Config(#[from] rustls::Error),
+}
+
+/// TLS acceptor loaded from a PEM certificate chain and private key, which
+/// can be reloaded while the server keeps running.
+///
+/// Connections pick up the acceptor current at the time they're accepted, so
+/// a reload doesn't affect connections that are already established.
+pub struct ReloadableTlsAcceptor {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    acceptor: RwLock<Arc<TlsAcceptor>>,
+}
+
+impl ReloadableTlsAcceptor {
+    pub fn load(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Result<Self, TlsError> {
+        let cert_path = cert_path.as_ref().to_path_buf();
+        let key_path = key_path.as_ref().to_path_buf();
+        let acceptor = build_acceptor(&cert_path, &key_path)?;
+        info!("Loaded TLS certificate from {}", cert_path.display());
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            acceptor: RwLock::new(Arc::new(acceptor)),
+        })
+    }
+
+    pub fn acceptor(&self) -> Arc<TlsAcceptor> {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    /// Reads the certificate and key again. The current acceptor is kept when
+    /// they can't be loaded.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let acceptor = build_acceptor(&self.cert_path, &self.key_path)?;
+        *self.acceptor.write().unwrap() = Arc::new(acceptor);
+        info!("Reloaded TLS certificate from {}", self.cert_path.display());
+        Ok(())
+    }
+}
+
+fn build_acceptor(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor, TlsError> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| TlsError::Certificate(cert_path.display().to_string(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::Certificate(
+            cert_path.display().to_string(),
+            pem::Error::NoItemsFound,
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| TlsError::PrivateKey(key_path.display().to_string(), e))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![POSTGRES_ALPN.to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Startup handler refusing clients that didn't negotiate TLS when it's
+/// required, and otherwise deferring to the wrapped handler.
+pub struct TlsStartupHandler<H> {
+    inner: H,
+    required: bool,
+}
+
+impl<H> TlsStartupHandler<H> {
+    pub fn new(inner: H, required: bool) -> Self {
+        Self { inner, required }
+    }
+}
+
+#[async_trait]
+impl<H: StartupHandler> StartupHandler for TlsStartupHandler<H> {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if self.required && !client.is_secure() {
+            if let PgWireFrontendMessage::Startup(_) = message {
+                let error_info = ErrorInfo::from(AuthError::SslRequired);
+                client
+                    .feed(PgWireBackendMessage::ErrorResponse(ErrorResponse::from(
+                        error_info,
+                    )))
+                    .await?;
+                client.close().await?;
+                return Ok(());
+            }
+        }
+        self.inner.on_startup(client, message).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    const CERT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/cert.pem");
+    const KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/key.pem");
+
+    #[test]
+    fn test_load() {
+        let tls = ReloadableTlsAcceptor::load(CERT_PATH, KEY_PATH).unwrap();
+        let config = tls.acceptor().config().clone();
+        assert_eq!(config.alpn_protocols, vec![POSTGRES_ALPN.to_vec()]);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let missing = ReloadableTlsAcceptor::load("/nonexistent/cert.pem", KEY_PATH);
+        assert!(matches!(missing, Err(TlsError::Certificate(..))));
+
+        // A key isn't a certificate
+        let no_certs = ReloadableTlsAcceptor::load(KEY_PATH, KEY_PATH);
+        assert!(matches!(no_certs, Err(TlsError::Certificate(..))));
+
+        let no_key = ReloadableTlsAcceptor::load(CERT_PATH, CERT_PATH);
+        assert!(matches!(no_key, Err(TlsError::PrivateKey(..))));
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("eqtble-sql-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        fs::copy(CERT_PATH, &cert_path).unwrap();
+        fs::copy(KEY_PATH, &key_path).unwrap();
+
+        let tls = ReloadableTlsAcceptor::load(&cert_path, &key_path).unwrap();
+        let initial = tls.acceptor();
+
+        tls.reload().unwrap();
+        assert!(!Arc::ptr_eq(&initial, &tls.acceptor()));
+
+        // A broken certificate keeps the current acceptor in place
+        let current = tls.acceptor();
+        fs::write(&cert_path, "not a certificate").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(Arc::ptr_eq(&current, &tls.acceptor()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! Runs the golden cases of `semantic-model-tests.sql` through [`SqlParser`]
+//! with the `semantic_models.json` store.
+//!
+//! Each case is an input query followed by `-- Expected:` and the SQL sent to
+//! the data store, or by `-- Expected error: <message>`. Cases are named by the
+//! `-- N. Title` headers above them, and `-- 1.`, `-- 2.` number the cases of a
+//! section. `-- Dialect: snowflake` runs the cases below it with that mapping,
+//! and `-- Ignore: <reason>` before an input records a case that does not pass
+//! yet. SQL is compared after parsing and printing it again, so formatting and
+//! keyword case do not matter.
+//!
+//! Failing expectations are regenerated with
+//!
+//! ```bash
+//! UPDATE_EXPECTED=1 cargo test --test semantic_model_tests
+//! ```
+use std::fs;
+
+use eqtble_sql::data_store::postgres::PostgresMapping;
+use eqtble_sql::data_store::snowflake::SnowflakeMapping;
+use eqtble_sql::data_store::DataStoreMapping;
+use eqtble_sql::semantic_model::local_store::LocalSemanticModelStore;
+use eqtble_sql::sql_parser::{SqlError, SqlParser};
+use sqlparser::dialect::Dialect;
+use sqlparser::parser::Parser;
+
+const TESTS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/semantic-model-tests.sql");
+const MODELS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/semantic_models.json");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DataStore {
+    Postgres,
+    Snowflake,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expected {
+    Sql(String),
+    Error(String),
+    Missing,
+}
+
+#[derive(Debug)]
+struct Case {
+    name: String,
+    data_store: DataStore,
+    /// Line number of the input, starting at 1.
+    line: usize,
+    input: String,
+    expected: Expected,
+    ignore: Option<String>,
+    /// Lines replaced when the expectation is updated.
+    expected_lines: (usize, usize),
+}
+
+#[derive(Default)]
+struct CaseBuilder {
+    input: Vec<String>,
+    input_start: usize,
+    input_end: usize,
+    marker: Option<usize>,
+    expected: Option<Vec<String>>,
+    expected_end: usize,
+    error: Option<String>,
+    ignore: Option<String>,
+}
+
+fn parse_cases(content: &str) -> Vec<Case> {
+    let mut cases = Vec::new();
+    let mut section = String::new();
+    let mut number: Option<String> = None;
+    let mut data_store = DataStore::Postgres;
+    let mut case = CaseBuilder::default();
+
+    let mut flush =
+        |case: &mut CaseBuilder, section: &str, number: &Option<String>, data_store: DataStore| {
+            let case = std::mem::take(case);
+            if case.input.iter().all(|line| line.trim().is_empty()) {
+                return;
+            }
+            let name = match number {
+                Some(number) => format!("{} ({})", section, number),
+                None => section.to_string(),
+            };
+            let expected = match (case.error, case.expected) {
+                (Some(error), _) => Expected::Error(error),
+                (None, Some(lines)) => Expected::Sql(lines.join("\n").trim().to_string()),
+                (None, None) => Expected::Missing,
+            };
+            let expected_lines = match case.marker {
+                Some(marker) => (marker, case.expected_end.max(marker + 1)),
+                None => (case.input_end, case.input_end),
+            };
+            cases.push(Case {
+                name,
+                data_store,
+                line: case.input_start + 1,
+                input: case.input.join("\n").trim().to_string(),
+                expected,
+                ignore: case.ignore,
+                expected_lines,
+            });
+        };
+
+    for (idx, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.len() >= 10 && trimmed.chars().all(|c| c == '-') {
+            flush(&mut case, &section, &number, data_store);
+        } else if let Some(name) = trimmed.strip_prefix("-- Dialect:") {
+            flush(&mut case, &section, &number, data_store);
+            data_store = match name.trim() {
+                "postgres" => DataStore::Postgres,
+                "snowflake" => DataStore::Snowflake,
+                name => panic!("line {}: unknown dialect {}", idx + 1, name),
+            };
+        } else if let Some(reason) = trimmed.strip_prefix("-- Ignore:") {
+            case.ignore = Some(reason.trim().to_string());
+        } else if let Some((n, title)) = section_header(trimmed) {
+            flush(&mut case, &section, &number, data_store);
+            if title.is_empty() {
+                number = Some(n.to_string());
+            } else {
+                section = format!("{}. {}", n, title);
+                number = None;
+            }
+        } else if trimmed == "-- Expected:" {
+            case.marker = Some(idx);
+            case.expected = Some(Vec::new());
+            case.expected_end = idx + 1;
+        } else if let Some(error) = trimmed.strip_prefix("-- Expected error:") {
+            case.marker = Some(idx);
+            case.error = Some(error.trim().to_string());
+            case.expected_end = idx + 1;
+        } else if trimmed.starts_with("--") || case.error.is_some() {
+            // Comments, and anything after an expected error
+        } else if let Some(expected) = &mut case.expected {
+            expected.push(line.to_string());
+            if !trimmed.is_empty() {
+                case.expected_end = idx + 1;
+            }
+        } else if !trimmed.is_empty() || !case.input.is_empty() {
+            if case.input.is_empty() {
+                case.input_start = idx;
+            }
+            case.input.push(line.to_string());
+            if !trimmed.is_empty() {
+                case.input_end = idx + 1;
+            }
+        }
+    }
+    flush(&mut case, &section, &number, data_store);
+    cases
+}
+
+/// Splits `-- 5. Title` into its number and title.
+fn section_header(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("-- ")?;
+    let (number, title) = rest.split_once('.')?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((number, title.trim()))
+}
+
+fn run(case: &Case, store: &LocalSemanticModelStore) -> Expected {
+    let result = match case.data_store {
+        DataStore::Postgres => transform(PostgresMapping {}, store.clone(), &case.input),
+        DataStore::Snowflake => transform(SnowflakeMapping {}, store.clone(), &case.input),
+    };
+    match result {
+        Ok(statements) => Expected::Sql(statements.join(";\n")),
+        Err(e) => Expected::Error(e.to_string()),
+    }
+}
+
+fn transform<M: DataStoreMapping>(
+    mapping: M,
+    store: LocalSemanticModelStore,
+    sql: &str,
+) -> Result<Vec<String>, SqlError> {
+    SqlParser::new(mapping, store).transform(sql)
+}
+
+/// Prints the SQL as sqlparser does, falling back to collapsing whitespace when
+/// it does not parse.
+fn normalize(expected: &Expected, data_store: DataStore) -> Expected {
+    let Expected::Sql(sql) = expected else {
+        return expected.clone();
+    };
+    let dialect: &dyn Dialect = match data_store {
+        DataStore::Postgres => PostgresMapping {}.get_dialect(),
+        DataStore::Snowflake => SnowflakeMapping {}.get_dialect(),
+    };
+    let sql = match Parser::parse_sql(dialect, sql) {
+        Ok(statements) => statements
+            .iter()
+            .map(|statement| statement.to_string())
+            .collect::<Vec<_>>()
+            .join(";\n"),
+        Err(_) => sql.split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+    Expected::Sql(sql)
+}
+
+fn describe(expected: &Expected) -> String {
+    match expected {
+        Expected::Sql(sql) => sql.clone(),
+        Expected::Error(error) => format!("error: {}", error),
+        Expected::Missing => "nothing".to_string(),
+    }
+}
+
+fn expected_lines(actual: &Expected) -> Vec<String> {
+    match actual {
+        Expected::Sql(sql) => std::iter::once("-- Expected:".to_string())
+            .chain(sql.lines().map(str::to_string))
+            .collect(),
+        Expected::Error(error) => vec![format!("-- Expected error: {}", error)],
+        Expected::Missing => vec![],
+    }
+}
+
+#[test]
+fn semantic_model_tests() {
+    let content = fs::read_to_string(TESTS_PATH).unwrap();
+    let store = LocalSemanticModelStore::load_from_json(MODELS_PATH).unwrap();
+    let update = std::env::var("UPDATE_EXPECTED").is_ok_and(|v| !v.is_empty() && v != "0");
+
+    let cases = parse_cases(&content);
+    assert!(!cases.is_empty(), "no cases found in {}", TESTS_PATH);
+
+    let mut failures = Vec::new();
+    let mut updates = Vec::new();
+    let mut ignored = 0;
+    for case in &cases {
+        let expected = normalize(&case.expected, case.data_store);
+        let actual = normalize(&run(case, &store), case.data_store);
+        let passed = expected == actual;
+        let location = format!("semantic-model-tests.sql:{} {}", case.line, case.name);
+        match &case.ignore {
+            Some(_) if passed => {
+                failures.push(format!("{} passes, remove its `-- Ignore:` line", location))
+            }
+            Some(_) => ignored += 1,
+            None if passed => {}
+            None if update => updates.push((case.expected_lines, expected_lines(&actual))),
+            None => failures.push(format!(
+                "{} ({:?})\n- {}\n+ {}",
+                location,
+                case.data_store,
+                describe(&expected).replace('\n', "\n- "),
+                describe(&actual).replace('\n', "\n+ "),
+            )),
+        }
+    }
+
+    if !updates.is_empty() {
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        for ((start, end), replacement) in updates.into_iter().rev() {
+            lines.splice(start..end, replacement);
+        }
+        fs::write(TESTS_PATH, lines.join("\n") + "\n").unwrap();
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} cases, {} failed, {} ignored\n\n{}\n",
+        cases.len(),
+        failures.len(),
+        ignored,
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn test_parse_cases() {
+    let content = "\
+-------
+-- 1. Simple
+-------
+SELECT 1;
+-- Expected:
+SELECT
+    1
+
+-- Dialect: snowflake
+-- 2. Numbered
+-- 1.
+-- Ignore: Not yet
+SELECT 2;
+-- Expected error: Boom
+-- 2.
+SELECT 3
+";
+    let cases = parse_cases(content);
+    assert_eq!(cases.len(), 3);
+
+    assert_eq!(cases[0].name, "1. Simple");
+    assert_eq!(cases[0].line, 4);
+    assert_eq!(cases[0].input, "SELECT 1;");
+    assert_eq!(
+        cases[0].expected,
+        Expected::Sql("SELECT\n    1".to_string())
+    );
+    assert_eq!(cases[0].data_store, DataStore::Postgres);
+    assert_eq!(cases[0].expected_lines, (4, 7));
+
+    assert_eq!(cases[1].name, "2. Numbered (1)");
+    assert_eq!(cases[1].expected, Expected::Error("Boom".to_string()));
+    assert_eq!(cases[1].ignore.as_deref(), Some("Not yet"));
+    assert_eq!(cases[1].data_store, DataStore::Snowflake);
+
+    assert_eq!(cases[2].name, "2. Numbered (2)");
+    assert_eq!(cases[2].expected, Expected::Missing);
+    assert_eq!(cases[2].expected_lines, (16, 16));
+}